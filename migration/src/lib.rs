@@ -7,6 +7,19 @@ mod m20220912_000002_create_group_table;
 mod m20220912_000003_create_group_member_table;
 mod m20220920_234028_create_transaction_table;
 mod m20220920_234539_create_debt_table;
+mod m20261019_000001_create_user_totp_table;
+mod m20261019_000002_create_user_totp_recovery_code_table;
+mod m20261019_000003_add_external_id_to_group_table;
+mod m20261019_000004_add_is_deleted_to_user_table;
+mod m20261019_000005_add_admin_columns_to_user_table;
+mod m20261019_000006_create_invite_code_table;
+mod m20261019_000007_create_direct_ledger_tables;
+mod m20261019_000008_make_debt_amounts_signed;
+mod m20261019_000009_make_timestamps_64_bit;
+mod m20261019_000010_create_group_webhook_tables;
+mod m20261019_000011_create_idempotency_key_table;
+mod m20261019_000012_add_request_hash_to_idempotency_key_table;
+mod m20261019_000013_create_friend_request_table;
 
 pub struct Migrator;
 
@@ -19,6 +32,19 @@ impl MigratorTrait for Migrator {
             Box::new(m20220912_000003_create_group_member_table::Migration),
             Box::new(m20220920_234028_create_transaction_table::Migration),
             Box::new(m20220920_234539_create_debt_table::Migration),
+            Box::new(m20261019_000001_create_user_totp_table::Migration),
+            Box::new(m20261019_000002_create_user_totp_recovery_code_table::Migration),
+            Box::new(m20261019_000003_add_external_id_to_group_table::Migration),
+            Box::new(m20261019_000004_add_is_deleted_to_user_table::Migration),
+            Box::new(m20261019_000005_add_admin_columns_to_user_table::Migration),
+            Box::new(m20261019_000006_create_invite_code_table::Migration),
+            Box::new(m20261019_000007_create_direct_ledger_tables::Migration),
+            Box::new(m20261019_000008_make_debt_amounts_signed::Migration),
+            Box::new(m20261019_000009_make_timestamps_64_bit::Migration),
+            Box::new(m20261019_000010_create_group_webhook_tables::Migration),
+            Box::new(m20261019_000011_create_idempotency_key_table::Migration),
+            Box::new(m20261019_000012_add_request_hash_to_idempotency_key_table::Migration),
+            Box::new(m20261019_000013_create_friend_request_table::Migration),
         ]
     }
 }
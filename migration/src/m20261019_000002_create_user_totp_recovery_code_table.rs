@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220912_000001_create_user_table::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserTotpRecoveryCode::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserTotpRecoveryCode::UserId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserTotpRecoveryCode::CodeHash)
+                            .string()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(UserTotpRecoveryCode::UserId)
+                            .col(UserTotpRecoveryCode::CodeHash),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(UserTotpRecoveryCode::Table, UserTotpRecoveryCode::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserTotpRecoveryCode::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum UserTotpRecoveryCode {
+    Table,
+    UserId,
+    CodeHash,
+}
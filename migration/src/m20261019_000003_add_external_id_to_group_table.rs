@@ -0,0 +1,69 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, DbBackend, Statement};
+
+use crate::m20220912_000002_create_group_table::Group;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Group::Table)
+                    .add_column(ColumnDef::new(GroupExternalId::ExternalId).string().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-group-external_id")
+                    .table(Group::Table)
+                    .col(GroupExternalId::ExternalId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-group-external_id")
+                    .table(Group::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        // sea-query refuses to drop columns on sqlite, although sqlite >= 3.35 supports it
+        if manager.get_database_backend() == DbBackend::Sqlite {
+            return manager
+                .get_connection()
+                .execute(Statement::from_string(
+                    DbBackend::Sqlite,
+                    r#"ALTER TABLE "group" DROP COLUMN "external_id""#.to_owned(),
+                ))
+                .await
+                .map(|_| ());
+        }
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Group::Table)
+                    .drop_column(GroupExternalId::ExternalId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum GroupExternalId {
+    ExternalId,
+}
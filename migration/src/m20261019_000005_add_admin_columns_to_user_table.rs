@@ -0,0 +1,59 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, DbBackend, Statement};
+
+use crate::m20220912_000001_create_user_table::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite only supports adding one column per statement
+        for column in [UserAdmin::IsAdmin, UserAdmin::IsDisabled] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(User::Table)
+                        .add_column(ColumnDef::new(column).boolean().not_null().default(false))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [UserAdmin::IsAdmin, UserAdmin::IsDisabled] {
+            // sea-query refuses to drop columns on sqlite, although sqlite >= 3.35 supports it
+            if manager.get_database_backend() == DbBackend::Sqlite {
+                manager
+                    .get_connection()
+                    .execute(Statement::from_string(
+                        DbBackend::Sqlite,
+                        format!(r#"ALTER TABLE "user" DROP COLUMN "{}""#, column.to_string()),
+                    ))
+                    .await?;
+                continue;
+            }
+
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(User::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum UserAdmin {
+    IsAdmin,
+    IsDisabled,
+}
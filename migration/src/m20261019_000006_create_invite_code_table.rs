@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220912_000001_create_user_table::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(InviteCode::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(InviteCode::Code)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(InviteCode::CreatedBy).string().not_null())
+                    .col(ColumnDef::new(InviteCode::UsedBy).string().null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(InviteCode::Table, InviteCode::CreatedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(InviteCode::Table, InviteCode::UsedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(InviteCode::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum InviteCode {
+    Table,
+    Code,
+    CreatedBy,
+    UsedBy,
+}
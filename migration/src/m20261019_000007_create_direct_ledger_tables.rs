@@ -0,0 +1,182 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220912_000001_create_user_table::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Friend::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Friend::UserId).string().not_null())
+                    .col(ColumnDef::new(Friend::FriendId).string().not_null())
+                    .primary_key(Index::create().col(Friend::UserId).col(Friend::FriendId))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Friend::Table, Friend::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Friend::Table, Friend::FriendId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(DirectTransaction::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(DirectTransaction::Id)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(DirectTransaction::FirstUserId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(DirectTransaction::SecondUserId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(DirectTransaction::CreditorId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(DirectTransaction::Timestamp)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(DirectTransaction::Description)
+                            .string()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(DirectTransaction::Table, DirectTransaction::FirstUserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Restrict),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(DirectTransaction::Table, DirectTransaction::SecondUserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Restrict),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(DirectTransaction::Table, DirectTransaction::CreditorId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Restrict),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-direct_transaction-users")
+                    .table(DirectTransaction::Table)
+                    .col(DirectTransaction::FirstUserId)
+                    .col(DirectTransaction::SecondUserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(DirectDebt::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(DirectDebt::TransactionId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(DirectDebt::DebtorId).string().not_null())
+                    .col(ColumnDef::new(DirectDebt::Amount).big_unsigned().not_null())
+                    .col(
+                        ColumnDef::new(DirectDebt::WasSplitUnequally)
+                            .boolean()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(DirectDebt::TransactionId)
+                            .col(DirectDebt::DebtorId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(DirectDebt::Table, DirectDebt::TransactionId)
+                            .to(DirectTransaction::Table, DirectTransaction::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(DirectDebt::Table, DirectDebt::DebtorId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Restrict),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DirectDebt::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(DirectTransaction::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Friend::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Friend {
+    Table,
+    UserId,
+    FriendId,
+}
+
+/// Transaction between exactly two users, stored with the smaller user id first
+#[derive(Iden)]
+enum DirectTransaction {
+    Table,
+    Id,
+    FirstUserId,
+    SecondUserId,
+    CreditorId,
+    Timestamp,
+    Description,
+}
+
+#[derive(Iden)]
+enum DirectDebt {
+    Table,
+    TransactionId,
+    DebtorId,
+    Amount,
+    WasSplitUnequally,
+}
@@ -0,0 +1,70 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::DbBackend;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Timestamps were created as 32-bit integers, which overflow in 2038. Postgres and MySQL
+/// convert the existing values when the column type is changed, sqlite integers have 64 bits
+/// already.
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() == DbBackend::Sqlite {
+            return Ok(());
+        }
+
+        for table in [
+            TransactionTable::Transaction,
+            TransactionTable::DirectTransaction,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table)
+                        .modify_column(
+                            ColumnDef::new(TransactionTable::Timestamp)
+                                .big_integer()
+                                .not_null(),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() == DbBackend::Sqlite {
+            return Ok(());
+        }
+
+        for table in [
+            TransactionTable::Transaction,
+            TransactionTable::DirectTransaction,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table)
+                        .modify_column(
+                            ColumnDef::new(TransactionTable::Timestamp)
+                                .integer()
+                                .not_null(),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum TransactionTable {
+    Transaction,
+    DirectTransaction,
+    Timestamp,
+}
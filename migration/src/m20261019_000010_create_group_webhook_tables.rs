@@ -0,0 +1,147 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220912_000002_create_group_table::Group;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(GroupWebhook::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(GroupWebhook::Id)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(GroupWebhook::GroupId).string().not_null())
+                    .col(ColumnDef::new(GroupWebhook::Url).string().not_null())
+                    .col(ColumnDef::new(GroupWebhook::Events).string().not_null())
+                    .col(ColumnDef::new(GroupWebhook::Secret).string().not_null())
+                    .col(
+                        ColumnDef::new(GroupWebhook::CreatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(GroupWebhook::Table, GroupWebhook::GroupId)
+                            .to(Group::Table, Group::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(GroupWebhookDelivery::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(GroupWebhookDelivery::Id)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupWebhookDelivery::WebhookId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupWebhookDelivery::Event)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupWebhookDelivery::Payload)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupWebhookDelivery::Status)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupWebhookDelivery::Attempts)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(GroupWebhookDelivery::ResponseStatus).integer())
+                    .col(ColumnDef::new(GroupWebhookDelivery::Error).string())
+                    .col(
+                        ColumnDef::new(GroupWebhookDelivery::CreatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupWebhookDelivery::UpdatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(GroupWebhookDelivery::Table, GroupWebhookDelivery::WebhookId)
+                            .to(GroupWebhook::Table, GroupWebhook::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-group_webhook_delivery-webhook_id")
+                    .table(GroupWebhookDelivery::Table)
+                    .col(GroupWebhookDelivery::WebhookId)
+                    .col(GroupWebhookDelivery::CreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(GroupWebhookDelivery::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(GroupWebhook::Table).to_owned())
+            .await
+    }
+}
+
+/// Subscription of a url to events of a group, `events` is a comma separated list
+#[derive(Iden)]
+enum GroupWebhook {
+    Table,
+    Id,
+    GroupId,
+    Url,
+    Events,
+    Secret,
+    CreatedAt,
+}
+
+/// One event sent to a webhook, including its retries
+#[derive(Iden)]
+enum GroupWebhookDelivery {
+    Table,
+    Id,
+    WebhookId,
+    Event,
+    Payload,
+    Status,
+    Attempts,
+    ResponseStatus,
+    Error,
+    CreatedAt,
+    UpdatedAt,
+}
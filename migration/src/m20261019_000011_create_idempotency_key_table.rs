@@ -0,0 +1,70 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220912_000001_create_user_table::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(IdempotencyKey::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(IdempotencyKey::UserId).string().not_null())
+                    .col(ColumnDef::new(IdempotencyKey::Key).string().not_null())
+                    .col(ColumnDef::new(IdempotencyKey::Request).string().not_null())
+                    .col(ColumnDef::new(IdempotencyKey::ResponseStatus).integer())
+                    .col(ColumnDef::new(IdempotencyKey::ResponseBody).text())
+                    .col(
+                        ColumnDef::new(IdempotencyKey::CreatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(IdempotencyKey::UserId)
+                            .col(IdempotencyKey::Key),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(IdempotencyKey::Table, IdempotencyKey::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-idempotency_key-created_at")
+                    .table(IdempotencyKey::Table)
+                    .col(IdempotencyKey::CreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(IdempotencyKey::Table).to_owned())
+            .await
+    }
+}
+
+/// Response of a mutating request, replayed when it is retried with the same key.
+/// The response is missing while the request is still being processed.
+#[derive(Iden)]
+enum IdempotencyKey {
+    Table,
+    UserId,
+    Key,
+    Request,
+    ResponseStatus,
+    ResponseBody,
+    CreatedAt,
+}
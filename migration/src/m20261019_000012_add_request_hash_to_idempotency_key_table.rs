@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, DbBackend, Statement};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(IdempotencyKey::Table)
+                    .add_column(
+                        ColumnDef::new(IdempotencyKey::RequestHash)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sea-query refuses to drop columns on sqlite, although sqlite >= 3.35 supports it
+        if manager.get_database_backend() == DbBackend::Sqlite {
+            manager
+                .get_connection()
+                .execute(Statement::from_string(
+                    DbBackend::Sqlite,
+                    r#"ALTER TABLE "idempotency_key" DROP COLUMN "request_hash""#.to_owned(),
+                ))
+                .await?;
+            return Ok(());
+        }
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(IdempotencyKey::Table)
+                    .drop_column(IdempotencyKey::RequestHash)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// The hash of the body binds a key to the exact request
+#[derive(Iden)]
+enum IdempotencyKey {
+    Table,
+    RequestHash,
+}
@@ -0,0 +1,53 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220912_000001_create_user_table::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(FriendRequest::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(FriendRequest::UserId).string().not_null())
+                    .col(ColumnDef::new(FriendRequest::FriendId).string().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(FriendRequest::UserId)
+                            .col(FriendRequest::FriendId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(FriendRequest::Table, FriendRequest::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(FriendRequest::Table, FriendRequest::FriendId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(FriendRequest::Table).to_owned())
+            .await
+    }
+}
+
+/// Sent by the user to the friend, who has to accept it before they become friends
+#[derive(Iden)]
+enum FriendRequest {
+    Table,
+    UserId,
+    FriendId,
+}
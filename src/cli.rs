@@ -0,0 +1,620 @@
+use crate::services::backup::{Backup, BackupService};
+use crate::services::configuration::ConfigurationService;
+use crate::services::group::GroupService;
+use crate::services::user::{User, UserService};
+use crate::services::webhook::WebhookService;
+use clap::{Parser, Subcommand};
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{ConnectionTrait, DatabaseConnection};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Parser, Debug)]
+#[clap(version, about = "Keeps track of who paid for what and who owes whom")]
+pub struct Cli {
+    /// TOML config file, defaults to MONEYBALANCER_CONFIG_PATH. Environment variables take
+    /// precedence over its values.
+    #[clap(short, long, global = true)]
+    pub config: Option<PathBuf>,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Runs the server (default)
+    Serve,
+    /// Writes a consistent dump of the database as JSON
+    Backup {
+        /// File to write the backup to, stdout if omitted. Only its owner can read it.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// Overwrite the output file if it exists
+        #[clap(long)]
+        force: bool,
+    },
+    /// Restores a backup into an empty database
+    Restore {
+        /// Backup created by the `backup` command
+        input: PathBuf,
+    },
+    /// Creates a local user
+    CreateUser {
+        username: String,
+        /// Defaults to the username
+        #[clap(short, long)]
+        nickname: Option<String>,
+        /// Read from stdin if omitted, typed passwords are not echoed
+        #[clap(short, long)]
+        password: Option<String>,
+    },
+    /// Sets the password of a user
+    SetPassword {
+        username: String,
+        /// Read from stdin if omitted, typed passwords are not echoed
+        #[clap(short, long)]
+        password: Option<String>,
+    },
+    /// Makes a user an instance administrator
+    MakeAdmin {
+        username: String,
+        /// Takes the administrator rights away instead
+        #[clap(long)]
+        revoke: bool,
+    },
+    /// Lists all groups with their members
+    ListGroups,
+    /// Adds a user to a group
+    AddMember {
+        group_id: String,
+        username: String,
+        #[clap(long)]
+        owner: bool,
+    },
+    /// Manages the database schema
+    Migrate {
+        #[clap(subcommand)]
+        command: MigrateCommand,
+    },
+    /// Validates the configuration and checks the connection to the database
+    CheckConfig,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MigrateCommand {
+    /// Applies pending migrations
+    Up {
+        /// Number of migrations to apply, all if omitted
+        #[clap(short = 'n', long)]
+        steps: Option<u32>,
+    },
+    /// Reverts applied migrations
+    Down {
+        /// Number of migrations to revert
+        #[clap(short = 'n', long, default_value = "1")]
+        steps: u32,
+    },
+    /// Lists all migrations and whether they were applied
+    Status,
+}
+
+/// Runs an administrative command against the configured database
+pub async fn run(
+    command: Command,
+    db: DatabaseConnection,
+    configuration_service: Arc<ConfigurationService>,
+) -> Result<(), String> {
+    let db = Arc::new(db);
+    let user_service = UserService::new(db.clone(), configuration_service.clone());
+    let webhook_service = Arc::new(WebhookService::new(
+        db.clone(),
+        configuration_service.clone(),
+    ));
+    let group_service = GroupService::new(db.clone(), webhook_service);
+    let backup_service = BackupService::new(db.clone());
+
+    let needs_schema = !matches!(
+        command,
+        Command::Backup { .. }
+            | Command::Restore { .. }
+            | Command::Migrate { .. }
+            | Command::CheckConfig
+    );
+    if needs_schema
+        && !Migrator::get_pending_migrations(db.as_ref())
+            .await
+            .map_err(|e| e.to_string())?
+            .is_empty()
+    {
+        return Err("the database has pending migrations, apply them with `migrate up`".to_owned());
+    }
+
+    match command {
+        Command::Serve => Err("the server cannot be started as a command".to_owned()),
+        Command::Backup { output, force } => backup(&backup_service, output, force).await,
+        Command::Restore { input } => restore(&backup_service, input).await,
+        Command::CreateUser {
+            username,
+            nickname,
+            password,
+        } => {
+            let password = password_or_stdin(password)?;
+            let nickname = nickname.unwrap_or_else(|| username.to_owned());
+            let user = user_service
+                .create_user(username, nickname, password, false, None)
+                .await
+                .map_err(|e| e.to_string())?;
+            println!("Created user {} with id {}", user.username, user.id);
+            Ok(())
+        }
+        Command::SetPassword { username, password } => {
+            let user = find_user(&user_service, &username).await?;
+            let password = password_or_stdin(password)?;
+            user_service
+                .set_password(&user.id, &password)
+                .await
+                .map_err(|e| e.to_string())?;
+            println!("Changed the password of {}", user.username);
+            Ok(())
+        }
+        Command::MakeAdmin { username, revoke } => {
+            let user = find_user(&user_service, &username).await?;
+            user_service
+                .set_user_admin(&user.id, !revoke)
+                .await
+                .map_err(|e| e.to_string())?;
+            match revoke {
+                false => println!("{} is an administrator now", user.username),
+                true => println!("{} is no administrator anymore", user.username),
+            }
+            Ok(())
+        }
+        Command::ListGroups => {
+            let groups = group_service
+                .get_all_groups()
+                .await
+                .map_err(|e| e.to_string())?;
+            for group in groups {
+                let members = group
+                    .members
+                    .iter()
+                    .map(|member| member.nickname.to_owned())
+                    .collect::<Vec<String>>();
+                println!("{}\t{}\t{}", group.id, group.name, members.join(", "));
+            }
+            Ok(())
+        }
+        Command::AddMember {
+            group_id,
+            username,
+            owner,
+        } => {
+            let user = find_user(&user_service, &username).await?;
+            group_service
+                .create_group_member(group_id.to_owned(), user.id, owner)
+                .await
+                .map_err(|e| e.to_string())?;
+            println!("Added {} to the group {}", user.username, group_id);
+            Ok(())
+        }
+        Command::Migrate { command } => migrate(db.as_ref(), command).await,
+        Command::CheckConfig => check_config(&configuration_service, db.as_ref()).await,
+    }
+}
+
+async fn find_user(user_service: &UserService, username: &str) -> Result<User, String> {
+    user_service
+        .get_user_by_username(username)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or(format!("user {} not found", username))
+}
+
+/// Passwords should not end up in the shell history, so they can be piped in as well
+fn password_or_stdin(password: Option<String>) -> Result<String, String> {
+    if let Some(password) = password {
+        return Ok(password);
+    }
+
+    // typed passwords are not echoed, piped ones are read as they are
+    if io::stdin().is_terminal() {
+        return rpassword::prompt_password("Password: ").map_err(|e| e.to_string());
+    }
+
+    eprintln!("Password:");
+    let mut password = String::new();
+    io::stdin()
+        .read_line(&mut password)
+        .map_err(|e| e.to_string())?;
+
+    Ok(password.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
+
+async fn migrate(db: &DatabaseConnection, command: MigrateCommand) -> Result<(), String> {
+    match command {
+        MigrateCommand::Up { steps } => Migrator::up(db, steps).await,
+        MigrateCommand::Down { steps } => Migrator::down(db, Some(steps)).await,
+        MigrateCommand::Status => Ok(()),
+    }
+    .map_err(|e| e.to_string())?;
+
+    let applied = Migrator::get_migration_models(db)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect::<HashSet<String>>();
+
+    for migration in Migrator::migrations() {
+        let status = match applied.contains(migration.name()) {
+            true => "applied",
+            false => "pending",
+        };
+        println!("{:<8} {}", status, migration.name());
+    }
+
+    Ok(())
+}
+
+async fn check_config(
+    configuration_service: &ConfigurationService,
+    db: &DatabaseConnection,
+) -> Result<(), String> {
+    let pending = Migrator::get_pending_migrations(db)
+        .await
+        .map_err(|e| e.to_string())?
+        .len();
+    let enabled = |enabled: bool| match enabled {
+        true => "enabled",
+        false => "disabled",
+    };
+    let admins = configuration_service.admin_usernames();
+
+    println!(
+        "Database: {:?}, {} pending migrations",
+        db.get_database_backend(),
+        pending
+    );
+    println!(
+        "Local authentication: {}",
+        enabled(configuration_service.auth_local().is_some())
+    );
+    println!(
+        "Proxy authentication: {}",
+        enabled(configuration_service.auth_proxy().is_some())
+    );
+    println!(
+        "Metrics: {}",
+        enabled(configuration_service.metrics().is_some())
+    );
+    println!(
+        "Webhooks: {}",
+        enabled(configuration_service.webhooks().is_some())
+    );
+    println!(
+        "Registration policy: {:?}",
+        configuration_service.registration().policy
+    );
+    match admins.is_empty() {
+        true => println!("Administrators: none"),
+        false => println!("Administrators: {}", admins.join(", ")),
+    }
+    println!("The configuration is valid");
+
+    Ok(())
+}
+
+async fn backup(
+    backup_service: &BackupService,
+    output: Option<PathBuf>,
+    force: bool,
+) -> Result<(), String> {
+    let backup = backup_service
+        .create_backup()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let writer: Box<dyn Write> = match &output {
+        Some(path) => Box::new(create_backup_file(path, force).map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => format!(
+                "{} already exists, pass --force to overwrite it",
+                path.display()
+            ),
+            _ => e.to_string(),
+        })?),
+        None => Box::new(io::stdout()),
+    };
+    let mut writer = BufWriter::new(writer);
+
+    rocket::serde::json::serde_json::to_writer_pretty(&mut writer, &backup)
+        .map_err(|e| e.to_string())?;
+    writer.flush().map_err(|e| e.to_string())?;
+
+    if let Some(path) = output {
+        eprintln!(
+            "Backed up {} users and {} groups to {}",
+            backup.users.len(),
+            backup.groups.len(),
+            path.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// The backup contains password hashes and secrets, so only the owner may read the file
+fn create_backup_file(path: &Path, overwrite: bool) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    match overwrite {
+        true => options.create(true).truncate(true),
+        false => options.create_new(true),
+    };
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let file = options.open(path)?;
+
+    // the mode only applies to new files
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+
+    Ok(file)
+}
+
+async fn restore(backup_service: &BackupService, input: PathBuf) -> Result<(), String> {
+    let file = File::open(&input).map_err(|e| e.to_string())?;
+    let backup: Backup = rocket::serde::json::serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("invalid backup: {}", e))?;
+
+    let (users, groups) = (backup.users.len(), backup.groups.len());
+    backup_service
+        .restore_backup(backup)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    eprintln!(
+        "Restored {} users and {} groups from {}",
+        users,
+        groups,
+        input.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run, Cli};
+    use crate::routes::user::tests::{create_token, create_user};
+    use crate::services::backup::{BackupError, BackupService};
+    use crate::services::configuration::ConfigurationService;
+    use crate::{build_rocket, build_test_rocket, set_up_db};
+    use clap::Parser;
+    use futures::executor::block_on;
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::{json, Value};
+    use serial_test::serial;
+    use std::env;
+    use std::sync::Arc;
+
+    #[test]
+    #[serial]
+    fn test_backup_and_restore() {
+        env::set_var("MONEYBALANCER_WEBHOOKS_ENABLED", "true");
+        env::set_var("MONEYBALANCER_WEBHOOKS_ALLOWED_NETWORKS", "127.0.0.1");
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+        env::remove_var("MONEYBALANCER_WEBHOOKS_ENABLED");
+        env::remove_var("MONEYBALANCER_WEBHOOKS_ALLOWED_NETWORKS");
+
+        let alice = create_user(&client, "alice")
+            .expect("user to be created")
+            .id;
+        let bob = create_user(&client, "bob").expect("user to be created").id;
+        let authorization = Header::new(
+            "Authorization",
+            format!(
+                "Bearer {}",
+                create_token(&client, "alice", "alice").unwrap()
+            ),
+        );
+
+        let group = client
+            .post("/api/v1/group")
+            .header(authorization.clone())
+            .body(json!({ "name": "Trip" }).to_string())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        let group_id = group["id"].as_str().unwrap();
+
+        let response = client
+            .post(format!("/api/v1/group/{}/transaction", group_id))
+            .header(authorization.clone())
+            .body(
+                json!({ "debtor_ids": [alice], "amount": 10, "description": "Bread" }).to_string(),
+            )
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .post(format!("/api/v1/friend/{}", bob))
+            .header(authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Accepted);
+
+        // not triggered by anything in this test, so no deliveries change during the backup
+        let response = client
+            .post(format!("/api/v1/group/{}/webhook", group_id))
+            .header(authorization.clone())
+            .body(
+                json!({ "url": "http://127.0.0.1:9/hook", "events": ["member.removed"], "secret": "secret" })
+                    .to_string(),
+            )
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let configuration_service = Arc::new(ConfigurationService::load(None).unwrap());
+        let source = BackupService::new(Arc::new(
+            block_on(set_up_db(configuration_service.database())).unwrap(),
+        ));
+        let backup = block_on(source.create_backup()).unwrap();
+        assert_eq!(backup.users.len(), 2);
+        assert_eq!(backup.debts.len(), 1);
+        assert_eq!(backup.friend_requests.len(), 1);
+        assert_eq!(backup.group_webhooks.len(), 1);
+        assert_eq!(backup.group_webhooks[0].secret, "secret");
+
+        let mut restore_config = configuration_service.database().clone();
+        let _ = std::fs::remove_file("./.money-balancer-restore-test-tmp.sqlite");
+        restore_config.url = "sqlite:./.money-balancer-restore-test-tmp.sqlite?mode=rwc".to_owned();
+        let target = BackupService::new(Arc::new(block_on(set_up_db(&restore_config)).unwrap()));
+
+        let mut outdated = block_on(source.create_backup()).unwrap();
+        outdated.migration = "m20220912_000001_create_user_table".to_owned();
+        assert!(matches!(
+            block_on(target.restore_backup(outdated)),
+            Err(BackupError::MigrationMismatch { .. })
+        ));
+
+        block_on(target.restore_backup(block_on(source.create_backup()).unwrap())).unwrap();
+        assert!(matches!(
+            block_on(target.restore_backup(block_on(source.create_backup()).unwrap())),
+            Err(BackupError::DatabaseNotEmpty)
+        ));
+
+        let mut restored = block_on(target.create_backup()).unwrap();
+        restored.created_at = backup.created_at;
+        assert_eq!(restored, backup);
+
+        // the restored instance is usable as is
+        let restored_db = block_on(set_up_db(&restore_config)).unwrap();
+        let client = Client::tracked(build_rocket(restored_db, configuration_service))
+            .expect("valid rocket instance");
+        let authorization = Header::new(
+            "Authorization",
+            format!(
+                "Bearer {}",
+                create_token(&client, "alice", "alice").unwrap()
+            ),
+        );
+        let response = client
+            .get(format!("/api/v1/group/{}/transaction", group_id))
+            .header(authorization)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_json::<Vec<Value>>().unwrap().len(), 1);
+    }
+
+    fn run_command(args: &[&str]) -> Result<(), String> {
+        let cli = Cli::try_parse_from([&["money-balancer"], args].concat()).unwrap();
+        let configuration_service = Arc::new(ConfigurationService::load(None).unwrap());
+        let db = block_on(set_up_db(configuration_service.database())).unwrap();
+
+        block_on(run(cli.command.unwrap(), db, configuration_service))
+    }
+
+    #[test]
+    #[serial]
+    fn test_admin_commands() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        create_user(&client, "alice").expect("user to be created");
+        let authorization = Header::new(
+            "Authorization",
+            format!(
+                "Bearer {}",
+                create_token(&client, "alice", "alice").unwrap()
+            ),
+        );
+        let group = client
+            .post("/api/v1/group")
+            .header(authorization.clone())
+            .body(json!({ "name": "Trip" }).to_string())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        let group_id = group["id"].as_str().unwrap();
+
+        run_command(&["create-user", "carol", "--password", "secret"]).unwrap();
+        assert_eq!(
+            run_command(&["create-user", "carol", "--password", "secret"]),
+            Err("username_taken".to_owned())
+        );
+        assert!(create_token(&client, "carol", "secret").is_ok());
+
+        run_command(&["set-password", "carol", "--password", "changed"]).unwrap();
+        assert!(create_token(&client, "carol", "secret").is_err());
+        let carol_authorization = Header::new(
+            "Authorization",
+            format!(
+                "Bearer {}",
+                create_token(&client, "carol", "changed").unwrap()
+            ),
+        );
+
+        let is_admin = || {
+            client
+                .get("/api/v1/admin/user")
+                .header(carol_authorization.clone())
+                .dispatch()
+                .status()
+                == Status::Ok
+        };
+        assert!(!is_admin());
+        run_command(&["make-admin", "carol"]).unwrap();
+        assert!(is_admin());
+        run_command(&["make-admin", "carol", "--revoke"]).unwrap();
+        assert!(!is_admin());
+        assert!(run_command(&["make-admin", "dave"]).is_err());
+
+        run_command(&["add-member", group_id, "carol"]).unwrap();
+        assert!(run_command(&["add-member", group_id, "carol"]).is_err());
+        let response = client
+            .get(format!("/api/v1/group/{}", group_id))
+            .header(carol_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let _ = std::fs::remove_file("./.money-balancer-backup-test-tmp.json");
+        run_command(&[
+            "backup",
+            "--output",
+            "./.money-balancer-backup-test-tmp.json",
+        ])
+        .unwrap();
+        assert!(run_command(&[
+            "backup",
+            "--output",
+            "./.money-balancer-backup-test-tmp.json"
+        ])
+        .unwrap_err()
+        .contains("--force"));
+        run_command(&[
+            "backup",
+            "--output",
+            "./.money-balancer-backup-test-tmp.json",
+            "--force",
+        ])
+        .unwrap();
+        #[cfg(unix)]
+        assert_eq!(
+            std::os::unix::fs::PermissionsExt::mode(
+                &std::fs::metadata("./.money-balancer-backup-test-tmp.json")
+                    .unwrap()
+                    .permissions()
+            ) & 0o777,
+            0o600
+        );
+        std::fs::remove_file("./.money-balancer-backup-test-tmp.json").unwrap();
+
+        run_command(&["list-groups"]).unwrap();
+        run_command(&["migrate", "status"]).unwrap();
+        run_command(&["migrate", "up"]).unwrap();
+        run_command(&["check-config"]).unwrap();
+    }
+}
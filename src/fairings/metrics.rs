@@ -0,0 +1,48 @@
+use crate::services::metrics::MetricsService;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+use std::sync::Arc;
+use std::time::Instant;
+
+struct RequestStart(Instant);
+
+/// Counts requests and measures their duration per route
+pub struct RequestMetrics;
+
+#[rocket::async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Collect request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let metrics_service = match request.rocket().state::<Arc<MetricsService>>() {
+            Some(metrics_service) => metrics_service,
+            None => return,
+        };
+
+        let duration = request
+            .local_cache(|| RequestStart(Instant::now()))
+            .0
+            .elapsed();
+        // the uri pattern instead of the uri itself, so ids don't end up as label values
+        let route = request
+            .route()
+            .map(|route| route.uri.to_string())
+            .unwrap_or_else(|| "unmatched".to_owned());
+
+        metrics_service.record_request(
+            request.method().as_str(),
+            &route,
+            response.status().code,
+            duration,
+        );
+    }
+}
@@ -1 +1,3 @@
 pub mod cors;
+pub mod metrics;
+pub mod request_id;
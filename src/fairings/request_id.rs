@@ -0,0 +1,112 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Data, Request, Response};
+use std::time::Instant;
+use tracing::info;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Identifies a request in the logs and in the `X-Request-Id` response header.
+/// The id of a proxy in front of money-balancer is reused, if it sent one.
+pub struct RequestId(String);
+
+impl RequestId {
+    pub fn of<'r>(request: &'r Request<'_>) -> &'r str {
+        &request
+            .local_cache(|| {
+                let id = request
+                    .headers()
+                    .get_one(REQUEST_ID_HEADER)
+                    .filter(|id| RequestId::_is_valid(id))
+                    .map(str::to_owned)
+                    .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+                RequestId(id)
+            })
+            .0
+    }
+
+    fn _is_valid(id: &str) -> bool {
+        !id.is_empty()
+            && id.len() <= 128
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    }
+}
+
+struct RequestStart(Instant);
+
+/// Assigns every request an id and logs it once it has been answered
+pub struct RequestLogger;
+
+#[rocket::async_trait]
+impl Fairing for RequestLogger {
+    fn info(&self) -> Info {
+        Info {
+            name: "Tag requests with an id and log them",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Instant::now()));
+        RequestId::of(request);
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let request_id = RequestId::of(request);
+        let duration = request
+            .local_cache(|| RequestStart(Instant::now()))
+            .0
+            .elapsed();
+
+        response.set_header(Header::new(REQUEST_ID_HEADER, request_id.to_owned()));
+
+        info!(
+            request_id,
+            method = %request.method(),
+            uri = %request.uri(),
+            status = response.status().code,
+            duration_ms = duration.as_millis() as u64,
+            "request"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::REQUEST_ID_HEADER;
+    use crate::build_test_rocket;
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use serial_test::serial;
+
+    #[test]
+    #[serial]
+    fn test_request_id() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        let response = client.get("/api/v1/group").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let generated = response.headers().get_one(REQUEST_ID_HEADER).unwrap();
+        assert!(uuid::Uuid::parse_str(generated).is_ok());
+
+        let response = client
+            .get("/api/v1/group")
+            .header(Header::new(REQUEST_ID_HEADER, "proxy-id.1"))
+            .dispatch();
+        assert_eq!(
+            response.headers().get_one(REQUEST_ID_HEADER),
+            Some("proxy-id.1")
+        );
+
+        let response = client
+            .get("/api/v1/group")
+            .header(Header::new(REQUEST_ID_HEADER, "not valid"))
+            .dispatch();
+        assert_ne!(
+            response.headers().get_one(REQUEST_ID_HEADER),
+            Some("not valid")
+        );
+    }
+}
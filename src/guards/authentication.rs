@@ -1,10 +1,16 @@
+use crate::fairings::request_id::RequestId;
+use crate::guards::headers::RequestHeaders;
 use crate::services;
 use crate::services::authentication::{AuthenticationError, AuthenticationService};
+use crate::services::configuration::ConfigurationService;
 use crate::services::user::UserService;
 use rocket::http::Status;
 use rocket::outcome::Outcome;
 use rocket::request::{self, FromRequest, Request};
+use sha2::{Digest, Sha256};
 use std::sync::Arc;
+use subtle::ConstantTimeEq;
+use tracing::{debug, error};
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for services::user::User {
@@ -16,6 +22,23 @@ impl<'r> FromRequest<'r> for services::user::User {
             .rocket()
             .state::<Arc<AuthenticationService>>()
             .unwrap();
+
+        if authentication_service.forward_auth_enabled() {
+            let headers = request.guard::<RequestHeaders>().await.unwrap();
+            let user = authentication_service
+                .authenticate_forward_auth(headers.headers, request.remote().map(|r| r.ip()))
+                .await;
+
+            match user {
+                Ok(Some(user)) => return Outcome::Success(user),
+                Ok(None) => {}
+                Err(e) => {
+                    error!(request_id = RequestId::of(request), error = ?e, "forward auth failed");
+                    return Outcome::Failure((Status::InternalServerError, e));
+                }
+            }
+        }
+
         let tokens: Vec<_> = request.headers().get("authorization").collect();
 
         if tokens.len() != 1 {
@@ -27,16 +50,85 @@ impl<'r> FromRequest<'r> for services::user::User {
         let claims = authentication_service.validate_jwt(token).await;
 
         if let Err(e) = claims {
-            println!("   >> Error validating token: {}", e);
+            debug!(request_id = RequestId::of(request), error = %e, "invalid token");
             return Outcome::Failure((Status::Unauthorized, AuthenticationError::InvalidToken));
         }
 
-        let user = user_service.get_user_by_id(claims.unwrap().id).await;
+        match user_service.get_user_by_id(claims.unwrap().id).await {
+            Ok(Some(user)) => Outcome::Success(user),
+            Ok(None) => Outcome::Failure((Status::Unauthorized, AuthenticationError::InvalidToken)),
+            Err(e) => {
+                error!(request_id = RequestId::of(request), error = %e, "loading the user failed");
+                Outcome::Failure((Status::InternalServerError, e.into()))
+            }
+        }
+    }
+}
 
-        if user.is_none() {
-            return Outcome::Failure((Status::Unauthorized, AuthenticationError::InvalidToken));
+/// A user with the admin flag set
+pub struct AdminUser(pub services::user::User);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminUser {
+    type Error = AuthenticationError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let user = match request.guard::<services::user::User>().await {
+            Outcome::Success(user) => user,
+            Outcome::Failure(f) => return Outcome::Failure(f),
+            Outcome::Forward(f) => return Outcome::Forward(f),
+        };
+
+        if !user.is_admin {
+            return Outcome::Failure((Status::Forbidden, AuthenticationError::NotAnAdmin));
         }
 
-        Outcome::Success(user.unwrap())
+        Outcome::Success(AdminUser(user))
     }
 }
+
+/// A client which may read the metrics, i.e. which sent the metrics token if one is configured
+pub struct MetricsScraper;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for MetricsScraper {
+    type Error = AuthenticationError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let configuration_service = request
+            .rocket()
+            .state::<Arc<ConfigurationService>>()
+            .unwrap();
+
+        let expected = match configuration_service.metrics() {
+            Some(config) => config.token.as_deref(),
+            None => {
+                return Outcome::Failure((Status::NotFound, AuthenticationError::ProviderDisabled))
+            }
+        };
+
+        let expected = match expected {
+            Some(expected) => expected,
+            None => return Outcome::Success(MetricsScraper),
+        };
+
+        let token = request
+            .headers()
+            .get_one("authorization")
+            .and_then(|header| header.strip_prefix("Bearer "));
+
+        match token {
+            Some(token) if is_same_secret(token, expected) => Outcome::Success(MetricsScraper),
+            Some(_) => Outcome::Failure((Status::Unauthorized, AuthenticationError::InvalidToken)),
+            None => Outcome::Failure((Status::Unauthorized, AuthenticationError::MissingToken)),
+        }
+    }
+}
+
+/// Compares the digests in constant time, so neither the content nor the length of the
+/// secret can be guessed from the response time
+fn is_same_secret(given: &str, expected: &str) -> bool {
+    Sha256::digest(given.as_bytes())
+        .ct_eq(&Sha256::digest(expected.as_bytes()))
+        .into()
+}
@@ -0,0 +1,36 @@
+use crate::services::configuration::ConfigurationService;
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// The ip address of the client. The `X-Real-IP` header is only honored for requests from
+/// the trusted proxies of the rate limit, everyone else could spoof it.
+pub struct ClientIp(pub Option<IpAddr>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientIp {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let configuration_service = request
+            .rocket()
+            .state::<Arc<ConfigurationService>>()
+            .unwrap();
+
+        let remote_ip = request.remote().map(|remote| remote.ip());
+        let trusted_proxies = &configuration_service
+            .auth_local_rate_limit()
+            .trusted_proxies;
+
+        let ip = match (remote_ip, trusted_proxies) {
+            (Some(ip), Some(trusted_proxies)) if trusted_proxies.contains(&ip) => {
+                request.real_ip().or(remote_ip)
+            }
+            _ => remote_ip,
+        };
+
+        Outcome::Success(ClientIp(ip))
+    }
+}
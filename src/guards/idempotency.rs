@@ -0,0 +1,161 @@
+use crate::routes::problem::Problem;
+use crate::services::idempotency::{IdempotencyService, StoredResponse, IDEMPOTENCY_KEY_HEADER};
+use rocket::http::{ContentType, Status};
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::serde_json::Error as JsonError;
+use rocket::serde::json::{self, Json};
+use rocket::serde::Serialize;
+use std::convert::Infallible;
+use std::future::Future;
+use std::io::Cursor;
+use std::sync::Arc;
+
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+/// The optional `Idempotency-Key` of a mutating request and the request it is bound to
+pub struct Idempotency {
+    idempotency_service: Arc<IdempotencyService>,
+    key: Option<String>,
+    request: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Idempotency {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let idempotency_service = request.rocket().state::<Arc<IdempotencyService>>().unwrap();
+
+        Outcome::Success(Idempotency {
+            idempotency_service: idempotency_service.clone(),
+            key: request
+                .headers()
+                .get_one(IDEMPOTENCY_KEY_HEADER)
+                .map(str::to_owned),
+            request: format!("{} {}", request.method(), request.uri().path()),
+        })
+    }
+}
+
+impl Idempotency {
+    /// Runs the handler, unless the user already made the request with the same key.
+    /// Then the stored response is replayed instead. Failed requests are not stored.
+    /// `body` is the parsed body of the request, `&()` for requests without one.
+    pub async fn run<B, R, F>(
+        self,
+        user_id: &str,
+        body: &B,
+        handler: F,
+    ) -> Result<IdempotentResponse, Problem>
+    where
+        B: Serialize,
+        R: IntoStoredResponse,
+        F: Future<Output = Result<R, Problem>>,
+    {
+        let key = match self.key {
+            Some(key) => key,
+            None => {
+                let response = handler.await?.into_stored_response();
+                return response
+                    .map(IdempotentResponse::new)
+                    .map_err(Self::_problem);
+            }
+        };
+
+        let body = json::to_string(body).map_err(Self::_problem)?;
+        if let Some(response) = self
+            .idempotency_service
+            .begin(user_id, &key, &self.request, &body)
+            .await?
+        {
+            return Ok(IdempotentResponse {
+                response,
+                replayed: true,
+            });
+        }
+
+        let response = match handler.await {
+            Ok(result) => result.into_stored_response().map_err(Self::_problem),
+            Err(problem) => Err(problem),
+        };
+        let response = match response {
+            Ok(response) => response,
+            Err(problem) => {
+                self.idempotency_service.release(user_id, &key).await?;
+                return Err(problem);
+            }
+        };
+
+        self.idempotency_service
+            .complete(user_id, &key, &response)
+            .await;
+
+        Ok(IdempotentResponse::new(response))
+    }
+
+    fn _problem(e: JsonError) -> Problem {
+        Problem {
+            internal_error: Some(e.to_string()),
+            ..Problem::new(Status::InternalServerError, "internal_error")
+        }
+    }
+}
+
+/// Results of handlers which can be replayed
+pub trait IntoStoredResponse {
+    fn into_stored_response(self) -> Result<StoredResponse, JsonError>;
+}
+
+impl<T: Serialize> IntoStoredResponse for Json<T> {
+    fn into_stored_response(self) -> Result<StoredResponse, JsonError> {
+        Ok(StoredResponse {
+            status: Status::Ok.code,
+            body: Some(json::to_string(&self.into_inner())?),
+        })
+    }
+}
+
+impl IntoStoredResponse for () {
+    fn into_stored_response(self) -> Result<StoredResponse, JsonError> {
+        Ok(StoredResponse {
+            status: Status::Ok.code,
+            body: None,
+        })
+    }
+}
+
+/// The response of a request with an `Idempotency-Key`, marked if it was replayed
+pub struct IdempotentResponse {
+    response: StoredResponse,
+    replayed: bool,
+}
+
+impl IdempotentResponse {
+    fn new(response: StoredResponse) -> IdempotentResponse {
+        IdempotentResponse {
+            response,
+            replayed: false,
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for IdempotentResponse {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let mut builder = Response::build();
+        builder.status(Status::from_code(self.response.status).unwrap_or(Status::Ok));
+
+        if let Some(body) = self.response.body {
+            builder
+                .header(ContentType::JSON)
+                .sized_body(body.len(), Cursor::new(body));
+        }
+
+        if self.replayed {
+            builder.raw_header(REPLAYED_HEADER, "true");
+        }
+
+        builder.ok()
+    }
+}
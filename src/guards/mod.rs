@@ -1,2 +1,4 @@
 pub mod authentication;
+pub mod client_ip;
 pub mod headers;
+pub mod idempotency;
@@ -0,0 +1,27 @@
+use crate::services::configuration::{LogFormat, LoggingConfig};
+use std::io;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
+
+/// Applied before the configured directives, which can override them. Rocket logs its whole
+/// configuration and several lines per request (the details with target `_`), which are replaced
+/// by the launch message and `fairings::request_id`. sqlx logs every query on info.
+const DEFAULT_DIRECTIVES: &str = "rocket=warn,_=off,sqlx=warn";
+
+/// Installs the global subscriber, which also receives the records of the `log` crate used by
+/// Rocket and sqlx. Commands log to stderr, so their output on stdout stays usable.
+pub fn init(config: &LoggingConfig, to_stderr: bool) {
+    let filter = EnvFilter::new(format!("{},{}", DEFAULT_DIRECTIVES, config.level));
+    let writer = match to_stderr {
+        true => BoxMakeWriter::new(io::stderr),
+        false => BoxMakeWriter::new(io::stdout),
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer);
+
+    let _ = match config.format {
+        LogFormat::Text => subscriber.try_init(),
+        LogFormat::Json => subscriber.json().flatten_event(true).try_init(),
+    };
+}
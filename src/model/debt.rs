@@ -1,16 +1,17 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.9.2
 
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "debt")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub transaction_id: String,
     #[sea_orm(primary_key, auto_increment = false)]
     pub debtor_id: String,
-    pub amount: i32,
-    pub was_split_unequally: i32,
+    pub amount: i64,
+    pub was_split_unequally: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
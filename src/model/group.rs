@@ -1,19 +1,24 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.9.2
 
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "group")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub id: String,
     pub name: String,
+    #[sea_orm(unique)]
+    pub external_id: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::group_member::Entity")]
     GroupMember,
+    #[sea_orm(has_many = "super::group_webhook::Entity")]
+    GroupWebhook,
     #[sea_orm(has_many = "super::transaction::Entity")]
     Transaction,
 }
@@ -24,6 +29,12 @@ impl Related<super::group_member::Entity> for Entity {
     }
 }
 
+impl Related<super::group_webhook::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::GroupWebhook.def()
+    }
+}
+
 impl Related<super::transaction::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Transaction.def()
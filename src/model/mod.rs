@@ -3,7 +3,17 @@
 pub mod prelude;
 
 pub mod debt;
+pub mod direct_debt;
+pub mod direct_transaction;
+pub mod friend;
+pub mod friend_request;
 pub mod group;
 pub mod group_member;
+pub mod group_webhook;
+pub mod group_webhook_delivery;
+pub mod idempotency_key;
+pub mod invite_code;
 pub mod transaction;
 pub mod user;
+pub mod user_totp;
+pub mod user_totp_recovery_code;
@@ -1,7 +1,17 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.9.2
 
 pub use super::debt::Entity as Debt;
+pub use super::direct_debt::Entity as DirectDebt;
+pub use super::direct_transaction::Entity as DirectTransaction;
+pub use super::friend::Entity as Friend;
+pub use super::friend_request::Entity as FriendRequest;
 pub use super::group::Entity as Group;
 pub use super::group_member::Entity as GroupMember;
+pub use super::group_webhook::Entity as GroupWebhook;
+pub use super::group_webhook_delivery::Entity as GroupWebhookDelivery;
+pub use super::idempotency_key::Entity as IdempotencyKey;
+pub use super::invite_code::Entity as InviteCode;
 pub use super::transaction::Entity as Transaction;
 pub use super::user::Entity as User;
+pub use super::user_totp::Entity as UserTotp;
+pub use super::user_totp_recovery_code::Entity as UserTotpRecoveryCode;
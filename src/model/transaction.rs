@@ -1,15 +1,16 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.9.2
 
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "transaction")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub id: String,
     pub group_id: String,
     pub creditor_id: String,
-    pub timestamp: i32,
+    pub timestamp: i64,
     pub description: String,
 }
 
@@ -1,8 +1,9 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.9.2
 
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "user")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
@@ -10,6 +11,9 @@ pub struct Model {
     pub username: String,
     pub nickname: String,
     pub password: String,
+    pub is_deleted: bool,
+    pub is_admin: bool,
+    pub is_disabled: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -20,6 +24,12 @@ pub enum Relation {
     Transaction,
     #[sea_orm(has_many = "super::debt::Entity")]
     Debt,
+    #[sea_orm(has_many = "super::user_totp::Entity")]
+    UserTotp,
+    #[sea_orm(has_many = "super::user_totp_recovery_code::Entity")]
+    UserTotpRecoveryCode,
+    #[sea_orm(has_many = "super::idempotency_key::Entity")]
+    IdempotencyKey,
 }
 
 impl Related<super::group_member::Entity> for Entity {
@@ -40,4 +50,22 @@ impl Related<super::debt::Entity> for Entity {
     }
 }
 
+impl Related<super::user_totp::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserTotp.def()
+    }
+}
+
+impl Related<super::user_totp_recovery_code::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserTotpRecoveryCode.def()
+    }
+}
+
+impl Related<super::idempotency_key::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::IdempotencyKey.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
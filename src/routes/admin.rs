@@ -0,0 +1,379 @@
+use crate::guards::authentication::AdminUser;
+use crate::routes::problem::Problem;
+use crate::services::error::invalid_field;
+use crate::services::group::{Group, GroupService};
+use crate::services::invite::{InviteCode, InviteService};
+use crate::services::user::{UserDetails, UserService};
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::*;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PasswordResetRequest {
+    password: String,
+}
+
+/// list all users
+#[utoipa::path(
+    context_path = "/admin",
+    tag = "admin",
+    params(("search" = Option<String>, Query, description = "only users whose username or nickname contains the search term")),
+    responses(
+        (status = 200, description = "all users including disabled ones", body = [UserDetails]),
+        (status = 403, description = "the user is not an administrator (code: `not_an_admin`)", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[get("/user?<search>")]
+async fn get_users(
+    search: Option<String>,
+    user_service: &State<Arc<UserService>>,
+    _admin: AdminUser,
+) -> Result<Json<Vec<UserDetails>>, Problem> {
+    Ok(Json(user_service.list_users(search.as_deref()).await?))
+}
+
+/// disable a user
+///
+/// Disabled users cannot log in and their tokens are rejected.
+#[utoipa::path(
+    context_path = "/admin",
+    tag = "admin",
+    params(("user_id" = String, Path, description = "id of the user")),
+    responses(
+        (status = 200, description = "the user was disabled"),
+        (status = 400, description = "admins cannot disable themselves (code: `validation_failed`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "the user is not an administrator (code: `not_an_admin`)", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "the user does not exist", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[post("/user/<user_id>/disable")]
+async fn disable_user(
+    user_id: String,
+    user_service: &State<Arc<UserService>>,
+    group_service: &State<Arc<GroupService>>,
+    admin: AdminUser,
+) -> Result<(), Problem> {
+    // admins must not lock themselves out
+    if admin.0.id == user_id {
+        return Err(invalid_field("user_id", "self", "Admins cannot disable themselves").into());
+    }
+
+    user_service.set_user_disabled(&user_id, true).await?;
+    group_service.revoke_access_of_user(&user_id);
+
+    Ok(())
+}
+
+/// enable a user
+///
+/// Also approves users waiting for approval.
+#[utoipa::path(
+    context_path = "/admin",
+    tag = "admin",
+    params(("user_id" = String, Path, description = "id of the user")),
+    responses(
+        (status = 200, description = "the user was enabled"),
+        (status = 403, description = "the user is not an administrator (code: `not_an_admin`)", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "the user does not exist", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[post("/user/<user_id>/enable")]
+async fn enable_user(
+    user_id: String,
+    user_service: &State<Arc<UserService>>,
+    _admin: AdminUser,
+) -> Result<(), Problem> {
+    Ok(user_service.set_user_disabled(&user_id, false).await?)
+}
+
+/// set the password of a user
+#[utoipa::path(
+    context_path = "/admin",
+    tag = "admin",
+    params(("user_id" = String, Path, description = "id of the user")),
+    request_body = PasswordResetRequest,
+    responses(
+        (status = 200, description = "the password was changed"),
+        (status = 400, description = "the password is invalid (code: `validation_failed`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "the user is not an administrator (code: `not_an_admin`)", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "the user does not exist", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[put("/user/<user_id>/password", data = "<password_reset_request>")]
+async fn reset_password(
+    user_id: String,
+    password_reset_request: Json<PasswordResetRequest>,
+    user_service: &State<Arc<UserService>>,
+    _admin: AdminUser,
+) -> Result<(), Problem> {
+    Ok(user_service
+        .set_password(&user_id, &password_reset_request.password)
+        .await?)
+}
+
+/// list all groups
+#[utoipa::path(
+    context_path = "/admin",
+    tag = "admin",
+    responses(
+        (status = 200, description = "detailed information about all groups", body = [Group]),
+        (status = 403, description = "the user is not an administrator (code: `not_an_admin`)", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[get("/group")]
+async fn get_groups(
+    group_service: &State<Arc<GroupService>>,
+    _admin: AdminUser,
+) -> Result<Json<Vec<Group>>, Problem> {
+    Ok(Json(group_service.get_all_groups().await?))
+}
+
+/// delete a group with all its transactions
+#[utoipa::path(
+    context_path = "/admin",
+    tag = "admin",
+    params(("group_id" = String, Path, description = "id of the group")),
+    responses(
+        (status = 200, description = "the group was deleted"),
+        (status = 403, description = "the user is not an administrator (code: `not_an_admin`)", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "the group does not exist", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[delete("/group/<group_id>")]
+async fn delete_group(
+    group_id: String,
+    group_service: &State<Arc<GroupService>>,
+    _admin: AdminUser,
+) -> Result<(), Problem> {
+    Ok(group_service.delete_group(&group_id).await?)
+}
+
+/// list all invite codes
+#[utoipa::path(
+    context_path = "/admin",
+    tag = "admin",
+    responses(
+        (status = 200, description = "all invite codes", body = [InviteCode]),
+        (status = 403, description = "the user is not an administrator (code: `not_an_admin`)", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[get("/invite")]
+async fn get_invite_codes(
+    invite_service: &State<Arc<InviteService>>,
+    _admin: AdminUser,
+) -> Result<Json<Vec<InviteCode>>, Problem> {
+    Ok(Json(invite_service.get_invite_codes().await?))
+}
+
+/// create an invite code
+#[utoipa::path(
+    context_path = "/admin",
+    tag = "admin",
+    responses(
+        (status = 200, description = "the new invite code", body = InviteCode),
+        (status = 403, description = "the user is not an administrator (code: `not_an_admin`)", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[post("/invite")]
+async fn create_invite_code(
+    invite_service: &State<Arc<InviteService>>,
+    admin: AdminUser,
+) -> Result<Json<InviteCode>, Problem> {
+    Ok(Json(invite_service.create_invite_code(&admin.0.id).await?))
+}
+
+/// delete an invite code
+#[utoipa::path(
+    context_path = "/admin",
+    tag = "admin",
+    params(("code" = String, Path, description = "the invite code")),
+    responses(
+        (status = 200, description = "the invite code was deleted"),
+        (status = 403, description = "the user is not an administrator (code: `not_an_admin`)", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "the invite code does not exist", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[delete("/invite/<code>")]
+async fn delete_invite_code(
+    code: String,
+    invite_service: &State<Arc<InviteService>>,
+    _admin: AdminUser,
+) -> Result<(), Problem> {
+    Ok(invite_service.delete_invite_code(&code).await?)
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![
+        get_users,
+        disable_user,
+        enable_user,
+        reset_password,
+        get_groups,
+        delete_group,
+        get_invite_codes,
+        create_invite_code,
+        delete_invite_code
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use crate::build_test_rocket;
+    use crate::routes::user::tests::{create_token, create_user, create_user_with_cli};
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::Value;
+    use serial_test::serial;
+
+    fn bearer(token: &str) -> Header<'static> {
+        Header::new("Authorization", format!("Bearer {}", token))
+    }
+
+    #[test]
+    #[serial]
+    fn test_admin_users() {
+        env::set_var("MONEYBALANCER_ADMIN_USERNAMES", "admin,root");
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+        env::remove_var("MONEYBALANCER_ADMIN_USERNAMES");
+
+        // admin rights are granted by username, which nobody may claim on their own
+        assert_eq!(
+            create_user(&client, "admin").unwrap_err(),
+            Status::Forbidden
+        );
+        let admin = create_user_with_cli(&client, "admin");
+        assert!(admin.is_admin);
+        let alice = create_user(&client, "alice").expect("user to be created");
+        assert!(!alice.is_admin);
+        create_user(&client, "bob").expect("user to be created");
+
+        let admin_token = create_token(&client, "admin", "admin").unwrap();
+        let alice_token = create_token(&client, "alice", "alice").unwrap();
+
+        let response = client
+            .patch("/api/v1/user")
+            .header(bearer(&alice_token))
+            .body(r#"{"username": " root"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client
+            .patch("/api/v1/user")
+            .header(bearer(&admin_token))
+            .body(r#"{"username": "admin", "nickname": "Admin"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .get("/api/v1/admin/user")
+            .header(bearer(&alice_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let users: Value = client
+            .get("/api/v1/admin/user?search=ALI")
+            .header(bearer(&admin_token))
+            .dispatch()
+            .into_json()
+            .unwrap();
+        assert_eq!(users.as_array().unwrap().len(), 1);
+        assert_eq!(users[0]["username"], "alice");
+
+        let response = client
+            .post(format!("/api/v1/admin/user/{}/disable", alice.id))
+            .header(bearer(&admin_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client
+            .get("/api/v1/user")
+            .header(bearer(&alice_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert!(create_token(&client, "alice", "alice").is_err());
+
+        let response = client
+            .post(format!("/api/v1/admin/user/{}/enable", alice.id))
+            .header(bearer(&admin_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .put(format!("/api/v1/admin/user/{}/password", alice.id))
+            .header(bearer(&admin_token))
+            .body(r#"{"password": "new-password"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(create_token(&client, "alice", "alice").is_err());
+        assert!(create_token(&client, "alice", "new-password").is_ok());
+
+        let response = client
+            .post(format!("/api/v1/admin/user/{}/disable", admin.id))
+            .header(bearer(&admin_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    #[serial]
+    fn test_admin_groups() {
+        env::set_var("MONEYBALANCER_ADMIN_USERNAMES", "admin");
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+        env::remove_var("MONEYBALANCER_ADMIN_USERNAMES");
+
+        create_user_with_cli(&client, "admin");
+        create_user(&client, "alice").expect("user to be created");
+        let admin_token = create_token(&client, "admin", "admin").unwrap();
+        let alice_token = create_token(&client, "alice", "alice").unwrap();
+
+        let group: Value = client
+            .post("/api/v1/group")
+            .header(bearer(&alice_token))
+            .body(r#"{"name": "Trip"}"#)
+            .dispatch()
+            .into_json()
+            .unwrap();
+        let group_id = group["id"].as_str().unwrap();
+        let response = client
+            .post(format!("/api/v1/group/{}/transaction", group_id))
+            .header(bearer(&alice_token))
+            .body(format!(
+                r#"{{"debtor_ids": ["{}"], "amount": 100, "description": "Bread"}}"#,
+                group["members"][0]["id"].as_str().unwrap()
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let groups: Value = client
+            .get("/api/v1/admin/group")
+            .header(bearer(&admin_token))
+            .dispatch()
+            .into_json()
+            .unwrap();
+        assert_eq!(groups.as_array().unwrap().len(), 1);
+        assert_eq!(groups[0]["name"], "Trip");
+
+        let response = client
+            .delete(format!("/api/v1/admin/group/{}", group_id))
+            .header(bearer(&admin_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .get(format!("/api/v1/group/{}", group_id))
+            .header(bearer(&alice_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+}
@@ -1,41 +1,114 @@
 use crate::guards;
-use crate::services::authentication::AuthenticationService;
+use crate::routes::problem::Problem;
+use crate::services::authentication::{
+    AuthenticationError, AuthenticationService, LocalAuthentication,
+};
 use ::serde::{Deserialize, Serialize};
-use rocket::http::Status;
-use rocket::response::Redirect;
+use rocket::http::{Header, Status};
+use rocket::response::{self, Redirect, Responder};
 use rocket::serde::json::Json;
 use rocket::*;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
-struct AvailableProviders {
+#[derive(Serialize, ToSchema)]
+pub struct AvailableProviders {
     local: PublicLocalConfig,
     proxy: PublicProxyConfig,
 }
 
-#[derive(Serialize, Deserialize)]
-struct TokenResponse {
-    token: String,
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TokenResponse {
+    pub token: String,
+}
+
+/// Returned instead of a token if the user has enabled two-factor authentication
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TotpChallengeResponse {
+    pub totp_challenge: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum LocalAuthenticationResponse {
+    Token(TokenResponse),
+    TotpChallenge(TotpChallengeResponse),
+}
+
+impl From<LocalAuthentication> for LocalAuthenticationResponse {
+    fn from(authentication: LocalAuthentication) -> Self {
+        match authentication {
+            LocalAuthentication::Token(token) => {
+                LocalAuthenticationResponse::Token(TokenResponse { token })
+            }
+            LocalAuthentication::TotpChallenge(totp_challenge) => {
+                LocalAuthenticationResponse::TotpChallenge(TotpChallengeResponse { totp_challenge })
+            }
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for AuthenticationError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let problem = match self {
+            AuthenticationError::Service(err) => return err.respond_to(request),
+            AuthenticationError::TooManyAttempts(retry_after) => {
+                return Response::build_from(
+                    Problem::new(Status::TooManyRequests, "too_many_attempts")
+                        .respond_to(request)?,
+                )
+                .header(Header::new(
+                    "Retry-After",
+                    // round up, so clients never retry too early
+                    (retry_after.as_secs() + 1).to_string(),
+                ))
+                .ok();
+            }
+            AuthenticationError::InvalidToken => "invalid_token",
+            AuthenticationError::MissingToken => "missing_token",
+            AuthenticationError::NotAnAdmin => "not_an_admin",
+            AuthenticationError::ProviderDisabled => "provider_disabled",
+            AuthenticationError::InvalidCredentials => "invalid_credentials",
+        };
+
+        Problem::new(Status::Unauthorized, problem).respond_to(request)
+    }
 }
 
 // == local provider ==
-#[derive(Serialize)]
-struct PublicLocalConfig {
+#[derive(Serialize, ToSchema)]
+pub struct PublicLocalConfig {
     enabled: bool,
 }
 
-#[derive(Deserialize, Serialize)]
-struct LocalRequest {
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct LocalRequest {
     username: String,
     password: String,
 }
 
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TotpRequest {
+    pub totp_challenge: String,
+    /// a code from the authenticator app or a recovery code
+    pub code: String,
+}
+
 // == proxy provider ==
-#[derive(Serialize)]
-struct PublicProxyConfig {
+#[derive(Serialize, ToSchema)]
+pub struct PublicProxyConfig {
     enabled: bool,
+    /// every request is authenticated by the proxy, no token is needed
+    forward_auth: bool,
 }
 
+/// get the enabled authentication providers
+#[utoipa::path(
+    context_path = "/auth",
+    tag = "auth",
+    responses((status = 200, description = "the enabled authentication providers", body = AvailableProviders))
+)]
 #[get("/")]
 async fn available_providers(
     authentication_service: &State<Arc<AuthenticationService>>,
@@ -46,45 +119,267 @@ async fn available_providers(
         },
         proxy: PublicProxyConfig {
             enabled: authentication_service.proxy_enabled(),
+            forward_auth: authentication_service.forward_auth_enabled(),
         },
     })
 }
 
+/// get a token from the local provider
+///
+/// If the user has enabled two-factor authentication, a totp challenge is returned instead,
+/// which has to be exchanged at `/auth/local/totp`.
+#[utoipa::path(
+    context_path = "/auth",
+    tag = "auth",
+    request_body = LocalRequest,
+    responses(
+        (status = 200, description = "the token or a totp challenge", body = LocalAuthenticationResponse),
+        (status = 401, description = "invalid username or password (code: `invalid_credentials`)", body = Problem, content_type = "application/problem+json"),
+        (status = 429, description = "too many failed login attempts, retry after the seconds in the `Retry-After` header", body = Problem, content_type = "application/problem+json")
+    )
+)]
 #[post("/local", data = "<local_request>")]
 async fn local(
     local_request: Json<LocalRequest>,
+    client_ip: guards::client_ip::ClientIp,
     authentication_service: &State<Arc<AuthenticationService>>,
-) -> Result<Json<TokenResponse>, Status> {
+) -> Result<Json<LocalAuthenticationResponse>, AuthenticationError> {
     let res = authentication_service
-        .authenticate_local(&local_request.username, &local_request.password)
-        .await;
+        .authenticate_local(
+            &local_request.username,
+            &local_request.password,
+            client_ip.0,
+        )
+        .await?;
 
-    match res {
-        Some(token) => Ok(Json(TokenResponse { token })),
-        None => Err(Status::Unauthorized),
-    }
+    Ok(Json(res.into()))
+}
+
+/// exchange a totp challenge for a token
+#[utoipa::path(
+    context_path = "/auth",
+    tag = "auth",
+    request_body = TotpRequest,
+    responses(
+        (status = 200, description = "the token", body = TokenResponse),
+        (status = 401, description = "invalid or expired challenge or code", body = Problem, content_type = "application/problem+json"),
+        (status = 429, description = "too many failed login attempts, retry after the seconds in the `Retry-After` header", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[post("/local/totp", data = "<totp_request>")]
+async fn local_totp(
+    totp_request: Json<TotpRequest>,
+    client_ip: guards::client_ip::ClientIp,
+    authentication_service: &State<Arc<AuthenticationService>>,
+) -> Result<Json<TokenResponse>, AuthenticationError> {
+    let totp_request = totp_request.into_inner();
+    let token = authentication_service
+        .authenticate_totp(totp_request.totp_challenge, &totp_request.code, client_ip.0)
+        .await?;
+
+    Ok(Json(TokenResponse { token }))
 }
 
+/// redirect the browser to the proxy login of the web client
+#[utoipa::path(
+    context_path = "/auth",
+    tag = "auth",
+    responses((status = 303, description = "redirect to the web client"))
+)]
 #[get("/proxy")]
 async fn proxy_redirect() -> Redirect {
     Redirect::to(uri!("/#/login/proxy"))
 }
 
+/// get a token from the proxy provider
+///
+/// The user is identified by the headers set by the proxy.
+#[utoipa::path(
+    context_path = "/auth",
+    tag = "auth",
+    responses(
+        (status = 200, description = "the token", body = TokenResponse),
+        (status = 401, description = "the request was not authenticated by the proxy", body = Problem, content_type = "application/problem+json")
+    )
+)]
 #[post("/proxy")]
 async fn proxy(
     request_headers: guards::headers::RequestHeaders,
+    remote: Option<SocketAddr>,
     authentication_service: &State<Arc<AuthenticationService>>,
-) -> Result<Json<TokenResponse>, Status> {
-    let res = authentication_service
-        .authenticate_proxy(request_headers.headers)
-        .await;
+) -> Result<Json<TokenResponse>, AuthenticationError> {
+    let token = authentication_service
+        .authenticate_proxy(request_headers.headers, remote.map(|r| r.ip()))
+        .await?
+        .ok_or(AuthenticationError::InvalidCredentials)?;
 
-    match res {
-        Some(token) => Ok(Json(TokenResponse { token })),
-        None => Err(Status::Unauthorized),
-    }
+    Ok(Json(TokenResponse { token }))
 }
 
 pub fn routes() -> Vec<rocket::Route> {
-    routes![available_providers, local, proxy, proxy_redirect]
+    routes![
+        available_providers,
+        local,
+        local_totp,
+        proxy,
+        proxy_redirect
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::net::SocketAddr;
+
+    use crate::build_test_rocket;
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use serial_test::serial;
+
+    const PROXY_VARIABLES: [(&str, &str); 8] = [
+        ("MONEYBALANCER_AUTH_PROXY_ENABLED", "true"),
+        ("MONEYBALANCER_AUTH_PROXY_HEADERS_USERNAME", "Remote-User"),
+        (
+            "MONEYBALANCER_AUTH_PROXY_TRUSTED_PROXIES",
+            "10.0.0.1, 192.168.0.0/16",
+        ),
+        ("MONEYBALANCER_AUTH_PROXY_HEADERS_SECRET", "X-Proxy-Secret"),
+        ("MONEYBALANCER_AUTH_PROXY_SECRET", "proxy-secret"),
+        ("MONEYBALANCER_AUTH_PROXY_HEADERS_GROUPS", "Remote-Groups"),
+        ("MONEYBALANCER_AUTH_PROXY_GROUPS_PREFIX", "mb-"),
+        ("MONEYBALANCER_AUTH_PROXY_GROUPS_REMOVE_STALE", "true"),
+    ];
+
+    fn build_proxy_client() -> Client {
+        for (key, value) in PROXY_VARIABLES {
+            env::set_var(key, value);
+        }
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+        for (key, _) in PROXY_VARIABLES {
+            env::remove_var(key);
+        }
+        client
+    }
+
+    fn proxy_login(client: &Client, remote: &str, secret: &str) -> Status {
+        client
+            .post("/api/v1/auth/proxy")
+            .remote(remote.parse::<SocketAddr>().unwrap())
+            .header(Header::new("Remote-User", "alice"))
+            .header(Header::new("X-Proxy-Secret", secret.to_owned()))
+            .dispatch()
+            .status()
+    }
+
+    #[test]
+    #[serial]
+    fn test_proxy_trusted_proxies() {
+        let client = build_proxy_client();
+
+        assert_eq!(
+            proxy_login(&client, "10.0.0.1:1234", "proxy-secret"),
+            Status::Ok
+        );
+        assert_eq!(
+            proxy_login(&client, "192.168.1.2:1234", "proxy-secret"),
+            Status::Ok
+        );
+        assert_eq!(
+            proxy_login(&client, "10.0.0.2:1234", "proxy-secret"),
+            Status::Unauthorized
+        );
+        assert_eq!(
+            proxy_login(&client, "10.0.0.1:1234", "wrong-secret"),
+            Status::Unauthorized
+        );
+
+        // spoofing the client ip via headers must not help
+        let status = client
+            .post("/api/v1/auth/proxy")
+            .remote("172.16.0.1:1234".parse::<SocketAddr>().unwrap())
+            .header(Header::new("X-Real-IP", "10.0.0.1"))
+            .header(Header::new("Remote-User", "alice"))
+            .header(Header::new("X-Proxy-Secret", "proxy-secret"))
+            .dispatch()
+            .status();
+        assert_eq!(status, Status::Unauthorized);
+    }
+
+    fn proxy_login_with_groups(
+        client: &Client,
+        username: &str,
+        groups: &str,
+    ) -> Vec<rocket::serde::json::Value> {
+        let response = client
+            .post("/api/v1/auth/proxy")
+            .remote("10.0.0.1:1234".parse::<SocketAddr>().unwrap())
+            .header(Header::new("Remote-User", username.to_owned()))
+            .header(Header::new("Remote-Groups", groups.to_owned()))
+            .header(Header::new("X-Proxy-Secret", "proxy-secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let token = response.into_json::<super::TokenResponse>().unwrap().token;
+
+        client
+            .get("/api/v1/group")
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch()
+            .into_json()
+            .unwrap()
+    }
+
+    fn group_names(groups: Vec<rocket::serde::json::Value>) -> Vec<String> {
+        let mut names = groups
+            .iter()
+            .map(|group| group["name"].as_str().unwrap().to_owned())
+            .collect::<Vec<String>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    #[serial]
+    fn test_proxy_groups() {
+        let client = build_proxy_client();
+
+        let groups = proxy_login_with_groups(&client, "alice", "mb-family, admins,mb-friends");
+        assert_eq!(group_names(groups), vec!["family", "friends"]);
+
+        // bob joins the existing group instead of creating a new one
+        let groups = proxy_login_with_groups(&client, "bob", "mb-family");
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0]["members"].as_array().unwrap().len(), 2);
+
+        let groups = proxy_login_with_groups(&client, "alice", "mb-friends");
+        assert_eq!(group_names(groups), vec!["friends"]);
+
+        let groups = proxy_login_with_groups(&client, "alice", "");
+        assert!(groups.is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn test_proxy_forward_auth() {
+        env::set_var("MONEYBALANCER_AUTH_PROXY_FORWARD_AUTH", "true");
+        let client = build_proxy_client();
+        env::remove_var("MONEYBALANCER_AUTH_PROXY_FORWARD_AUTH");
+
+        let get_user = |remote: &str| {
+            client
+                .get("/api/v1/user")
+                .remote(remote.parse::<SocketAddr>().unwrap())
+                .header(Header::new("Remote-User", "alice"))
+                .header(Header::new("Remote-Groups", "mb-family"))
+                .header(Header::new("X-Proxy-Secret", "proxy-secret"))
+                .dispatch()
+        };
+
+        let response = get_user("10.0.0.1:1234");
+        assert_eq!(response.status(), Status::Ok);
+        let user: rocket::serde::json::Value = response.into_json().unwrap();
+        assert_eq!(user["username"], "alice");
+        assert_eq!(user["groups"][0]["name"], "family");
+
+        assert_eq!(get_user("10.0.0.2:1234").status(), Status::Unauthorized);
+    }
 }
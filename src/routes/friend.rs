@@ -0,0 +1,508 @@
+use crate::routes::group::TransactionCreationRequest;
+use crate::routes::problem::Problem;
+use crate::services::friend::{Friend, FriendRequest, FriendService};
+use crate::services::group::Transaction;
+use crate::services::user::User;
+use rocket::serde::json::Json;
+use rocket::*;
+use std::sync::Arc;
+
+/// The new friend, or nothing while the friend request is pending
+#[derive(Responder)]
+enum FriendResponse {
+    #[response(status = 200)]
+    Friend(Json<Friend>),
+    #[response(status = 202)]
+    Requested(()),
+}
+
+/// get all friends
+#[utoipa::path(
+    context_path = "/friend",
+    tag = "friend",
+    responses((status = 200, description = "all friends with the balance between the user and each friend", body = [Friend])),
+    security(("bearerAuth" = []))
+)]
+#[get("/")]
+async fn get_all_friends(
+    friend_service: &State<Arc<FriendService>>,
+    user: User,
+) -> Result<Json<Vec<Friend>>, Problem> {
+    Ok(Json(friend_service.get_friends_of_user(&user.id).await?))
+}
+
+/// get a friend
+#[utoipa::path(
+    context_path = "/friend",
+    tag = "friend",
+    params(("friend_id" = String, Path, description = "id of the friend")),
+    responses(
+        (status = 200, description = "the friend with the balance between the user and the friend", body = Friend),
+        (status = 404, description = "the user is not a friend (code: `friend_not_found`)", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[get("/<friend_id>")]
+async fn get_friend(
+    friend_id: String,
+    friend_service: &State<Arc<FriendService>>,
+    user: User,
+) -> Result<Json<Friend>, Problem> {
+    Ok(Json(
+        friend_service
+            .get_friend_of_user(&user.id, &friend_id)
+            .await?,
+    ))
+}
+
+/// add a friend
+///
+/// Sends a friend request to the other user, or accepts the request of the other user.
+/// Friendships are mutual, both users become friends of each other once the request is accepted.
+#[utoipa::path(
+    context_path = "/friend",
+    tag = "friend",
+    params(("friend_id" = String, Path, description = "id of the friend")),
+    responses(
+        (status = 200, description = "the new friend, the request of the other user was accepted", body = Friend),
+        (status = 202, description = "the friend request was sent, the other user has to accept it"),
+        (status = 400, description = "users cannot befriend themselves (code: `validation_failed`)", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "the user does not exist (code: `user_not_found`)", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[post("/<friend_id>")]
+async fn add_friend(
+    friend_id: String,
+    friend_service: &State<Arc<FriendService>>,
+    user: User,
+) -> Result<FriendResponse, Problem> {
+    Ok(
+        match friend_service.add_friend(&user.id, &friend_id).await? {
+            Some(friend) => FriendResponse::Friend(Json(friend)),
+            None => FriendResponse::Requested(()),
+        },
+    )
+}
+
+/// get all pending friend requests
+#[utoipa::path(
+    context_path = "/friend",
+    tag = "friend",
+    responses((status = 200, description = "the friend requests sent by and to the user", body = [FriendRequest])),
+    security(("bearerAuth" = []))
+)]
+#[get("/request")]
+async fn get_friend_requests(
+    friend_service: &State<Arc<FriendService>>,
+    user: User,
+) -> Result<Json<Vec<FriendRequest>>, Problem> {
+    Ok(Json(
+        friend_service.get_friend_requests_of_user(&user.id).await?,
+    ))
+}
+
+/// decline or withdraw a friend request
+#[utoipa::path(
+    context_path = "/friend",
+    tag = "friend",
+    params(("user_id" = String, Path, description = "id of the other user")),
+    responses(
+        (status = 200, description = "the friend request was deleted"),
+        (status = 404, description = "there is no request between the users (code: `friend_request_not_found`)", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[delete("/request/<user_id>")]
+async fn delete_friend_request(
+    user_id: String,
+    friend_service: &State<Arc<FriendService>>,
+    user: User,
+) -> Result<(), Problem> {
+    Ok(friend_service
+        .delete_friend_request(&user.id, &user_id)
+        .await?)
+}
+
+/// remove a friend
+///
+/// The transactions are kept and show up again when they become friends again.
+#[utoipa::path(
+    context_path = "/friend",
+    tag = "friend",
+    params(("friend_id" = String, Path, description = "id of the friend")),
+    responses(
+        (status = 200, description = "the friendship was ended for both users"),
+        (status = 404, description = "the user is not a friend (code: `friend_not_found`)", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[delete("/<friend_id>")]
+async fn remove_friend(
+    friend_id: String,
+    friend_service: &State<Arc<FriendService>>,
+    user: User,
+) -> Result<(), Problem> {
+    Ok(friend_service.remove_friend(&user.id, &friend_id).await?)
+}
+
+/// get all transactions between the user and a friend
+#[utoipa::path(
+    context_path = "/friend",
+    tag = "friend",
+    params(("friend_id" = String, Path, description = "id of the friend")),
+    responses(
+        (status = 200, description = "all direct transactions with the friend", body = [Transaction]),
+        (status = 404, description = "the user is not a friend (code: `friend_not_found`)", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[get("/<friend_id>/transaction")]
+async fn get_friend_transactions(
+    friend_id: String,
+    friend_service: &State<Arc<FriendService>>,
+    user: User,
+) -> Result<Json<Vec<Transaction>>, Problem> {
+    Ok(Json(
+        friend_service
+            .get_transactions_with_friend(&user.id, &friend_id)
+            .await?,
+    ))
+}
+
+/// create a transaction with a friend
+///
+/// The debtors can only be the user and the friend.
+#[utoipa::path(
+    context_path = "/friend",
+    tag = "friend",
+    params(("friend_id" = String, Path, description = "id of the friend")),
+    request_body = TransactionCreationRequest,
+    responses(
+        (status = 200, description = "the new transaction", body = Transaction),
+        (status = 400, description = "the request contains invalid fields (code: `validation_failed`)", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "the user is not a friend (code: `friend_not_found`)", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[post("/<friend_id>/transaction", data = "<transaction_creation_request>")]
+async fn create_friend_transaction(
+    friend_id: String,
+    transaction_creation_request: Json<TransactionCreationRequest>,
+    friend_service: &State<Arc<FriendService>>,
+    user: User,
+) -> Result<Json<Transaction>, Problem> {
+    let request = transaction_creation_request.into_inner();
+
+    Ok(Json(
+        friend_service
+            .create_transaction(
+                &user.id,
+                &friend_id,
+                request.debtor_ids,
+                request.amount,
+                request.description,
+                request.timestamp,
+            )
+            .await?,
+    ))
+}
+
+/// delete a transaction with a friend
+#[utoipa::path(
+    context_path = "/friend",
+    tag = "friend",
+    params(
+        ("friend_id" = String, Path, description = "id of the friend"),
+        ("transaction_id" = String, Path, description = "id of the transaction")
+    ),
+    responses(
+        (status = 200, description = "the transaction was deleted"),
+        (status = 404, description = "the user is not a friend or the transaction does not exist", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[delete("/<friend_id>/transaction/<transaction_id>")]
+async fn delete_friend_transaction(
+    friend_id: String,
+    transaction_id: String,
+    friend_service: &State<Arc<FriendService>>,
+    user: User,
+) -> Result<(), Problem> {
+    Ok(friend_service
+        .delete_transaction(&user.id, &friend_id, &transaction_id)
+        .await?)
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![
+        get_all_friends,
+        get_friend,
+        add_friend,
+        get_friend_requests,
+        delete_friend_request,
+        remove_friend,
+        get_friend_transactions,
+        create_friend_transaction,
+        delete_friend_transaction
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::build_test_rocket;
+    use crate::routes::user::tests::{create_token, create_user};
+    use crate::services::friend::{Friend, FriendRequest};
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::{json, Value};
+    use serial_test::serial;
+
+    #[test]
+    #[serial]
+    fn test_friends() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        let alice = create_user(&client, "alice")
+            .expect("user to be created")
+            .id;
+        let bob = create_user(&client, "bob").expect("user to be created").id;
+        let alice_authorization = Header::new(
+            "Authorization",
+            format!(
+                "Bearer {}",
+                create_token(&client, "alice", "alice").unwrap()
+            ),
+        );
+        let bob_authorization = Header::new(
+            "Authorization",
+            format!("Bearer {}", create_token(&client, "bob", "bob").unwrap()),
+        );
+
+        let create_transaction = |authorization: &Header<'static>,
+                                  friend_id: &str,
+                                  debtor_ids: Vec<&str>,
+                                  amount: u32| {
+            client
+                .post(format!("/api/v1/friend/{}/transaction", friend_id))
+                .header(authorization.clone())
+                .body(
+                    json!({
+                        "debtor_ids": debtor_ids,
+                        "amount": amount,
+                        "description": "test",
+                    })
+                    .to_string(),
+                )
+                .dispatch()
+        };
+
+        // not friends yet
+        assert_eq!(
+            create_transaction(&alice_authorization, &bob, vec![&bob], 10).status(),
+            Status::NotFound
+        );
+
+        let response = client
+            .post(format!("/api/v1/friend/{}", alice))
+            .header(alice_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        // bob has to accept the request first
+        let response = client
+            .post(format!("/api/v1/friend/{}", bob))
+            .header(alice_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Accepted);
+        assert_eq!(
+            create_transaction(&alice_authorization, &bob, vec![&bob], 10).status(),
+            Status::NotFound
+        );
+        let requests = client
+            .get("/api/v1/friend/request")
+            .header(bob_authorization.clone())
+            .dispatch()
+            .into_json::<Vec<FriendRequest>>()
+            .unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].id, alice);
+        assert!(requests[0].is_incoming);
+
+        let response = client
+            .post(format!("/api/v1/friend/{}", alice))
+            .header(bob_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_json::<Friend>().unwrap().id, alice);
+        let requests = client
+            .get("/api/v1/friend/request")
+            .header(alice_authorization.clone())
+            .dispatch()
+            .into_json::<Vec<FriendRequest>>()
+            .unwrap();
+        assert!(requests.is_empty());
+
+        // friendships are mutual
+        let friends = client
+            .get("/api/v1/friend")
+            .header(bob_authorization.clone())
+            .dispatch()
+            .into_json::<Vec<Friend>>()
+            .unwrap();
+        assert_eq!(friends.len(), 1);
+        assert_eq!(friends[0].id, alice);
+
+        assert_eq!(
+            create_transaction(&alice_authorization, &bob, vec![], 10).status(),
+            Status::BadRequest
+        );
+
+        // split equally, the remaining cent is charged to one of them
+        let response = create_transaction(&alice_authorization, &bob, vec![&alice, &bob], 11);
+        assert_eq!(response.status(), Status::Ok);
+        let transaction = response.into_json::<Value>().unwrap();
+        assert!(transaction.get("group_id").is_none());
+        assert_eq!(transaction["debts"].as_array().unwrap().len(), 2);
+        let amount_of_bob = transaction["debts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|debt| debt["debtor_id"] == bob.as_str())
+            .unwrap()["amount"]
+            .as_i64()
+            .unwrap();
+
+        let response = create_transaction(&bob_authorization, &alice, vec![&alice], 3);
+        assert_eq!(response.status(), Status::Ok);
+        let transaction_of_bob = response.into_json::<Value>().unwrap();
+
+        let get_friend = |authorization: &Header<'static>, friend_id: &str| {
+            client
+                .get(format!("/api/v1/friend/{}", friend_id))
+                .header(authorization.clone())
+                .dispatch()
+                .into_json::<Friend>()
+                .unwrap()
+        };
+
+        assert_eq!(
+            get_friend(&alice_authorization, &bob).amount,
+            amount_of_bob - 3
+        );
+        assert_eq!(
+            get_friend(&bob_authorization, &alice).amount,
+            3 - amount_of_bob
+        );
+
+        // only the creditor can delete a transaction
+        let transaction_path = format!(
+            "/api/v1/friend/{}/transaction/{}",
+            bob,
+            transaction_of_bob["id"].as_str().unwrap()
+        );
+        let response = client
+            .delete(transaction_path)
+            .header(alice_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client
+            .get(format!("/api/v1/friend/{}/transaction", alice))
+            .header(bob_authorization.clone())
+            .dispatch();
+        assert_eq!(response.into_json::<Vec<Value>>().unwrap().len(), 2);
+
+        let response = client
+            .delete(format!("/api/v1/friend/{}", alice))
+            .header(bob_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .get(format!("/api/v1/friend/{}/transaction", bob))
+            .header(alice_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        // declined requests can not be accepted anymore
+        let response = client
+            .post(format!("/api/v1/friend/{}", bob))
+            .header(alice_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Accepted);
+        let response = client
+            .delete(format!("/api/v1/friend/request/{}", alice))
+            .header(bob_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client
+            .delete(format!("/api/v1/friend/request/{}", alice))
+            .header(bob_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client
+            .post(format!("/api/v1/friend/{}", alice))
+            .header(bob_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Accepted);
+    }
+
+    #[test]
+    #[serial]
+    fn test_timestamps_after_2038() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        let alice = create_user(&client, "alice")
+            .expect("user to be created")
+            .id;
+        let bob = create_user(&client, "bob").expect("user to be created").id;
+        let authorization = Header::new(
+            "Authorization",
+            format!(
+                "Bearer {}",
+                create_token(&client, "alice", "alice").unwrap()
+            ),
+        );
+
+        client
+            .post(format!("/api/v1/friend/{}", bob))
+            .header(authorization.clone())
+            .dispatch();
+        client
+            .post(format!("/api/v1/friend/{}", alice))
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", create_token(&client, "bob", "bob").unwrap()),
+            ))
+            .dispatch();
+
+        // after 2038, which does not fit into a 32-bit column
+        let response = client
+            .post(format!("/api/v1/friend/{}/transaction", bob))
+            .header(authorization.clone())
+            .body(
+                json!({
+                    "debtor_ids": [alice, bob],
+                    "amount": 10,
+                    "description": "Cinema",
+                    "timestamp": 4102444800i64,
+                })
+                .to_string(),
+            )
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_json::<Value>().unwrap()["timestamp"],
+            4102444800i64
+        );
+
+        let transactions = client
+            .get(format!("/api/v1/friend/{}/transaction", bob))
+            .header(authorization)
+            .dispatch()
+            .into_json::<Vec<Value>>()
+            .unwrap();
+        assert_eq!(transactions[0]["timestamp"], 4102444800i64);
+    }
+}
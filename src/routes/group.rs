@@ -1,159 +1,360 @@
-use crate::services::group::{
-    Debt, Group, GroupMember, GroupService, Transaction, TransactionCreationError,
-};
+use crate::guards::idempotency::{Idempotency, IdempotentResponse};
+use crate::routes::problem::Problem;
+use crate::services::group::{Debt, Group, GroupEvent, GroupMember, GroupService, Transaction};
 use crate::services::user::User;
-use rocket::http::Status;
-use rocket::serde::json::Json;
-use rocket::serde::Deserialize;
+use rocket::response::stream::{Event, EventStream};
+use rocket::serde::json::{json, Json};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::select;
 use rocket::*;
+use std::sync::Arc;
+use utoipa::ToSchema;
 
-#[derive(Deserialize)]
-struct GroupCreationRequest {
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct GroupCreationRequest {
+    #[schema(example = "Trip")]
     name: String,
 }
 
-#[derive(Deserialize)]
-struct TransactionCreationRequest {
-    debtor_ids: Vec<String>,
-    amount: u32,
-    description: String,
-    timestamp: Option<u32>,
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TransactionCreationRequest {
+    /// ids of the users who use the purchased good
+    pub debtor_ids: Vec<String>,
+    /// the amount of money purchased, in minor units
+    #[schema(minimum = 1, maximum = 9007199254740991, example = 20)]
+    pub amount: u64,
+    #[schema(example = "Bread")]
+    pub description: String,
+    /// unix timestamp (seconds), defaults to now. At most 100 years in the future.
+    #[schema(minimum = 0, example = 1675350727)]
+    pub timestamp: Option<i64>,
 }
 
+/// get all joined groups
+#[utoipa::path(
+    context_path = "/group",
+    tag = "group",
+    responses((status = 200, description = "detailed information about all joined groups", body = [Group])),
+    security(("bearerAuth" = []))
+)]
 #[get("/")]
 async fn get_all_groups(
-    group_service: &State<GroupService>,
+    group_service: &State<Arc<GroupService>>,
     user: User,
-) -> Result<Json<Vec<Group>>, Status> {
-    Ok(Json(group_service.get_groups_of_user(user.id).await))
+) -> Result<Json<Vec<Group>>, Problem> {
+    Ok(Json(group_service.get_groups_of_user(user.id).await?))
 }
 
+/// get detailed information about a group
+#[utoipa::path(
+    context_path = "/group",
+    tag = "group",
+    params(("group_id" = String, Path, description = "id of the group")),
+    responses(
+        (status = 200, description = "detailed information about the group", body = Group),
+        (status = 404, description = "the group does not exist or the user is not a member", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
 #[get("/<group_id>")]
 async fn get_group(
     group_id: String,
-    group_service: &State<GroupService>,
+    group_service: &State<Arc<GroupService>>,
     user: User,
-) -> Result<Json<Group>, Status> {
-    match group_service.get_group_of_user(group_id, user.id).await {
-        None => Err(Status::NotFound),
-        Some(group) => Ok(Json(group)),
-    }
+) -> Result<Json<Group>, Problem> {
+    Ok(Json(
+        group_service.get_group_of_user(group_id, user.id).await?,
+    ))
 }
 
+/// create a group
+#[utoipa::path(
+    context_path = "/group",
+    tag = "group",
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "replays the response of an earlier request with the same key")
+    ),
+    request_body = GroupCreationRequest,
+    responses(
+        (status = 200, description = "detailed information about the new group", body = Group),
+        (status = 400, description = "the request contains invalid fields (code: `validation_failed`)", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
 #[post("/", data = "<group_creation_request>")]
 async fn create_group(
-    group_service: &State<GroupService>,
+    group_service: &State<Arc<GroupService>>,
     user: User,
     group_creation_request: Json<GroupCreationRequest>,
-) -> Json<Group> {
-    Json(
-        group_service
-            .create_group(group_creation_request.name.to_owned(), user)
-            .await,
-    )
+    idempotency: Idempotency,
+) -> Result<IdempotentResponse, Problem> {
+    let user_id = user.id.to_owned();
+
+    idempotency
+        .run(&user_id, &*group_creation_request, async {
+            Ok(Json(
+                group_service
+                    .create_group(group_creation_request.name.to_owned(), user)
+                    .await?,
+            ))
+        })
+        .await
 }
 
+/// get all members of a group
+#[utoipa::path(
+    context_path = "/group",
+    tag = "group",
+    params(("group_id" = String, Path, description = "id of the group")),
+    responses(
+        (status = 200, description = "all members of the group", body = [GroupMember]),
+        (status = 404, description = "the group does not exist or the user is not a member", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
 #[get("/<group_id>/member")]
 async fn get_group_members(
     group_id: String,
-    group_service: &State<GroupService>,
+    group_service: &State<Arc<GroupService>>,
     user: User,
-) -> Result<Json<Vec<GroupMember>>, Status> {
-    match group_service
-        .get_members_of_group_of_user(group_id, user.id)
-        .await
-    {
-        None => Err(Status::NotFound),
-        Some(members) => Ok(Json(members)),
-    }
+) -> Result<Json<Vec<GroupMember>>, Problem> {
+    Ok(Json(
+        group_service
+            .get_members_of_group_of_user(group_id, user.id)
+            .await?,
+    ))
 }
 
+/// join a group
+#[utoipa::path(
+    context_path = "/group",
+    tag = "group",
+    params(
+        ("group_id" = String, Path, description = "id of the group"),
+        ("Idempotency-Key" = Option<String>, Header, description = "replays the response of an earlier request with the same key")
+    ),
+    responses(
+        (status = 200, description = "the user joined the group"),
+        (status = 404, description = "the group does not exist (code: `group_not_found`)", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "the user already is a member of the group (code: `already_a_member`)", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
 #[post("/<group_id>/member")]
 async fn create_group_member(
     group_id: String,
-    group_service: &State<GroupService>,
+    group_service: &State<Arc<GroupService>>,
     user: User,
-) -> Status {
-    if !group_service
-        .create_group_member(group_id, user.id, false)
+    idempotency: Idempotency,
+) -> Result<IdempotentResponse, Problem> {
+    idempotency
+        .run(&user.id, &(), async {
+            Ok(group_service
+                .create_group_member(group_id, user.id.to_owned(), false)
+                .await?)
+        })
         .await
-    {
-        return Status::BadRequest;
-    }
-
-    Status::Ok
 }
 
+/// get all transactions of a group
+#[utoipa::path(
+    context_path = "/group",
+    tag = "group",
+    params(("group_id" = String, Path, description = "id of the group")),
+    responses(
+        (status = 200, description = "all transactions of the group, the latest first", body = [Transaction]),
+        (status = 404, description = "the group does not exist or the user is not a member", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
 #[get("/<group_id>/transaction")]
 async fn get_group_transactions(
     group_id: String,
-    group_service: &State<GroupService>,
+    group_service: &State<Arc<GroupService>>,
     user: User,
-) -> Result<Json<Vec<Transaction>>, Status> {
-    match group_service
-        .get_transactions_of_group_of_user(group_id, user.id)
-        .await
-    {
-        Some(t) => Ok(Json(t)),
-        None => Err(Status::NotFound),
-    }
+) -> Result<Json<Vec<Transaction>>, Problem> {
+    Ok(Json(
+        group_service
+            .get_transactions_of_group_of_user(group_id, user.id)
+            .await?,
+    ))
 }
 
+/// create a transaction in a group
+///
+/// The amount is split equally between the debtors, see the readme for how the rest is assigned.
+#[utoipa::path(
+    context_path = "/group",
+    tag = "group",
+    params(
+        ("group_id" = String, Path, description = "id of the group"),
+        ("Idempotency-Key" = Option<String>, Header, description = "replays the response of an earlier request with the same key")
+    ),
+    request_body = TransactionCreationRequest,
+    responses(
+        (status = 200, description = "the new transaction", body = Transaction),
+        (status = 400, description = "the request contains invalid fields (code: `validation_failed`)", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "the group does not exist or the user is not a member", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
 #[post("/<group_id>/transaction", data = "<transaction_creation_request>")]
 async fn create_group_tansaction(
     group_id: String,
     transaction_creation_request: Json<TransactionCreationRequest>,
-    group_service: &State<GroupService>,
+    group_service: &State<Arc<GroupService>>,
     user: User,
-) -> Result<Json<Transaction>, Status> {
-    match group_service
-        .create_transaction(
-            group_id,
-            user.id,
-            transaction_creation_request.debtor_ids.to_owned(),
-            transaction_creation_request.amount,
-            transaction_creation_request.description.to_owned(),
-            transaction_creation_request.timestamp,
-        )
+    idempotency: Idempotency,
+) -> Result<IdempotentResponse, Problem> {
+    idempotency
+        .run(&user.id, &*transaction_creation_request, async {
+            Ok(Json(
+                group_service
+                    .create_transaction(
+                        group_id,
+                        user.id.to_owned(),
+                        transaction_creation_request.debtor_ids.to_owned(),
+                        transaction_creation_request.amount,
+                        transaction_creation_request.description.to_owned(),
+                        transaction_creation_request.timestamp,
+                    )
+                    .await?,
+            ))
+        })
         .await
-    {
-        Ok(t) => Ok(Json(t)),
-        Err(e) => match e {
-            TransactionCreationError::GroupNotFound => Err(Status::NotFound),
-            TransactionCreationError::DebtorNotInGroup => Err(Status::BadRequest),
-        },
-    }
 }
 
+/// delete a transaction in a group
+///
+/// Only the creditor of a transaction can delete it.
+#[utoipa::path(
+    context_path = "/group",
+    tag = "group",
+    params(
+        ("group_id" = String, Path, description = "id of the group"),
+        ("transaction_id" = String, Path, description = "id of the transaction"),
+        ("Idempotency-Key" = Option<String>, Header, description = "replays the response of an earlier request with the same key")
+    ),
+    responses(
+        (status = 200, description = "the transaction was deleted"),
+        (status = 404, description = "the transaction does not exist or the user is not its creditor", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
 #[delete("/<group_id>/transaction/<transaction_id>")]
 async fn delete_group_transaction(
     group_id: String,
     transaction_id: String,
-    group_service: &State<GroupService>,
+    group_service: &State<Arc<GroupService>>,
     user: User,
-) -> Status {
-    match group_service
-        .delete_transaction(&group_id, &user.id, &transaction_id)
+    idempotency: Idempotency,
+) -> Result<IdempotentResponse, Problem> {
+    idempotency
+        .run(&user.id, &(), async {
+            Ok(group_service
+                .delete_transaction(&group_id, &user.id, &transaction_id)
+                .await?)
+        })
         .await
-    {
-        true => Status::Ok,
-        false => Status::NotFound,
-    }
 }
 
+/// get the balance of the current user with every other member of a group
+///
+/// Positive amounts are owed to the user, negative amounts are owed by the user.
+#[utoipa::path(
+    context_path = "/group",
+    tag = "group",
+    params(("group_id" = String, Path, description = "id of the group")),
+    responses(
+        (status = 200, description = "the balance with every other member", body = [Debt]),
+        (status = 404, description = "the group does not exist or the user is not a member", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
 #[get("/<group_id>/debt")]
 async fn get_group_debts(
     group_id: String,
-    group_service: &State<GroupService>,
+    group_service: &State<Arc<GroupService>>,
     user: User,
-) -> Result<Json<Vec<Debt>>, Status> {
-    match group_service
-        .get_debts_of_user_in_group(&group_id, &user.id)
-        .await
-    {
-        Some(d) => Ok(Json(d)),
-        None => Err(Status::NotFound),
-    }
+) -> Result<Json<Vec<Debt>>, Problem> {
+    Ok(Json(
+        group_service
+            .get_debts_of_user_in_group(&group_id, &user.id)
+            .await?,
+    ))
+}
+
+/// subscribe to the changes of a group
+///
+/// A stream of server-sent events, named after the change. The data is a json object with
+/// the `event` and its `data`, like the body of a webhook without `id` and `timestamp`:
+/// `transaction.created`, `transaction.deleted`, `member.added` and `member.removed`.
+/// Changes of transactions are followed by `balance.changed` with the balance of the user
+/// with every other member. `resync` means that events were missed and the group has to be
+/// reloaded. The stream ends after `member.removed` if the user is removed from the group and
+/// after `group.deleted` if the group is deleted. It ends without an event if the user is
+/// disabled or deleted.
+///
+/// `EventSource` cannot send the token, so browsers have to read the stream with `fetch`.
+#[utoipa::path(
+    context_path = "/group",
+    tag = "group",
+    params(("group_id" = String, Path, description = "id of the group")),
+    responses(
+        (status = 200, description = "the events of the group", content_type = "text/event-stream", body = String),
+        (status = 404, description = "the group does not exist or the user is not a member", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[get("/<group_id>/events")]
+async fn get_group_events(
+    group_id: String,
+    group_service: &State<Arc<GroupService>>,
+    user: User,
+    mut shutdown: Shutdown,
+) -> Result<EventStream![], Problem> {
+    let mut events = group_service
+        .subscribe_to_group(&group_id, &user.id)
+        .await?;
+    let group_service = group_service.inner().clone();
+
+    Ok(EventStream! {
+        loop {
+            let event = select! {
+                event = events.recv() => event,
+                _ = &mut shutdown => break,
+            };
+
+            let event = match event {
+                Some(Ok(GroupEvent::AccessRevoked { user_id })) if user_id == user.id => break,
+                Some(Ok(GroupEvent::AccessRevoked { .. })) => continue,
+                Some(Ok(event)) => event,
+                Some(Err(_)) => {
+                    yield Event::empty().event("resync");
+                    continue;
+                }
+                None => break,
+            };
+
+            yield Event::json(&event).event(event.name());
+
+            match &event {
+                GroupEvent::MemberRemoved { id } if *id == user.id => break,
+                GroupEvent::GroupDeleted => break,
+                _ => {}
+            }
+
+            if event.changes_balances() {
+                match group_service.get_debts_of_user_in_group(&group_id, &user.id).await {
+                    Ok(debts) => {
+                        yield Event::json(&json!({ "event": "balance.changed", "data": debts }))
+                            .event("balance.changed")
+                    }
+                    Err(_) => yield Event::empty().event("resync"),
+                }
+            }
+        }
+    })
 }
 
 pub fn routes() -> Vec<rocket::Route> {
@@ -166,6 +367,620 @@ pub fn routes() -> Vec<rocket::Route> {
         get_group_transactions,
         create_group_tansaction,
         delete_group_transaction,
-        get_group_debts
+        get_group_debts,
+        get_group_events
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::build_test_rocket;
+    use crate::routes::problem::Problem;
+    use crate::routes::user::tests::{create_token, create_user, create_user_with_cli};
+    use crate::services::error::ServiceError;
+    use crate::services::group::MAX_AMOUNT;
+    use crate::services::idempotency::IdempotencyService;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::{self, json, Value};
+    use sea_orm::{ConnectionTrait, Database, Statement};
+    use serial_test::serial;
+    use std::env;
+    use std::io::{BufRead, BufReader};
+    use std::sync::Arc;
+
+    #[test]
+    #[serial]
+    fn test_transaction_validation() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        let alice = create_user(&client, "alice")
+            .expect("user to be created")
+            .id;
+        let authorization = Header::new(
+            "Authorization",
+            format!(
+                "Bearer {}",
+                create_token(&client, "alice", "alice").unwrap()
+            ),
+        );
+
+        let response = client
+            .post("/api/v1/group")
+            .header(authorization.clone())
+            .body(json!({ "name": " " }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let problem = response.into_json::<Problem>().unwrap();
+        assert_eq!(problem.errors[0].field, "name");
+
+        let group = client
+            .post("/api/v1/group")
+            .header(authorization.clone())
+            .body(json!({ "name": "Trip" }).to_string())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        let group_id = group["id"].as_str().unwrap();
+
+        let create_transaction = |group_id: &str, body: Value| {
+            client
+                .post(format!("/api/v1/group/{}/transaction", group_id))
+                .header(authorization.clone())
+                .body(body.to_string())
+                .dispatch()
+        };
+
+        let response = create_transaction(
+            group_id,
+            json!({
+                "debtor_ids": [alice, alice],
+                "amount": 10,
+                "description": "Bread",
+            }),
+        );
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(
+            response.content_type().unwrap().to_string(),
+            "application/problem+json"
+        );
+        let problem = response.into_json::<Problem>().unwrap();
+        assert_eq!(problem.code, "validation_failed");
+        assert_eq!(problem.errors.len(), 1);
+        assert_eq!(problem.errors[0].field, "debtor_ids");
+        assert_eq!(problem.errors[0].code, "duplicate");
+
+        // an empty list of debtors used to divide by zero
+        let problem = create_transaction(
+            group_id,
+            json!({
+                "debtor_ids": [],
+                "amount": 0,
+                "description": "x".repeat(256),
+            }),
+        )
+        .into_json::<Problem>()
+        .unwrap();
+        let errors = problem
+            .errors
+            .iter()
+            .map(|error| (error.field.as_str(), error.code.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(
+            errors,
+            vec![
+                ("debtor_ids", "empty"),
+                ("amount", "zero"),
+                ("description", "too_long")
+            ]
+        );
+
+        let transactions = client
+            .get(format!("/api/v1/group/{}/transaction", group_id))
+            .header(authorization.clone())
+            .dispatch()
+            .into_json::<Vec<Value>>()
+            .unwrap();
+        assert!(transactions.is_empty());
+
+        let response = create_transaction(
+            "unknown",
+            json!({
+                "debtor_ids": [alice],
+                "amount": 10,
+                "description": "Bread",
+            }),
+        );
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(
+            response.into_json::<Problem>().unwrap().code,
+            "group_not_found"
+        );
+
+        // errors of rocket itself are problem details as well
+        let response = create_transaction(group_id, json!({ "amount": 10 }));
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(
+            response.into_json::<Problem>().unwrap().code,
+            "unprocessable_entity"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_transaction_is_rolled_back_on_failure() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        let alice = create_user(&client, "alice")
+            .expect("user to be created")
+            .id;
+        let authorization = Header::new(
+            "Authorization",
+            format!(
+                "Bearer {}",
+                create_token(&client, "alice", "alice").unwrap()
+            ),
+        );
+
+        let group = client
+            .post("/api/v1/group")
+            .header(authorization.clone())
+            .body(json!({ "name": "Trip" }).to_string())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        let group_id = group["id"].as_str().unwrap();
+
+        // every debtor may only owe once, so the debt of the second transaction violates
+        // the index after its transaction row was inserted
+        let db = futures::executor::block_on(Database::connect(
+            env::var("MONEYBALANCER_DATABASE_URL").unwrap(),
+        ))
+        .unwrap();
+        futures::executor::block_on(db.execute(Statement::from_string(
+            db.get_database_backend(),
+            "CREATE UNIQUE INDEX idx_test_debt_debtor_id ON debt (debtor_id)".to_owned(),
+        )))
+        .unwrap();
+
+        let create_transaction = || {
+            client
+                .post(format!("/api/v1/group/{}/transaction", group_id))
+                .header(authorization.clone())
+                .body(
+                    json!({
+                        "debtor_ids": [alice],
+                        "amount": 10,
+                        "description": "Bread",
+                    })
+                    .to_string(),
+                )
+                .dispatch()
+                .status()
+        };
+        assert_eq!(create_transaction(), Status::Ok);
+        assert_eq!(create_transaction(), Status::InternalServerError);
+
+        let transactions = client
+            .get(format!("/api/v1/group/{}/transaction", group_id))
+            .header(authorization.clone())
+            .dispatch()
+            .into_json::<Vec<Value>>()
+            .unwrap();
+        assert_eq!(transactions.len(), 1);
+    }
+
+    #[test]
+    #[serial]
+    fn test_large_amounts_and_timestamps() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        create_user(&client, "alice").unwrap();
+        let bob = create_user(&client, "bob").unwrap().id;
+        let authorization = |username: &str| {
+            Header::new(
+                "Authorization",
+                format!(
+                    "Bearer {}",
+                    create_token(&client, username, username).unwrap()
+                ),
+            )
+        };
+
+        let group_id = client
+            .post("/api/v1/group")
+            .header(authorization("alice"))
+            .body(json!({ "name": "Trip" }).to_string())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        client
+            .post(format!("/api/v1/group/{}/member", group_id))
+            .header(authorization("bob"))
+            .dispatch();
+
+        let create_transaction = |amount: u64| {
+            client
+                .post(format!("/api/v1/group/{}/transaction", group_id))
+                .header(authorization("alice"))
+                .body(
+                    json!({
+                        "debtor_ids": [bob],
+                        "amount": amount,
+                        "description": "Car",
+                        // after 2038
+                        "timestamp": 4102444800i64,
+                    })
+                    .to_string(),
+                )
+                .dispatch()
+        };
+
+        // the sum exceeds 32 bits
+        for _ in 0..2 {
+            let response = create_transaction(3_000_000_000);
+            assert_eq!(response.status(), Status::Ok);
+            let transaction = response.into_json::<Value>().unwrap();
+            assert_eq!(transaction["timestamp"], 4102444800i64);
+            assert_eq!(transaction["debts"][0]["amount"], 3_000_000_000i64);
+        }
+
+        let debts = client
+            .get(format!("/api/v1/group/{}/debt", group_id))
+            .header(authorization("alice"))
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        assert_eq!(debts[0]["amount"], 6_000_000_000i64);
+
+        let balance = client
+            .get("/api/v1/user/balance")
+            .header(authorization("bob"))
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        assert_eq!(balance["amount"], -6_000_000_000i64);
+
+        let response = create_transaction(MAX_AMOUNT + 1);
+        assert_eq!(response.status(), Status::BadRequest);
+        let problem = response.into_json::<Problem>().unwrap();
+        assert_eq!(problem.errors[0].code, "too_large");
+
+        let create_transaction_at = |timestamp: i64| {
+            client
+                .post(format!("/api/v1/group/{}/transaction", group_id))
+                .header(authorization("alice"))
+                .body(
+                    json!({
+                        "debtor_ids": [bob],
+                        "amount": 10,
+                        "description": "Car",
+                        "timestamp": timestamp,
+                    })
+                    .to_string(),
+                )
+                .dispatch()
+                .into_json::<Problem>()
+                .unwrap()
+        };
+        let problem = create_transaction_at(-1);
+        assert_eq!(problem.errors[0].field, "timestamp");
+        assert_eq!(problem.errors[0].code, "negative");
+        // milliseconds instead of seconds
+        let problem = create_transaction_at(4102444800000);
+        assert_eq!(problem.errors[0].field, "timestamp");
+        assert_eq!(problem.errors[0].code, "too_far_in_future");
+    }
+
+    /// Reads the next event of a server-sent event stream, skipping comments
+    fn read_event(reader: &mut impl BufRead) -> (String, Value) {
+        let (mut name, mut data) = (String::new(), String::new());
+        loop {
+            let mut line = String::new();
+            assert!(reader.read_line(&mut line).unwrap() > 0, "the stream ended");
+            let line = line.trim_end();
+
+            if line.is_empty() && !name.is_empty() {
+                return (name, json::from_str(&data).unwrap_or(Value::Null));
+            } else if let Some(value) = line.strip_prefix("event:") {
+                name = value.trim().to_owned();
+            } else if let Some(value) = line.strip_prefix("data:") {
+                data.push_str(value.trim());
+            }
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_group_events() {
+        env::set_var("MONEYBALANCER_ADMIN_USERNAMES", "admin");
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+        env::remove_var("MONEYBALANCER_ADMIN_USERNAMES");
+
+        create_user_with_cli(&client, "admin");
+        create_user(&client, "alice").unwrap();
+        let bob = create_user(&client, "bob").unwrap().id;
+        let authorization = |username: &str| {
+            Header::new(
+                "Authorization",
+                format!(
+                    "Bearer {}",
+                    create_token(&client, username, username).unwrap()
+                ),
+            )
+        };
+
+        let group_id = client
+            .post("/api/v1/group")
+            .header(authorization("alice"))
+            .body(json!({ "name": "Dinner" }).to_string())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        let events_uri = format!("/api/v1/group/{}/events", group_id);
+
+        let response = client.get(&events_uri).dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client
+            .get(&events_uri)
+            .header(authorization("bob"))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client
+            .get(&events_uri)
+            .header(authorization("alice"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::EventStream));
+        let mut events = BufReader::new(response);
+
+        client
+            .post(format!("/api/v1/group/{}/member", group_id))
+            .header(authorization("bob"))
+            .dispatch();
+        let transaction = client
+            .post(format!("/api/v1/group/{}/transaction", group_id))
+            .header(authorization("bob"))
+            .body(json!({ "debtor_ids": [bob], "amount": 10, "description": "Pizza" }).to_string())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        let debts = client
+            .get(format!("/api/v1/group/{}/debt", group_id))
+            .header(authorization("alice"))
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+
+        let (name, event) = read_event(&mut events);
+        assert_eq!(name, "member.added");
+        assert_eq!(event["data"]["id"], bob);
+        assert_eq!(event["data"]["is_owner"], false);
+
+        let (name, event) = read_event(&mut events);
+        assert_eq!(name, "transaction.created");
+        assert_eq!(event["data"], transaction);
+
+        let (name, event) = read_event(&mut events);
+        assert_eq!(name, "balance.changed");
+        assert_eq!(event["data"], debts);
+
+        // deleted users lose access, but stay members
+        let response = client
+            .get(&events_uri)
+            .header(authorization("bob"))
+            .dispatch();
+        let mut events_of_bob = BufReader::new(response);
+        let response = client
+            .delete("/api/v1/user")
+            .header(authorization("bob"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_stream_ended(&mut events_of_bob);
+
+        let response = client
+            .delete(format!("/api/v1/admin/group/{}", group_id))
+            .header(authorization("admin"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let (name, _) = read_event(&mut events);
+        assert_eq!(name, "group.deleted");
+        assert_stream_ended(&mut events);
+    }
+
+    fn assert_stream_ended(reader: &mut impl BufRead) {
+        for line in reader.lines() {
+            let line = line.unwrap();
+            assert!(line.is_empty() || line.starts_with(':'), "{}", line);
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_idempotency_keys() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        let alice = create_user(&client, "alice").unwrap().id;
+        create_user(&client, "bob").unwrap();
+        let authorization = |username: &str| {
+            Header::new(
+                "Authorization",
+                format!(
+                    "Bearer {}",
+                    create_token(&client, username, username).unwrap()
+                ),
+            )
+        };
+        let idempotency_key = |key: &str| Header::new("Idempotency-Key", key.to_owned());
+
+        let group_id = client
+            .post("/api/v1/group")
+            .header(authorization("alice"))
+            .body(json!({ "name": "Trip" }).to_string())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        let transactions_uri = format!("/api/v1/group/{}/transaction", group_id);
+        let create_transaction = |key: &str, amount: u64| {
+            client
+                .post(&transactions_uri)
+                .header(authorization("alice"))
+                .header(idempotency_key(key))
+                .body(
+                    json!({ "debtor_ids": [alice], "amount": amount, "description": "Fuel" })
+                        .to_string(),
+                )
+                .dispatch()
+        };
+        let count_transactions = || {
+            client
+                .get(&transactions_uri)
+                .header(authorization("alice"))
+                .dispatch()
+                .into_json::<Vec<Value>>()
+                .unwrap()
+                .len()
+        };
+
+        let response = create_transaction("first", 10);
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Idempotent-Replayed"), None);
+        let transaction = response.into_json::<Value>().unwrap();
+
+        // the retry returns the first transaction instead of creating another one
+        let response = create_transaction("first", 10);
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        assert_eq!(
+            response.headers().get_one("Idempotent-Replayed"),
+            Some("true")
+        );
+        assert_eq!(response.into_json::<Value>().unwrap(), transaction);
+        assert_eq!(count_transactions(), 1);
+
+        let response = create_transaction("first", 20);
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(
+            response.into_json::<Problem>().unwrap().code,
+            "idempotency_key_reused"
+        );
+
+        // failed requests are not stored
+        let response = create_transaction("second", 0);
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = create_transaction("second", 20);
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Idempotent-Replayed"), None);
+        assert_eq!(count_transactions(), 2);
+
+        let delete_transaction = || {
+            client
+                .delete(format!(
+                    "{}/{}",
+                    transactions_uri,
+                    transaction["id"].as_str().unwrap()
+                ))
+                .header(authorization("alice"))
+                .header(idempotency_key("third"))
+                .dispatch()
+        };
+        assert_eq!(delete_transaction().status(), Status::Ok);
+        let response = delete_transaction();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Idempotent-Replayed"),
+            Some("true")
+        );
+        assert_eq!(count_transactions(), 1);
+
+        let response = client
+            .post("/api/v1/group")
+            .header(authorization("alice"))
+            .header(idempotency_key("first"))
+            .body(json!({ "name": "Dinner" }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(
+            response.into_json::<Problem>().unwrap().code,
+            "idempotency_key_reused"
+        );
+
+        // keys belong to the user
+        let response = client
+            .post(format!("/api/v1/group/{}/member", group_id))
+            .header(authorization("bob"))
+            .header(idempotency_key("first"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.body().is_none());
+
+        let response = client
+            .post("/api/v1/group")
+            .header(authorization("alice"))
+            .header(idempotency_key(&"x".repeat(256)))
+            .body(json!({ "name": "Dinner" }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(
+            response.into_json::<Problem>().unwrap().errors[0].field,
+            "Idempotency-Key"
+        );
+
+        let db = futures::executor::block_on(Database::connect(
+            env::var("MONEYBALANCER_DATABASE_URL").unwrap(),
+        ))
+        .unwrap();
+        let execute = |sql: &str| {
+            futures::executor::block_on(db.execute(Statement::from_string(
+                db.get_database_backend(),
+                sql.to_owned(),
+            )))
+            .unwrap();
+        };
+
+        // a key is in use until the response of its request is stored
+        let idempotency_service = client.rocket().state::<Arc<IdempotencyService>>().unwrap();
+        let begin = || {
+            futures::executor::block_on(idempotency_service.begin(&alice, "fourth", "POST /", "{}"))
+        };
+        assert!(matches!(begin(), Ok(None)));
+        assert!(matches!(
+            begin(),
+            Err(ServiceError::Conflict("idempotency_key_in_use"))
+        ));
+
+        // expired keys are ignored until they are removed
+        execute("UPDATE idempotency_key SET created_at = 0");
+        let response = create_transaction("first", 20);
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Idempotent-Replayed"), None);
+        futures::executor::block_on(idempotency_service.remove_expired_keys()).unwrap();
+        assert!(matches!(begin(), Ok(None)));
+
+        // storing the second response fails, so its key is never processed again
+        execute("DELETE FROM idempotency_key");
+        execute("CREATE UNIQUE INDEX idx_test_idempotency_key_response_status ON idempotency_key (response_status)");
+        assert_eq!(create_transaction("fifth", 30).status(), Status::Ok);
+        assert_eq!(create_transaction("sixth", 40).status(), Status::Ok);
+        let count = count_transactions();
+        let response = create_transaction("sixth", 40);
+        assert_eq!(response.status(), Status::Conflict);
+        assert_eq!(
+            response.into_json::<Problem>().unwrap().code,
+            "idempotency_key_in_use"
+        );
+        execute("UPDATE idempotency_key SET created_at = created_at - 3600");
+        assert_eq!(create_transaction("sixth", 40).status(), Status::Conflict);
+        assert_eq!(count_transactions(), count);
+    }
+}
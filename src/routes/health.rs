@@ -0,0 +1,87 @@
+use crate::services::health::{HealthService, HealthStatus, Readiness};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::*;
+use std::sync::Arc;
+
+#[derive(Serialize)]
+struct Liveness {
+    status: HealthStatus,
+}
+
+/// The process is running and answering requests, nothing else is checked
+#[get("/live")]
+async fn live() -> Json<Liveness> {
+    Json(Liveness {
+        status: HealthStatus::Up,
+    })
+}
+
+#[get("/ready")]
+async fn ready(health_service: &State<Arc<HealthService>>) -> (Status, Json<Readiness>) {
+    let readiness = health_service.readiness().await;
+    let status = match readiness.status {
+        HealthStatus::Up => Status::Ok,
+        HealthStatus::Down => Status::ServiceUnavailable,
+    };
+
+    (status, Json(readiness))
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![live, ready]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::services::configuration::ConfigurationService;
+    use crate::{build_test_rocket, set_up_db};
+    use futures::executor::block_on;
+    use migration::{Migrator, MigratorTrait};
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::{json, Value};
+    use serial_test::serial;
+
+    #[test]
+    #[serial]
+    fn test_health() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        let response = client.get("/health/live").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_json::<Value>(), Some(json!({"status": "up"})));
+
+        let response = client.get("/health/ready").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let readiness = response.into_json::<Value>().unwrap();
+        assert_eq!(readiness["status"], "up");
+        assert_eq!(readiness["checks"]["database"]["status"], "up");
+        assert_eq!(readiness["checks"]["migrations"]["status"], "up");
+        assert_eq!(readiness["checks"]["migrations"]["pending"], json!([]));
+        assert!(readiness["checks"]["migrations"]["current"].is_string());
+    }
+
+    #[test]
+    #[serial]
+    fn test_health_pending_migrations() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+        let configuration_service = ConfigurationService::load(None).unwrap();
+        let db = block_on(set_up_db(configuration_service.database())).unwrap();
+        block_on(Migrator::down(&db, Some(1))).unwrap();
+
+        let response = client.get("/health/ready").dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let readiness = response.into_json::<Value>().unwrap();
+        assert_eq!(readiness["status"], "down");
+        assert_eq!(readiness["checks"]["database"]["status"], "up");
+        assert_eq!(
+            readiness["checks"]["migrations"]["pending"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
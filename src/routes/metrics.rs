@@ -0,0 +1,113 @@
+use crate::guards::authentication::MetricsScraper;
+use crate::routes::problem::Problem;
+use crate::services::metrics::MetricsService;
+use rocket::http::ContentType;
+use rocket::*;
+use std::sync::Arc;
+
+#[get("/metrics")]
+async fn metrics(
+    metrics_service: &State<Arc<MetricsService>>,
+    _scraper: MetricsScraper,
+) -> Result<(ContentType, String), Problem> {
+    Ok((
+        ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+        metrics_service.render().await?,
+    ))
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![metrics]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::build_test_rocket;
+    use crate::routes::user::tests::{create_token, create_user};
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use serial_test::serial;
+    use std::env;
+
+    fn build_metrics_client(token: Option<&str>) -> Client {
+        env::set_var("MONEYBALANCER_METRICS_ENABLED", "true");
+        if let Some(token) = token {
+            env::set_var("MONEYBALANCER_METRICS_TOKEN", token);
+        }
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+        env::remove_var("MONEYBALANCER_METRICS_ENABLED");
+        env::remove_var("MONEYBALANCER_METRICS_TOKEN");
+        client
+    }
+
+    #[test]
+    #[serial]
+    fn test_metrics() {
+        let client = build_metrics_client(None);
+
+        create_user(&client, "alice").unwrap();
+        create_token(&client, "alice", "alice").unwrap();
+        assert_eq!(
+            create_token(&client, "alice", "wrong"),
+            Err(Status::Unauthorized)
+        );
+        client.get("/api/v1/group/some-id").dispatch();
+
+        let response = client.get("/metrics").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let metrics = response.into_string().unwrap();
+
+        assert!(metrics.contains(
+            r#"moneybalancer_http_requests_total{method="POST",route="/api/v1/user",status="200"} 1"#
+        ));
+        // the route pattern instead of the requested uri
+        assert!(metrics.contains(r#"route="/api/v1/group/<group_id>""#));
+        assert!(!metrics.contains("some-id"));
+        assert!(metrics.contains(
+            r#"moneybalancer_authentications_total{provider="local",result="success"} 1"#
+        ));
+        assert!(metrics.contains(
+            r#"moneybalancer_authentications_total{provider="local",result="failure"} 1"#
+        ));
+        assert!(metrics.contains(r#"moneybalancer_entities{kind="users"} 1"#));
+        assert!(metrics.contains(r#"moneybalancer_entities{kind="groups"} 0"#));
+        assert!(metrics.contains(
+            r#"moneybalancer_db_query_duration_seconds_count{failed="false",statement="insert"}"#
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn test_metrics_token() {
+        let client = build_metrics_client(Some("scrape-secret"));
+
+        assert_eq!(
+            client.get("/metrics").dispatch().status(),
+            Status::Unauthorized
+        );
+        assert_eq!(
+            client
+                .get("/metrics")
+                .header(Header::new("Authorization", "Bearer wrong"))
+                .dispatch()
+                .status(),
+            Status::Unauthorized
+        );
+        assert_eq!(
+            client
+                .get("/metrics")
+                .header(Header::new("Authorization", "Bearer scrape-secret"))
+                .dispatch()
+                .status(),
+            Status::Ok
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_metrics_disabled() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        assert_eq!(client.get("/metrics").dispatch().status(), Status::NotFound);
+    }
+}
@@ -1,5 +1,11 @@
+pub mod admin;
 pub mod auth;
 pub mod client;
+pub mod friend;
 pub mod group;
+pub mod health;
+pub mod metrics;
+pub mod problem;
 pub mod swagger;
 pub mod user;
+pub mod webhook;
@@ -0,0 +1,112 @@
+use crate::fairings::request_id::RequestId;
+use crate::services::error::{FieldError, ServiceError};
+use ::serde::{Deserialize, Serialize};
+use rocket::http::{ContentType, Status};
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use rocket::*;
+use tracing::error;
+use utoipa::ToSchema;
+
+/// An error response following RFC 7807 (`application/problem+json`).
+/// `code` is machine-readable and stable, `title` and `detail` are meant for humans.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Problem {
+    #[schema(example = 400)]
+    pub status: u16,
+    #[schema(example = "validation_failed")]
+    pub code: String,
+    #[schema(example = "Bad Request")]
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+    /// Logged together with the request id, but never sent to the client
+    #[serde(skip)]
+    pub internal_error: Option<String>,
+}
+
+impl Problem {
+    pub fn new(status: Status, code: &str) -> Problem {
+        Problem {
+            status: status.code,
+            code: code.to_owned(),
+            title: status.reason_lossy().to_owned(),
+            detail: None,
+            errors: Vec::new(),
+            internal_error: None,
+        }
+    }
+
+    pub fn with_detail(mut self, detail: &str) -> Problem {
+        self.detail = Some(detail.to_owned());
+        self
+    }
+
+    /// A problem without a more specific code than its status
+    pub fn from_status(status: Status) -> Problem {
+        let code = status
+            .reason_lossy()
+            .to_lowercase()
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+
+        Problem::new(status, &code)
+    }
+}
+
+impl From<ServiceError> for Problem {
+    fn from(err: ServiceError) -> Self {
+        match err {
+            ServiceError::NotFound(code) => Problem::new(Status::NotFound, code),
+            ServiceError::Forbidden(code) => Problem::new(Status::Forbidden, code),
+            ServiceError::Conflict(code) => Problem::new(Status::Conflict, code),
+            ServiceError::Unprocessable(code) => Problem::new(Status::UnprocessableEntity, code),
+            ServiceError::Validation(errors) => Problem {
+                errors,
+                ..Problem::new(Status::BadRequest, "validation_failed")
+                    .with_detail("The request contains invalid fields")
+            },
+            ServiceError::Database(err) => Problem {
+                internal_error: Some(err.to_string()),
+                ..Problem::new(Status::InternalServerError, "internal_error")
+            },
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Problem {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let status = Status::from_code(self.status).unwrap_or(Status::InternalServerError);
+
+        if let Some(internal_error) = &self.internal_error {
+            error!(
+                request_id = RequestId::of(request),
+                error = %internal_error,
+                "request failed"
+            );
+        }
+
+        Response::build_from(Json(self).respond_to(request)?)
+            .status(status)
+            .header(ContentType::new("application", "problem+json"))
+            .ok()
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ServiceError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        Problem::from(self).respond_to(request)
+    }
+}
+
+/// Turns all errors of the api without a body, like failing guards or malformed json,
+/// into problem details as well
+#[catch(default)]
+fn default_catcher(status: Status, _: &Request) -> Problem {
+    Problem::from_status(status)
+}
+
+pub fn catchers() -> Vec<rocket::Catcher> {
+    catchers![default_catcher]
+}
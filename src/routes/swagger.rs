@@ -1,14 +1,165 @@
 use rocket::*;
 
+use crate::routes::{admin, auth, friend, group, problem, user, webhook};
+use crate::services;
 use rocket::http::ContentType;
 use rust_embed::RustEmbed;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::openapi::{Deprecated, PathItemType};
+use utoipa::{Modify, OpenApi};
 
 #[derive(RustEmbed)]
 #[folder = "src/resources/api"]
 #[prefix = "swagger/"]
 struct SwaggerAssets;
 
+/// The API documentation, generated from the routes and their types
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "money-balancer API",
+        description = "Errors are returned as problem details (RFC 7807) with the content type `application/problem+json`. The `code` of a problem is machine-readable and stable, invalid fields of a request are listed in `errors`. Requests which change a group can be retried safely with the same `Idempotency-Key` header, reusing a key for another request fails with the code `idempotency_key_reused` and retrying a request which is still being processed with `idempotency_key_in_use`.",
+    ),
+    servers((url = "/api/v1")),
+    paths(
+        auth::available_providers,
+        auth::local,
+        auth::local_totp,
+        auth::proxy_redirect,
+        auth::proxy,
+        user::get_current_user,
+        user::get_balance,
+        user::export,
+        user::create_user,
+        user::update_user,
+        user::delete_user,
+        user::token,
+        user::begin_totp_enrolment,
+        user::confirm_totp_enrolment,
+        user::disable_totp,
+        group::get_all_groups,
+        group::get_group,
+        group::create_group,
+        group::get_group_members,
+        group::create_group_member,
+        group::get_group_transactions,
+        group::create_group_tansaction,
+        group::delete_group_transaction,
+        group::get_group_debts,
+        group::get_group_events,
+        webhook::get_group_webhooks,
+        webhook::create_group_webhook,
+        webhook::delete_group_webhook,
+        webhook::get_group_webhook_deliveries,
+        friend::get_all_friends,
+        friend::get_friend,
+        friend::add_friend,
+        friend::get_friend_requests,
+        friend::delete_friend_request,
+        friend::remove_friend,
+        friend::get_friend_transactions,
+        friend::create_friend_transaction,
+        friend::delete_friend_transaction,
+        admin::get_users,
+        admin::disable_user,
+        admin::enable_user,
+        admin::reset_password,
+        admin::get_groups,
+        admin::delete_group,
+        admin::get_invite_codes,
+        admin::create_invite_code,
+        admin::delete_invite_code,
+    ),
+    components(schemas(
+        problem::Problem,
+        services::error::FieldError,
+        auth::AvailableProviders,
+        auth::PublicLocalConfig,
+        auth::PublicProxyConfig,
+        auth::LocalRequest,
+        auth::TotpRequest,
+        auth::TokenResponse,
+        auth::TotpChallengeResponse,
+        auth::LocalAuthenticationResponse,
+        user::UserCreationRequest,
+        user::UserUpdateRequest,
+        user::UserAuthenticationRequest,
+        user::TotpCodeRequest,
+        user::FullUser,
+        admin::PasswordResetRequest,
+        webhook::WebhookCreationRequest,
+        group::GroupCreationRequest,
+        group::TransactionCreationRequest,
+        services::group::Group,
+        services::group::GroupMember,
+        services::group::Transaction,
+        services::group::Debt,
+        services::group::Balance,
+        services::group::GroupBalance,
+        services::group::UserBalance,
+        services::friend::Friend,
+        services::friend::FriendRequest,
+        services::export::UserExport,
+        services::export::ExportedProfile,
+        services::export::ExportedActivity,
+        services::export::ActivityKind,
+        services::invite::InviteCode,
+        services::totp::TotpEnrolment,
+        services::totp::TotpRecoveryCodes,
+        services::user::UserDetails,
+        services::webhook::Webhook,
+        services::webhook::WebhookDelivery,
+        services::webhook::DeliveryStatus,
+    )),
+    tags(
+        (name = "auth", description = "Authentication with the enabled providers"),
+        (name = "user", description = "The authenticated user"),
+        (name = "group", description = "Groups, their members and transactions"),
+        (name = "webhook", description = "Webhooks of groups, only available if enabled"),
+        (name = "friend", description = "Friends and direct transactions between two users"),
+        (name = "admin", description = "Administration, only available to admins"),
+    ),
+    modifiers(&ApiDocModifier),
+)]
+pub struct ApiDoc;
+
+struct ApiDocModifier;
+
+impl Modify for ApiDocModifier {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        // the license is taken from the crate metadata, which has none
+        openapi.info.license = None;
+
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearerAuth",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
+        }
+
+        // rocket serves routes mounted at `/` without the trailing slash
+        openapi.paths.paths = std::mem::take(&mut openapi.paths.paths)
+            .into_iter()
+            .map(|(path, item)| match path.strip_suffix('/') {
+                Some(stripped) if !stripped.is_empty() => (stripped.to_owned(), item),
+                _ => (path, item),
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        // superseded by `/auth/local`, but kept for existing clients
+        if let Some(operation) = openapi
+            .paths
+            .paths
+            .get_mut("/user/token")
+            .and_then(|item| item.operations.get_mut(&PathItemType::Post))
+        {
+            operation.deprecated = Some(Deprecated::True);
+        }
+    }
+}
+
 #[get("/")]
 async fn swagger() -> (ContentType, Cow<'static, [u8]>) {
     (
@@ -18,13 +169,82 @@ async fn swagger() -> (ContentType, Cow<'static, [u8]>) {
 }
 
 #[get("/openapi.yaml")]
-async fn openapi() -> (ContentType, Cow<'static, [u8]>) {
+async fn openapi() -> (ContentType, String) {
     (
         ContentType::Text,
-        SwaggerAssets::get("swagger/openapi.yaml").unwrap().data,
+        ApiDoc::openapi()
+            .to_yaml()
+            .expect("error serializing the api documentation"),
     )
 }
 
 pub fn routes() -> Vec<rocket::Route> {
     routes![swagger, openapi]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ApiDoc;
+    use crate::build_test_rocket;
+    use rocket::http::{ContentType, Method, Status};
+    use rocket::local::blocking::Client;
+    use serial_test::serial;
+    use std::env;
+    use utoipa::openapi::PathItemType;
+    use utoipa::OpenApi;
+
+    #[test]
+    #[serial]
+    fn test_openapi() {
+        // mounts the optional routes as well
+        env::set_var("MONEYBALANCER_WEBHOOKS_ENABLED", "true");
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+        env::remove_var("MONEYBALANCER_WEBHOOKS_ENABLED");
+
+        let response = client.get("/api/v1/openapi.yaml").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::Text));
+        let document = response.into_string().unwrap();
+        assert!(document.starts_with("openapi: 3."));
+        assert!(document.contains("/group/{group_id}/transaction:"));
+        assert!(document.contains("bearerAuth"));
+
+        // every api route has to be documented
+        let openapi = ApiDoc::openapi();
+        let mut documented = 0;
+        for route in client.rocket().routes() {
+            let path = match route.uri.path().strip_prefix("/api/v1") {
+                Some(path) if route.uri.base() != "/api/v1" => path,
+                _ => continue,
+            };
+            let path = path
+                .trim_end_matches('/')
+                .replace('<', "{")
+                .replace('>', "}");
+            let operation = match route.method {
+                Method::Get => PathItemType::Get,
+                Method::Post => PathItemType::Post,
+                Method::Put => PathItemType::Put,
+                Method::Patch => PathItemType::Patch,
+                Method::Delete => PathItemType::Delete,
+                method => panic!("unexpected method {}", method),
+            };
+
+            assert!(
+                openapi.paths.get_path_operation(&path, operation).is_some(),
+                "{} {} is not documented",
+                route.method,
+                path
+            );
+            documented += 1;
+        }
+
+        let operations: usize = openapi
+            .paths
+            .paths
+            .values()
+            .map(|item| item.operations.len())
+            .sum();
+        assert_eq!(documented, operations);
+    }
+}
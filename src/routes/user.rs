@@ -1,130 +1,500 @@
-use crate::services::authentication::AuthenticationService;
-use crate::services::configuration::ConfigurationService;
-use crate::services::group::{Group, GroupService};
+use crate::guards::client_ip::ClientIp;
+use crate::routes::auth::LocalAuthenticationResponse;
+use crate::routes::problem::Problem;
+use crate::services::authentication::{AuthenticationError, AuthenticationService};
+use crate::services::configuration::{ConfigurationService, RegistrationPolicy};
+use crate::services::error::{invalid_field, ServiceResult};
+use crate::services::export::{ExportService, UserExport, EXPORT_FILE_NAME};
+use crate::services::group::{Balance, Group, GroupService};
+use crate::services::invite::InviteService;
+use crate::services::totp::{TotpEnrolment, TotpError, TotpRecoveryCodes, TotpService};
 use crate::services::user::{User, UserService};
 use ::serde::{Deserialize, Serialize};
-use rocket::http::Status;
+use rocket::http::{ContentType, Header, Status};
+use rocket::response::{self, status, Responder};
 use rocket::serde::json::Json;
 use rocket::*;
 use std::sync::Arc;
+use utoipa::ToSchema;
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct UserCreationRequest {
     username: String,
     nickname: String,
     password: String,
+    /// required if the registration policy is `invite`
+    invite_code: Option<String>,
 }
 
-#[derive(Deserialize, Serialize)]
-struct UserAuthenticationRequest {
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct UserUpdateRequest {
+    username: Option<String>,
+    nickname: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct UserAuthenticationRequest {
     username: String,
     password: String,
 }
 
-#[derive(Serialize, Deserialize)]
-struct TokenResponse {
-    token: String,
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TotpCodeRequest {
+    code: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct FullUser {
     pub id: String,
     pub username: String,
     pub nickname: String,
+    pub is_admin: bool,
     pub groups: Vec<Group>,
 }
 
+/// The personal data export, served as a file download
+pub enum ExportResponse {
+    Json(String),
+    Zip(Vec<u8>),
+}
+
+impl<'r> Responder<'r, 'static> for ExportResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let (content_type, extension, response) = match self {
+            ExportResponse::Json(body) => (ContentType::JSON, "json", body.respond_to(request)?),
+            ExportResponse::Zip(body) => (ContentType::ZIP, "zip", body.respond_to(request)?),
+        };
+
+        Response::build_from(response)
+            .header(content_type)
+            .header(Header::new(
+                "Content-Disposition",
+                format!(
+                    "attachment; filename=\"{}.{}\"",
+                    EXPORT_FILE_NAME, extension
+                ),
+            ))
+            .ok()
+    }
+}
+
+impl From<TotpError> for Problem {
+    fn from(err: TotpError) -> Self {
+        match err {
+            TotpError::AlreadyEnabled => Problem::new(Status::Conflict, "totp_already_enabled"),
+            TotpError::NotEnrolled => Problem::new(Status::NotFound, "totp_not_enrolled"),
+            TotpError::InvalidCode => Problem::new(Status::Forbidden, "invalid_totp_code"),
+            TotpError::Service(err) => err.into(),
+        }
+    }
+}
+
 #[async_trait]
 trait UserToFullUser {
-    async fn to_full_user(self: Self, service: &GroupService) -> FullUser;
+    async fn to_full_user(self, service: &GroupService) -> ServiceResult<FullUser>;
 }
 
 #[async_trait]
 impl UserToFullUser for User {
-    async fn to_full_user(self, service: &GroupService) -> FullUser {
-        FullUser {
+    async fn to_full_user(self, service: &GroupService) -> ServiceResult<FullUser> {
+        Ok(FullUser {
             id: self.id.to_owned(),
             username: self.username.to_owned(),
             nickname: self.nickname.to_owned(),
-            groups: service.get_groups_of_user(self.id).await,
-        }
+            is_admin: self.is_admin,
+            groups: service.get_groups_of_user(self.id).await?,
+        })
     }
 }
 
+/// get detailed information about the authenticated user
+#[utoipa::path(
+    context_path = "/user",
+    tag = "user",
+    responses((status = 200, description = "detailed information about the user", body = FullUser)),
+    security(("bearerAuth" = []))
+)]
 #[get("/")]
 async fn get_current_user(
-    group_service: &State<GroupService>,
+    group_service: &State<Arc<GroupService>>,
+    user: User,
+) -> Result<Json<FullUser>, Problem> {
+    Ok(Json(user.to_full_user(group_service).await?))
+}
+
+/// get the balance of the current user across all groups
+///
+/// Positive amounts are owed to the user, negative amounts are owed by the user.
+#[utoipa::path(
+    context_path = "/user",
+    tag = "user",
+    responses((status = 200, description = "the total balance, the balance per group and the balance per other user", body = Balance)),
+    security(("bearerAuth" = []))
+)]
+#[get("/balance")]
+async fn get_balance(
+    group_service: &State<Arc<GroupService>>,
     user: User,
-) -> Result<Json<FullUser>, Status> {
-    let group_service = group_service as &GroupService;
-    Ok(Json(user.to_full_user(group_service).await))
+) -> Result<Json<Balance>, Problem> {
+    Ok(Json(group_service.get_balance_of_user(&user.id).await?))
+}
+
+/// export all personal data of the current user
+///
+/// Passwords, TOTP secrets and recovery codes are never exported.
+#[utoipa::path(
+    context_path = "/user",
+    tag = "user",
+    params(("format" = Option<String>, Query, description = "`json` (default) or `zip`")),
+    responses(
+        (status = 200, description = "the export as a file download", content(
+            ("application/json" = UserExport),
+            ("application/zip" = [u8])
+        )),
+        (status = 400, description = "the format is not supported", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[get("/export?<format>")]
+async fn export(
+    export_service: &State<Arc<ExportService>>,
+    user: User,
+    format: Option<&str>,
+) -> Result<ExportResponse, Problem> {
+    let zip = match format {
+        None | Some("json") => false,
+        Some("zip") => true,
+        Some(_) => {
+            return Err(invalid_field(
+                "format",
+                "unsupported",
+                "The format has to be either json or zip",
+            )
+            .into())
+        }
+    };
+
+    let export = export_service.export_user(user).await?;
+
+    if zip {
+        Ok(ExportResponse::Zip(export_service.to_zip(&export)))
+    } else {
+        Ok(ExportResponse::Json(
+            rocket::serde::json::to_string(&export).expect("error serializing export"),
+        ))
+    }
 }
 
+/// create a local user
+///
+/// Only allowed when local authentication is enabled and the registration policy permits it.
+#[utoipa::path(
+    context_path = "/user",
+    tag = "user",
+    request_body = UserCreationRequest,
+    responses(
+        (status = 200, description = "detailed information about the new user", body = FullUser),
+        (status = 202, description = "the user was created, but has to be approved by an administrator before logging in", body = FullUser),
+        (status = 400, description = "the request contains invalid fields (code: `validation_failed`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "registration is not allowed by the registration policy or the username is reserved for an administrator (code: `username_reserved`)", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "the username is already taken", body = Problem, content_type = "application/problem+json")
+    )
+)]
 #[post("/", data = "<user_creation_request>")]
 async fn create_user(
     user_service: &State<Arc<UserService>>,
     configuration_service: &State<Arc<ConfigurationService>>,
-    group_service: &State<GroupService>,
+    group_service: &State<Arc<GroupService>>,
+    invite_service: &State<Arc<InviteService>>,
     user_creation_request: Json<UserCreationRequest>,
-) -> Result<Json<FullUser>, Status> {
+) -> Result<status::Custom<Json<FullUser>>, Problem> {
     if configuration_service.auth_local().is_none() {
-        return Err(Status::Forbidden);
+        return Err(Problem::new(Status::Forbidden, "provider_disabled"));
+    }
+
+    let registration = configuration_service.registration();
+
+    if !registration.is_username_allowed(&user_creation_request.username) {
+        return Err(Problem::new(Status::Forbidden, "username_not_allowed"));
+    }
+
+    // admin rights are bound to the username, only the create-user command may claim them
+    if configuration_service.is_configured_admin(&user_creation_request.username) {
+        return Err(Problem::new(Status::Forbidden, "username_reserved"));
     }
 
-    let res = user_service
+    let policy = registration.policy;
+
+    let invite_code = match policy {
+        RegistrationPolicy::Closed => {
+            return Err(Problem::new(Status::Forbidden, "registration_closed"))
+        }
+        RegistrationPolicy::Invite => match &user_creation_request.invite_code {
+            Some(code) if invite_service.is_invite_code_valid(code).await? => Some(code),
+            _ => return Err(Problem::new(Status::Forbidden, "invalid_invite_code")),
+        },
+        RegistrationPolicy::Open | RegistrationPolicy::Approval => None,
+    };
+
+    let user = user_service
         .create_user(
             user_creation_request.username.to_owned(),
             user_creation_request.nickname.to_owned(),
             user_creation_request.password.to_owned(),
+            policy == RegistrationPolicy::Approval,
+            invite_code.map(String::as_str),
         )
-        .await;
+        .await?;
+
+    let status = if policy == RegistrationPolicy::Approval {
+        Status::Accepted
+    } else {
+        Status::Ok
+    };
+
+    Ok(status::Custom(
+        status,
+        Json(user.to_full_user(group_service).await?),
+    ))
+}
 
-    match res {
-        Ok(u) => Ok(Json(u.to_full_user(group_service).await)),
-        Err(_) => Err(Status::Conflict),
+/// update the authenticated user
+///
+/// The username can only be changed when local authentication is enabled.
+#[utoipa::path(
+    context_path = "/user",
+    tag = "user",
+    request_body = UserUpdateRequest,
+    responses(
+        (status = 200, description = "detailed information about the updated user", body = FullUser),
+        (status = 400, description = "the request contains invalid fields (code: `validation_failed`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "the username is managed by the proxy, not allowed by the registration settings (code: `username_not_allowed`) or reserved for an administrator (code: `username_reserved`)", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "the username is already taken", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[patch("/", data = "<user_update_request>")]
+async fn update_user(
+    user_service: &State<Arc<UserService>>,
+    configuration_service: &State<Arc<ConfigurationService>>,
+    group_service: &State<Arc<GroupService>>,
+    user: User,
+    user_update_request: Json<UserUpdateRequest>,
+) -> Result<Json<FullUser>, Problem> {
+    let user_update_request = user_update_request.into_inner();
+
+    // proxy users are identified by their username
+    if user_update_request.username.is_some() && configuration_service.auth_local().is_none() {
+        return Err(Problem::new(Status::Forbidden, "username_managed_by_proxy"));
     }
+
+    if let Some(username) = &user_update_request.username {
+        let username = username.trim();
+        if !configuration_service
+            .registration()
+            .is_username_allowed(username)
+        {
+            return Err(Problem::new(Status::Forbidden, "username_not_allowed"));
+        }
+        if username != user.username
+            && !user.is_admin
+            && configuration_service.is_configured_admin(username)
+        {
+            return Err(Problem::new(Status::Forbidden, "username_reserved"));
+        }
+    }
+
+    let user = user_service
+        .update_user(
+            &user.id,
+            user_update_request.username,
+            user_update_request.nickname,
+        )
+        .await?;
+
+    Ok(Json(user.to_full_user(group_service).await?))
 }
 
+/// delete the authenticated user
+///
+/// The user is anonymised, its transactions and debts are kept for the other group members.
+#[utoipa::path(
+    context_path = "/user",
+    tag = "user",
+    responses((status = 200, description = "the user was deleted")),
+    security(("bearerAuth" = []))
+)]
+#[delete("/")]
+async fn delete_user(
+    user_service: &State<Arc<UserService>>,
+    group_service: &State<Arc<GroupService>>,
+    user: User,
+) -> Result<(), Problem> {
+    user_service.delete_user(&user.id).await?;
+    group_service.revoke_access_of_user(&user.id);
+
+    Ok(())
+}
+
+/// authenticate with username and password
+///
+/// Deprecated, use `/auth/local` instead.
+#[utoipa::path(
+    context_path = "/user",
+    tag = "user",
+    request_body = UserAuthenticationRequest,
+    responses(
+        (status = 200, description = "the token or a totp challenge", body = LocalAuthenticationResponse),
+        (status = 401, description = "invalid username or password (code: `invalid_credentials`)", body = Problem, content_type = "application/problem+json"),
+        (status = 429, description = "too many failed login attempts, retry after the seconds in the `Retry-After` header", body = Problem, content_type = "application/problem+json")
+    )
+)]
 #[post("/token", data = "<user_authentication_request>")]
 async fn token(
     authentication_service: &State<Arc<AuthenticationService>>,
     user_authentication_request: Json<UserAuthenticationRequest>,
-) -> Result<Json<TokenResponse>, Status> {
+    client_ip: ClientIp,
+) -> Result<Json<LocalAuthenticationResponse>, AuthenticationError> {
     let res = authentication_service
         .authenticate_local(
             &user_authentication_request.username,
             &user_authentication_request.password,
+            client_ip.0,
         )
-        .await;
+        .await?;
+
+    Ok(Json(res.into()))
+}
 
-    match res {
-        Some(token) => Ok(Json(TokenResponse { token })),
-        None => Err(Status::Unauthorized),
+/// begin the totp enrolment
+///
+/// Generates a new secret, which is only activated after confirming a code.
+#[utoipa::path(
+    context_path = "/user",
+    tag = "user",
+    responses(
+        (status = 200, description = "the secret and an otpauth uri to be shown as qr code", body = TotpEnrolment),
+        (status = 409, description = "totp is already enabled", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[post("/totp")]
+async fn begin_totp_enrolment(
+    totp_service: &State<Arc<TotpService>>,
+    configuration_service: &State<Arc<ConfigurationService>>,
+    user: User,
+) -> Result<Json<TotpEnrolment>, Problem> {
+    if configuration_service.auth_local().is_none() {
+        return Err(Problem::new(Status::Forbidden, "provider_disabled"));
     }
+
+    Ok(Json(
+        totp_service
+            .begin_enrolment(&user.id, &user.username)
+            .await?,
+    ))
+}
+
+/// confirm the totp enrolment
+#[utoipa::path(
+    context_path = "/user",
+    tag = "user",
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 200, description = "totp is enabled, the recovery codes are only shown once", body = TotpRecoveryCodes),
+        (status = 403, description = "invalid code", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[post("/totp/confirm", data = "<totp_code_request>")]
+async fn confirm_totp_enrolment(
+    totp_service: &State<Arc<TotpService>>,
+    user: User,
+    totp_code_request: Json<TotpCodeRequest>,
+) -> Result<Json<TotpRecoveryCodes>, Problem> {
+    Ok(Json(
+        totp_service
+            .confirm_enrolment(&user.id, &totp_code_request.code)
+            .await?,
+    ))
+}
+
+/// disable totp
+#[utoipa::path(
+    context_path = "/user",
+    tag = "user",
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 200, description = "totp was disabled"),
+        (status = 403, description = "invalid code", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[delete("/totp", data = "<totp_code_request>")]
+async fn disable_totp(
+    totp_service: &State<Arc<TotpService>>,
+    user: User,
+    totp_code_request: Json<TotpCodeRequest>,
+) -> Result<(), Problem> {
+    Ok(totp_service
+        .disable(&user.id, &totp_code_request.code)
+        .await?)
 }
 
 pub fn routes() -> Vec<rocket::Route> {
-    routes![get_current_user, create_user, token]
+    routes![
+        get_current_user,
+        get_balance,
+        export,
+        create_user,
+        update_user,
+        delete_user,
+        token,
+        begin_totp_enrolment,
+        confirm_totp_enrolment,
+        disable_totp
+    ]
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use std::env;
 
     use crate::build_test_rocket;
-    use crate::routes::user::{FullUser, TokenResponse};
-    use rocket::http::Status;
+    use crate::routes::auth::{TokenResponse, TotpChallengeResponse, TotpRequest};
+    use crate::routes::user::FullUser;
+    use crate::services::error::ServiceError;
+    use crate::services::group::{Balance, GroupService};
+    use crate::services::user::{User, UserService};
+    use rocket::http::{ContentType, Header, Status};
     use rocket::local::blocking::Client;
+    use rocket::serde::json::{json, Value};
     use serial_test::serial;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    /// Creates a user like the `create-user` command, bypassing the registration policy
+    pub fn create_user_with_cli(client: &Client, username: &str) -> User {
+        let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+        futures::executor::block_on(user_service.create_user(
+            username.to_owned(),
+            username.to_uppercase(),
+            username.to_owned(),
+            false,
+            None,
+        ))
+        .expect("user to be created")
+    }
 
     pub fn create_user(client: &Client, username: &str) -> Result<FullUser, Status> {
         let user_creation_request = super::UserCreationRequest {
             username: username.to_owned(),
             nickname: username.to_uppercase(),
             password: username.to_owned(),
+            invite_code: None,
         };
         let request = client
-            .post("/user")
+            .post("/api/v1/user")
             .body(rocket::serde::json::to_string(&user_creation_request).expect(""));
         let response = request.clone().dispatch();
 
@@ -138,13 +508,12 @@ mod tests {
     }
 
     pub fn create_token(client: &Client, username: &str, password: &str) -> Result<String, Status> {
-        env::set_var("JWT_SECRET", "secret");
         let user_authentication_request = super::UserAuthenticationRequest {
             username: username.to_owned(),
             password: password.to_owned(),
         };
         let request = client
-            .post("/user/token")
+            .post("/api/v1/user/token")
             .body(rocket::serde::json::to_string(&user_authentication_request).expect(""));
         let response = request.clone().dispatch();
 
@@ -190,4 +559,595 @@ mod tests {
         let response = create_token(&client, "does-not-exist", "");
         assert!(response.is_err());
     }
+
+    #[test]
+    #[serial]
+    fn test_create_token_rate_limit() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        create_user(&client, "alice").expect("user to be created");
+        for _ in 0..5 {
+            let response = create_token(&client, "alice", "wrong-password");
+            assert_eq!(response, Err(Status::Unauthorized));
+        }
+
+        let request = super::UserAuthenticationRequest {
+            username: "alice".to_owned(),
+            password: "alice".to_owned(),
+        };
+        let response = client
+            .post("/api/v1/user/token")
+            .body(rocket::serde::json::to_string(&request).expect(""))
+            .dispatch();
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert!(response.headers().get_one("Retry-After").is_some());
+    }
+
+    #[test]
+    #[serial]
+    fn test_rate_limit_client_ip() {
+        let client = build_test_rocket_with(&[
+            (
+                "MONEYBALANCER_AUTH_LOCAL_RATE_LIMIT_MAX_ATTEMPTS_PER_USERNAME",
+                "100",
+            ),
+            (
+                "MONEYBALANCER_AUTH_LOCAL_RATE_LIMIT_MAX_ATTEMPTS_PER_IP",
+                "2",
+            ),
+            (
+                "MONEYBALANCER_AUTH_LOCAL_RATE_LIMIT_TRUSTED_PROXIES",
+                "10.0.0.1",
+            ),
+        ]);
+        create_user(&client, "alice").expect("user to be created");
+
+        let login = |remote: &str, real_ip: &str| {
+            client
+                .post("/api/v1/auth/local")
+                .remote(remote.parse::<SocketAddr>().unwrap())
+                .header(Header::new("X-Real-IP", real_ip.to_owned()))
+                .body(json!({ "username": "alice", "password": "wrong" }).to_string())
+                .dispatch()
+                .status()
+        };
+
+        // the header of other clients is ignored
+        assert_eq!(
+            login("192.0.2.1:1234", "198.51.100.1"),
+            Status::Unauthorized
+        );
+        assert_eq!(
+            login("192.0.2.1:1234", "198.51.100.2"),
+            Status::Unauthorized
+        );
+        assert_eq!(
+            login("192.0.2.1:1234", "198.51.100.3"),
+            Status::TooManyRequests
+        );
+
+        // the header of a trusted proxy is the ip of the client
+        assert_eq!(login("10.0.0.1:1234", "198.51.100.1"), Status::Unauthorized);
+        assert_eq!(login("10.0.0.1:1234", "198.51.100.2"), Status::Unauthorized);
+        assert_eq!(login("10.0.0.1:1234", "198.51.100.2"), Status::Unauthorized);
+        assert_eq!(
+            login("10.0.0.1:1234", "198.51.100.2"),
+            Status::TooManyRequests
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_totp() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        create_user(&client, "alice").expect("user to be created");
+        let token = create_token(&client, "alice", "alice").expect("token to be created");
+        let authorization = Header::new("Authorization", format!("Bearer {}", token));
+
+        let response = client
+            .post("/api/v1/user/totp")
+            .header(authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let enrolment: rocket::serde::json::Value = response.into_json().unwrap();
+        let totp =
+            totp_rs::TOTP::from_url_unchecked(enrolment["provisioning_uri"].as_str().unwrap())
+                .unwrap();
+
+        let code_request = |code: &str| {
+            rocket::serde::json::to_string(&super::TotpCodeRequest {
+                code: code.to_owned(),
+            })
+            .unwrap()
+        };
+
+        let response = client
+            .post("/api/v1/user/totp/confirm")
+            .header(authorization.clone())
+            .body(code_request("000000x"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client
+            .post("/api/v1/user/totp/confirm")
+            .header(authorization)
+            .body(code_request(&totp.generate_current().unwrap()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let recovery_codes: rocket::serde::json::Value = response.into_json().unwrap();
+        let recovery_code = recovery_codes["recovery_codes"][0].as_str().unwrap();
+
+        // the password alone is not enough anymore
+        let request = super::UserAuthenticationRequest {
+            username: "alice".to_owned(),
+            password: "alice".to_owned(),
+        };
+        let totp_challenge = || {
+            client
+                .post("/api/v1/auth/local")
+                .body(rocket::serde::json::to_string(&request).unwrap())
+                .dispatch()
+                .into_json::<TotpChallengeResponse>()
+                .unwrap()
+                .totp_challenge
+        };
+        let exchange = |totp_challenge: String, code: &str| {
+            client
+                .post("/api/v1/auth/local/totp")
+                .body(
+                    rocket::serde::json::to_string(&TotpRequest {
+                        totp_challenge,
+                        code: code.to_owned(),
+                    })
+                    .unwrap(),
+                )
+                .dispatch()
+        };
+
+        let response = exchange(totp_challenge(), "000000x");
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = exchange(totp_challenge(), &totp.generate_current().unwrap());
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.into_json::<TokenResponse>().is_some());
+
+        // the challenge itself must not be accepted as a token
+        let response = client
+            .get("/api/v1/user")
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", totp_challenge()),
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        // recovery codes work exactly once
+        let response = exchange(totp_challenge(), recovery_code);
+        assert_eq!(response.status(), Status::Ok);
+        let response = exchange(totp_challenge(), recovery_code);
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    #[serial]
+    fn test_update_and_delete_user() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        create_user(&client, "alice").expect("user to be created");
+        create_user(&client, "bob").expect("user to be created");
+        let token = create_token(&client, "alice", "alice").expect("token to be created");
+        let authorization = Header::new("Authorization", format!("Bearer {}", token));
+
+        let update = |username: Option<&str>, nickname: Option<&str>| {
+            client
+                .patch("/api/v1/user")
+                .header(authorization.clone())
+                .body(
+                    rocket::serde::json::to_string(&super::UserUpdateRequest {
+                        username: username.map(str::to_owned),
+                        nickname: nickname.map(str::to_owned),
+                    })
+                    .unwrap(),
+                )
+                .dispatch()
+        };
+
+        let response = update(None, Some("Alice"));
+        assert_eq!(response.status(), Status::Ok);
+        let user = response.into_json::<FullUser>().unwrap();
+        assert_eq!(user.nickname, "Alice");
+        assert_eq!(user.username, "alice");
+
+        // nothing to change
+        let response = update(None, None);
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_json::<FullUser>().unwrap().nickname, "Alice");
+
+        assert_eq!(update(Some("bob"), None).status(), Status::Conflict);
+        assert_eq!(update(None, Some(" ")).status(), Status::BadRequest);
+
+        let response = update(Some("alice2"), None);
+        assert_eq!(response.status(), Status::Ok);
+        assert!(create_token(&client, "alice2", "alice").is_ok());
+
+        let response = client
+            .delete("/api/v1/user")
+            .header(authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .get("/api/v1/user")
+            .header(authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert!(create_token(&client, "alice2", "alice").is_err());
+
+        // the username can be used again
+        assert!(create_user(&client, "alice2").is_ok());
+    }
+
+    fn create_user_with_invite_code(
+        client: &Client,
+        username: &str,
+        invite_code: Option<&str>,
+    ) -> Status {
+        let user_creation_request = super::UserCreationRequest {
+            username: username.to_owned(),
+            nickname: username.to_uppercase(),
+            password: username.to_owned(),
+            invite_code: invite_code.map(str::to_owned),
+        };
+        client
+            .post("/api/v1/user")
+            .body(rocket::serde::json::to_string(&user_creation_request).unwrap())
+            .dispatch()
+            .status()
+    }
+
+    fn build_test_rocket_with(variables: &[(&str, &str)]) -> Client {
+        for (key, value) in variables {
+            env::set_var(key, value);
+        }
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+        for (key, _) in variables {
+            env::remove_var(key);
+        }
+        client
+    }
+
+    #[test]
+    #[serial]
+    fn test_rename_with_allowed_domains() {
+        let client = build_test_rocket_with(&[(
+            "MONEYBALANCER_REGISTRATION_ALLOWED_DOMAINS",
+            "example.com",
+        )]);
+        create_user(&client, "alice@example.com").expect("user to be created");
+        let token = create_token(&client, "alice@example.com", "alice@example.com").unwrap();
+
+        let rename = |username: &str| {
+            client
+                .patch("/api/v1/user")
+                .header(Header::new("Authorization", format!("Bearer {}", token)))
+                .body(json!({ "username": username }).to_string())
+                .dispatch()
+                .status()
+        };
+        assert_eq!(rename("alice@example.org"), Status::Forbidden);
+        assert_eq!(rename("alice"), Status::Forbidden);
+        assert_eq!(rename("alice2@example.com"), Status::Ok);
+    }
+
+    #[test]
+    #[serial]
+    fn test_registration_closed() {
+        let client = build_test_rocket_with(&[("MONEYBALANCER_REGISTRATION_POLICY", "closed")]);
+        assert_eq!(
+            create_user(&client, "alice").unwrap_err(),
+            Status::Forbidden
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_registration_invite() {
+        let client = build_test_rocket_with(&[
+            ("MONEYBALANCER_REGISTRATION_POLICY", "invite"),
+            ("MONEYBALANCER_ADMIN_USERNAMES", "admin"),
+        ]);
+
+        assert_eq!(
+            create_user(&client, "alice").unwrap_err(),
+            Status::Forbidden
+        );
+
+        // configured admins are subject to the policy as well
+        assert_eq!(
+            create_user(&client, "admin").unwrap_err(),
+            Status::Forbidden
+        );
+        assert!(create_user_with_cli(&client, "admin").is_admin);
+        let token = create_token(&client, "admin", "admin").unwrap();
+        let invite_code: rocket::serde::json::Value = client
+            .post("/api/v1/admin/invite")
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch()
+            .into_json()
+            .unwrap();
+        let invite_code = invite_code["code"].as_str().unwrap();
+
+        assert_eq!(
+            create_user_with_invite_code(&client, "alice", Some("invalid")),
+            Status::Forbidden
+        );
+        assert_eq!(
+            create_user_with_invite_code(&client, "alice", Some(invite_code)),
+            Status::Ok
+        );
+        assert_eq!(
+            create_user_with_invite_code(&client, "bob", Some(invite_code)),
+            Status::Forbidden
+        );
+
+        // a code redeemed after it was checked does not leave a user behind
+        let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+        assert!(matches!(
+            futures::executor::block_on(user_service.create_user(
+                "bob".to_owned(),
+                "BOB".to_owned(),
+                "bob".to_owned(),
+                false,
+                Some(invite_code),
+            )),
+            Err(ServiceError::Forbidden("invalid_invite_code"))
+        ));
+        assert!(
+            futures::executor::block_on(user_service.get_user_by_username("bob"))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_registration_approval() {
+        let client = build_test_rocket_with(&[
+            ("MONEYBALANCER_REGISTRATION_POLICY", "approval"),
+            ("MONEYBALANCER_REGISTRATION_ALLOWED_DOMAINS", "example.com"),
+        ]);
+
+        assert_eq!(
+            create_user_with_invite_code(&client, "alice", None),
+            Status::Forbidden
+        );
+        assert_eq!(
+            create_user_with_invite_code(&client, "alice@example.org", None),
+            Status::Forbidden
+        );
+        assert_eq!(
+            create_user_with_invite_code(&client, "alice@Example.com", None),
+            Status::Accepted
+        );
+
+        // not approved yet
+        assert_eq!(
+            create_token(&client, "alice@Example.com", "alice@Example.com"),
+            Err(Status::Unauthorized)
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_balance() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        let mut users = Vec::new();
+        for username in ["alice", "bob", "carol"] {
+            let user = create_user(&client, username).expect("user to be created");
+            let token = create_token(&client, username, username).expect("token to be created");
+            users.push((
+                user.id,
+                Header::new("Authorization", format!("Bearer {}", token)),
+            ));
+        }
+        let (alice, bob, carol) = (&users[0], &users[1], &users[2]);
+
+        let create_group = |name: &str, members: &[&(String, Header<'static>)]| -> String {
+            let group = client
+                .post("/api/v1/group")
+                .header(members[0].1.clone())
+                .body(format!("{{\"name\":\"{}\"}}", name))
+                .dispatch()
+                .into_json::<Value>()
+                .unwrap();
+            let group_id = group["id"].as_str().unwrap().to_owned();
+            for member in &members[1..] {
+                client
+                    .post(format!("/api/v1/group/{}/member", group_id))
+                    .header(member.1.clone())
+                    .dispatch();
+            }
+            group_id
+        };
+
+        let create_transaction =
+            |group_id: &str, creditor: &(String, Header<'static>), debtors: &[&String], amount| {
+                let response = client
+                    .post(format!("/api/v1/group/{}/transaction", group_id))
+                    .header(creditor.1.clone())
+                    .body(
+                        json!({
+                            "debtor_ids": debtors,
+                            "amount": amount,
+                            "description": "test",
+                        })
+                        .to_string(),
+                    )
+                    .dispatch();
+                assert_eq!(response.status(), Status::Ok);
+            };
+
+        let group_1 = create_group("1", &[alice, bob, carol]);
+        let group_2 = create_group("2", &[alice, bob]);
+        let group_3 = create_group("3", &[alice]);
+
+        // alice pays 30 for everyone, bob pays 50 for alice
+        create_transaction(&group_1, alice, &[&alice.0, &bob.0, &carol.0], 30);
+        create_transaction(&group_2, bob, &[&alice.0], 50);
+
+        let balance = client
+            .get("/api/v1/user/balance")
+            .header(alice.1.clone())
+            .dispatch()
+            .into_json::<Balance>()
+            .unwrap();
+
+        assert_eq!(balance.amount, -30);
+        assert_eq!(
+            balance
+                .groups
+                .iter()
+                .map(|g| (g.group_id.as_str(), g.amount))
+                .collect::<Vec<(&str, i64)>>(),
+            vec![
+                (group_1.as_str(), 20),
+                (group_2.as_str(), -50),
+                (group_3.as_str(), 0)
+            ]
+        );
+
+        let mut expected_users = vec![(bob.0.as_str(), -40), (carol.0.as_str(), 10)];
+        expected_users.sort();
+        assert_eq!(
+            balance
+                .users
+                .iter()
+                .map(|u| (u.user_id.as_str(), u.amount))
+                .collect::<Vec<(&str, i64)>>(),
+            expected_users
+        );
+
+        // the open balance in a group alice was removed from is left out everywhere
+        let group_service = client.rocket().state::<Arc<GroupService>>().unwrap();
+        let sync_groups = |group_names: Vec<String>| {
+            futures::executor::block_on(group_service.sync_external_groups_of_user(
+                &alice.0,
+                "proxy",
+                group_names,
+                true,
+            ))
+            .unwrap()
+        };
+        sync_groups(vec!["4".to_owned()]);
+        let group_4 =
+            futures::executor::block_on(group_service.get_groups_of_user(alice.0.to_owned()))
+                .unwrap()
+                .into_iter()
+                .find(|group| group.name == "4")
+                .unwrap()
+                .id;
+        client
+            .post(format!("/api/v1/group/{}/member", group_4))
+            .header(carol.1.clone())
+            .dispatch();
+        create_transaction(&group_4, carol, &[&alice.0], 15);
+        sync_groups(vec![]);
+
+        let balance = client
+            .get("/api/v1/user/balance")
+            .header(alice.1.clone())
+            .dispatch()
+            .into_json::<Balance>()
+            .unwrap();
+        assert_eq!(balance.amount, -30);
+        assert_eq!(balance.groups.len(), 3);
+        assert_eq!(
+            balance.groups.iter().map(|g| g.amount).sum::<i64>(),
+            balance.amount
+        );
+        assert_eq!(
+            balance
+                .users
+                .iter()
+                .map(|u| (u.user_id.as_str(), u.amount))
+                .collect::<Vec<(&str, i64)>>(),
+            expected_users
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_export() {
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+
+        let alice = create_user(&client, "alice").expect("user to be created");
+        let token = create_token(&client, "alice", "alice").expect("token to be created");
+        let authorization = Header::new("Authorization", format!("Bearer {}", token));
+
+        let group = client
+            .post("/api/v1/group")
+            .header(authorization.clone())
+            .body(json!({ "name": "group" }).to_string())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        let response = client
+            .post(format!(
+                "/api/v1/group/{}/transaction",
+                group["id"].as_str().unwrap()
+            ))
+            .header(authorization.clone())
+            .body(
+                json!({
+                    "debtor_ids": [alice.id],
+                    "amount": 42,
+                    "description": "bread",
+                })
+                .to_string(),
+            )
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .get("/api/v1/user/export")
+            .header(authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Content-Disposition"),
+            Some("attachment; filename=\"money-balancer-export.json\"")
+        );
+        let export = response.into_json::<Value>().unwrap();
+        assert_eq!(export["profile"]["username"], "alice");
+        assert!(export["profile"].get("password").is_none());
+        assert_eq!(export["memberships"].as_array().unwrap().len(), 1);
+        assert_eq!(export["transactions"].as_array().unwrap().len(), 1);
+        // alice paid and owes on the same transaction
+        assert_eq!(export["activity"].as_array().unwrap().len(), 2);
+
+        let response = client
+            .get("/api/v1/user/export?format=zip")
+            .header(authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::ZIP));
+        let mut archive =
+            zip::ZipArchive::new(std::io::Cursor::new(response.into_bytes().unwrap())).unwrap();
+        let mut json = String::new();
+        std::io::Read::read_to_string(
+            &mut archive.by_name("money-balancer-export.json").unwrap(),
+            &mut json,
+        )
+        .unwrap();
+        let zipped_export: Value = rocket::serde::json::from_str(&json).unwrap();
+        assert_eq!(zipped_export["transactions"], export["transactions"]);
+
+        let response = client
+            .get("/api/v1/user/export?format=xml")
+            .header(authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
 }
@@ -0,0 +1,542 @@
+use crate::guards::idempotency::{Idempotency, IdempotentResponse};
+use crate::routes::problem::Problem;
+use crate::services::group::GroupService;
+use crate::services::user::User;
+use crate::services::webhook::{Webhook, WebhookDelivery, WebhookService};
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::*;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct WebhookCreationRequest {
+    /// receives the events as `POST` requests with a json body
+    #[schema(example = "https://chat.example.com/hooks/money")]
+    url: String,
+    /// `transaction.created`, `transaction.deleted`, `member.added` or `member.removed`
+    #[schema(example = json!(["transaction.created", "transaction.deleted"]))]
+    events: Vec<String>,
+    /// key of the HMAC-SHA256 signature in the `X-MoneyBalancer-Signature` header
+    secret: String,
+}
+
+/// get all webhooks of a group
+///
+/// Only owners of the group can manage its webhooks.
+#[utoipa::path(
+    context_path = "/group",
+    tag = "webhook",
+    params(("group_id" = String, Path, description = "id of the group")),
+    responses(
+        (status = 200, description = "all webhooks of the group", body = [Webhook]),
+        (status = 403, description = "the user is not an owner of the group (code: `not_an_owner`)", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "the group does not exist or the user is not a member", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[get("/<group_id>/webhook")]
+async fn get_group_webhooks(
+    group_id: String,
+    group_service: &State<Arc<GroupService>>,
+    webhook_service: &State<Arc<WebhookService>>,
+    user: User,
+) -> Result<Json<Vec<Webhook>>, Problem> {
+    group_service
+        .ensure_user_is_owner_of_group(&group_id, &user.id)
+        .await?;
+
+    Ok(Json(
+        webhook_service.get_webhooks_of_group(&group_id).await?,
+    ))
+}
+
+/// create a webhook in a group
+///
+/// The events are delivered asynchronously and retried with an increasing delay
+/// until the receiver responds with a 2xx status code.
+#[utoipa::path(
+    context_path = "/group",
+    tag = "webhook",
+    params(
+        ("group_id" = String, Path, description = "id of the group"),
+        ("Idempotency-Key" = Option<String>, Header, description = "replays the response of an earlier request with the same key")
+    ),
+    request_body = WebhookCreationRequest,
+    responses(
+        (status = 200, description = "the new webhook", body = Webhook),
+        (status = 400, description = "the request contains invalid fields (code: `validation_failed`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "the user is not an owner of the group (code: `not_an_owner`)", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "the group does not exist or the user is not a member", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "the group has too many webhooks (code: `too_many_webhooks`)", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[post("/<group_id>/webhook", data = "<webhook_creation_request>")]
+async fn create_group_webhook(
+    group_id: String,
+    webhook_creation_request: Json<WebhookCreationRequest>,
+    group_service: &State<Arc<GroupService>>,
+    webhook_service: &State<Arc<WebhookService>>,
+    user: User,
+    idempotency: Idempotency,
+) -> Result<IdempotentResponse, Problem> {
+    group_service
+        .ensure_user_is_owner_of_group(&group_id, &user.id)
+        .await?;
+
+    let request = webhook_creation_request.into_inner();
+
+    idempotency
+        .run(&user.id, &request, async {
+            Ok(Json(
+                webhook_service
+                    .create_webhook(
+                        &group_id,
+                        request.url.to_owned(),
+                        request.events.to_owned(),
+                        request.secret.to_owned(),
+                    )
+                    .await?,
+            ))
+        })
+        .await
+}
+
+/// delete a webhook of a group
+#[utoipa::path(
+    context_path = "/group",
+    tag = "webhook",
+    params(
+        ("group_id" = String, Path, description = "id of the group"),
+        ("webhook_id" = String, Path, description = "id of the webhook"),
+        ("Idempotency-Key" = Option<String>, Header, description = "replays the response of an earlier request with the same key")
+    ),
+    responses(
+        (status = 200, description = "the webhook and its deliveries were deleted"),
+        (status = 403, description = "the user is not an owner of the group (code: `not_an_owner`)", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "the group or the webhook does not exist", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[delete("/<group_id>/webhook/<webhook_id>")]
+async fn delete_group_webhook(
+    group_id: String,
+    webhook_id: String,
+    group_service: &State<Arc<GroupService>>,
+    webhook_service: &State<Arc<WebhookService>>,
+    user: User,
+    idempotency: Idempotency,
+) -> Result<IdempotentResponse, Problem> {
+    group_service
+        .ensure_user_is_owner_of_group(&group_id, &user.id)
+        .await?;
+
+    idempotency
+        .run(&user.id, &(), async {
+            Ok(webhook_service
+                .delete_webhook(&group_id, &webhook_id)
+                .await?)
+        })
+        .await
+}
+
+/// get the latest deliveries of a webhook
+#[utoipa::path(
+    context_path = "/group",
+    tag = "webhook",
+    params(
+        ("group_id" = String, Path, description = "id of the group"),
+        ("webhook_id" = String, Path, description = "id of the webhook")
+    ),
+    responses(
+        (status = 200, description = "the latest deliveries first", body = [WebhookDelivery]),
+        (status = 403, description = "the user is not an owner of the group (code: `not_an_owner`)", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "the group or the webhook does not exist", body = Problem, content_type = "application/problem+json")
+    ),
+    security(("bearerAuth" = []))
+)]
+#[get("/<group_id>/webhook/<webhook_id>/delivery")]
+async fn get_group_webhook_deliveries(
+    group_id: String,
+    webhook_id: String,
+    group_service: &State<Arc<GroupService>>,
+    webhook_service: &State<Arc<WebhookService>>,
+    user: User,
+) -> Result<Json<Vec<WebhookDelivery>>, Problem> {
+    group_service
+        .ensure_user_is_owner_of_group(&group_id, &user.id)
+        .await?;
+
+    Ok(Json(
+        webhook_service
+            .get_deliveries_of_webhook(&group_id, &webhook_id)
+            .await?,
+    ))
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![
+        get_group_webhooks,
+        create_group_webhook,
+        delete_group_webhook,
+        get_group_webhook_deliveries
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::build_test_rocket;
+    use crate::routes::problem::Problem;
+    use crate::routes::user::tests::{create_token, create_user};
+    use crate::services::webhook::sign;
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::{self, json, Value};
+    use serial_test::serial;
+    use std::collections::HashMap;
+    use std::env;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    struct ReceivedRequest {
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    fn build_webhook_client() -> Client {
+        env::set_var("MONEYBALANCER_WEBHOOKS_ENABLED", "true");
+        env::set_var("MONEYBALANCER_WEBHOOKS_MAX_ATTEMPTS", "3");
+        env::set_var("MONEYBALANCER_WEBHOOKS_RETRY_DELAY_SECONDS", "0");
+        // the receivers of the tests listen on the loopback interface
+        env::set_var("MONEYBALANCER_WEBHOOKS_ALLOWED_NETWORKS", "127.0.0.1");
+        let client = Client::tracked(build_test_rocket()).expect("valid rocket instance");
+        env::remove_var("MONEYBALANCER_WEBHOOKS_ENABLED");
+        env::remove_var("MONEYBALANCER_WEBHOOKS_MAX_ATTEMPTS");
+        env::remove_var("MONEYBALANCER_WEBHOOKS_RETRY_DELAY_SECONDS");
+        env::remove_var("MONEYBALANCER_WEBHOOKS_ALLOWED_NETWORKS");
+        client
+    }
+
+    /// Answers the requests with the given status codes in turn and passes them on
+    fn start_receiver(statuses: Vec<u16>) -> (String, mpsc::Receiver<ReceivedRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+
+                let mut headers = HashMap::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        headers.insert(name.to_lowercase(), value.trim().to_owned());
+                    }
+                }
+                let length = headers["content-length"].parse::<usize>().unwrap();
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {} Status\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+
+                let _ = sender.send(ReceivedRequest {
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                });
+            }
+        });
+
+        (url, receiver)
+    }
+
+    fn wait_for_deliveries(
+        client: &Client,
+        authorization: &Header<'static>,
+        group_id: &str,
+        webhook_id: &str,
+        count: usize,
+    ) -> Vec<Value> {
+        let started = Instant::now();
+        loop {
+            let deliveries = client
+                .get(format!(
+                    "/api/v1/group/{}/webhook/{}/delivery",
+                    group_id, webhook_id
+                ))
+                .header(authorization.clone())
+                .dispatch()
+                .into_json::<Vec<Value>>()
+                .unwrap();
+            let finished = deliveries
+                .iter()
+                .filter(|delivery| delivery["status"] != "pending")
+                .count();
+
+            if finished >= count {
+                return deliveries;
+            }
+            assert!(started.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_webhooks() {
+        let client = build_webhook_client();
+
+        create_user(&client, "alice").unwrap();
+        let bob = create_user(&client, "bob").unwrap().id;
+        let alice_authorization = Header::new(
+            "Authorization",
+            format!(
+                "Bearer {}",
+                create_token(&client, "alice", "alice").unwrap()
+            ),
+        );
+        let bob_authorization = Header::new(
+            "Authorization",
+            format!("Bearer {}", create_token(&client, "bob", "bob").unwrap()),
+        );
+
+        let group_id = client
+            .post("/api/v1/group")
+            .header(alice_authorization.clone())
+            .body(json!({ "name": "Trip" }).to_string())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        let webhooks_uri = format!("/api/v1/group/{}/webhook", group_id);
+
+        let problem = client
+            .post(&webhooks_uri)
+            .header(alice_authorization.clone())
+            .body(
+                json!({ "url": "ftp://example.com", "events": ["unknown"], "secret": "" })
+                    .to_string(),
+            )
+            .dispatch()
+            .into_json::<Problem>()
+            .unwrap();
+        let errors = problem
+            .errors
+            .iter()
+            .map(|error| (error.field.as_str(), error.code.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(
+            errors,
+            vec![
+                ("url", "invalid"),
+                ("events", "unknown"),
+                ("secret", "empty")
+            ]
+        );
+
+        for url in [
+            "http://169.254.169.254/latest/meta-data",
+            "http://10.0.0.1:8080/hook",
+            "https://[::1]/hook",
+            "http://127.0.0.2/hook",
+        ] {
+            let problem = client
+                .post(&webhooks_uri)
+                .header(alice_authorization.clone())
+                .body(
+                    json!({ "url": url, "events": ["member.added"], "secret": "secret" })
+                        .to_string(),
+                )
+                .dispatch()
+                .into_json::<Problem>()
+                .unwrap();
+            assert_eq!(problem.errors[0].field, "url");
+            assert_eq!(problem.errors[0].code, "not_allowed");
+        }
+
+        // the first attempt fails and is retried
+        let (url, requests) = start_receiver(vec![500, 200, 200]);
+        let webhook = client
+            .post(&webhooks_uri)
+            .header(alice_authorization.clone())
+            .body(
+                json!({
+                    "url": url,
+                    "events": ["member.added", "transaction.created"],
+                    "secret": "secret",
+                })
+                .to_string(),
+            )
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        assert!(webhook.get("secret").is_none());
+        let webhook_id = webhook["id"].as_str().unwrap();
+
+        let response = client
+            .get(&webhooks_uri)
+            .header(bob_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client
+            .post(format!("/api/v1/group/{}/member", group_id))
+            .header(bob_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .get(&webhooks_uri)
+            .header(bob_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        assert_eq!(
+            response.into_json::<Problem>().unwrap().code,
+            "not_an_owner"
+        );
+
+        for _ in 0..2 {
+            let request = requests.recv_timeout(Duration::from_secs(10)).unwrap();
+            assert_eq!(request.headers["x-moneybalancer-event"], "member.added");
+        }
+        let delivery =
+            wait_for_deliveries(&client, &alice_authorization, &group_id, webhook_id, 1).remove(0);
+        assert_eq!(delivery["event"], "member.added");
+        assert_eq!(delivery["status"], "delivered");
+        assert_eq!(delivery["attempts"], 2);
+        assert_eq!(delivery["response_status"], 200);
+
+        let transaction = client
+            .post(format!("/api/v1/group/{}/transaction", group_id))
+            .header(alice_authorization.clone())
+            .body(json!({ "debtor_ids": [bob], "amount": 10, "description": "Bread" }).to_string())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+
+        let request = requests.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(
+            request.headers["x-moneybalancer-event"],
+            "transaction.created"
+        );
+        assert_eq!(
+            request.headers["x-moneybalancer-signature"],
+            format!("sha256={}", sign("secret", &request.body))
+        );
+        let payload = json::from_str::<Value>(&request.body).unwrap();
+        assert_eq!(payload["id"], request.headers["x-moneybalancer-delivery"]);
+        assert_eq!(payload["event"], "transaction.created");
+        assert_eq!(payload["group_id"], group_id);
+        assert_eq!(payload["data"], transaction);
+
+        // not subscribed
+        client
+            .delete(format!(
+                "/api/v1/group/{}/transaction/{}",
+                group_id,
+                transaction["id"].as_str().unwrap()
+            ))
+            .header(alice_authorization.clone())
+            .dispatch();
+
+        let deliveries =
+            wait_for_deliveries(&client, &alice_authorization, &group_id, webhook_id, 2);
+        let events = deliveries
+            .iter()
+            .map(|delivery| delivery["event"].as_str().unwrap())
+            .collect::<Vec<&str>>();
+        assert_eq!(events, vec!["transaction.created", "member.added"]);
+
+        let response = client
+            .delete(format!("{}/{}", webhooks_uri, webhook_id))
+            .header(alice_authorization.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let webhooks = client
+            .get(&webhooks_uri)
+            .header(alice_authorization.clone())
+            .dispatch()
+            .into_json::<Vec<Value>>()
+            .unwrap();
+        assert!(webhooks.is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn test_webhook_delivery_failure() {
+        let client = build_webhook_client();
+
+        let alice = create_user(&client, "alice").unwrap().id;
+        let authorization = Header::new(
+            "Authorization",
+            format!(
+                "Bearer {}",
+                create_token(&client, "alice", "alice").unwrap()
+            ),
+        );
+        let group_id = client
+            .post("/api/v1/group")
+            .header(authorization.clone())
+            .body(json!({ "name": "Trip" }).to_string())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+
+        // nothing listens on the port anymore
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/hook", listener.local_addr().unwrap())
+        };
+        let webhook = client
+            .post(format!("/api/v1/group/{}/webhook", group_id))
+            .header(authorization.clone())
+            .body(
+                json!({ "url": url, "events": ["transaction.created"], "secret": "secret" })
+                    .to_string(),
+            )
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+
+        client
+            .post(format!("/api/v1/group/{}/transaction", group_id))
+            .header(authorization.clone())
+            .body(
+                json!({ "debtor_ids": [alice], "amount": 10, "description": "Bread" }).to_string(),
+            )
+            .dispatch();
+
+        let delivery = wait_for_deliveries(
+            &client,
+            &authorization,
+            &group_id,
+            webhook["id"].as_str().unwrap(),
+            1,
+        )
+        .remove(0);
+        assert_eq!(delivery["status"], "failed");
+        assert_eq!(delivery["attempts"], 3);
+        assert_eq!(delivery["response_status"], Value::Null);
+        assert!(delivery["error"].is_string());
+    }
+}
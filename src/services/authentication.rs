@@ -1,22 +1,57 @@
 use jsonwebtoken::{Algorithm, DecodingKey, Validation};
 use rocket::serde::{Deserialize, Serialize};
-use std::{collections::HashMap, process, sync::Arc};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use super::{
-    configuration::ConfigurationService,
+    configuration::{ConfigurationService, ProxyAuthConfig},
+    error::ServiceError,
+    group::GroupService,
+    metrics::MetricsService,
+    rate_limit::LoginRateLimiter,
+    totp::TotpService,
     user::{User, UserService},
 };
 
+const JWT_ISSUER: &str = "de:itsblue:money-balancer";
+const TOTP_CHALLENGE_ISSUER: &str = "de:itsblue:money-balancer:totp-challenge";
+const TOTP_CHALLENGE_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
 #[derive(Debug)]
 pub struct AuthenticationService {
     configuration_service: Arc<ConfigurationService>,
     user_service: Arc<UserService>,
+    group_service: Arc<GroupService>,
+    totp_service: Arc<TotpService>,
+    metrics_service: Arc<MetricsService>,
+    login_rate_limiter: LoginRateLimiter,
+}
+
+pub enum LocalAuthentication {
+    Token(String),
+    /// the password was correct, but a totp code is required as well
+    TotpChallenge(String),
 }
 
 #[derive(Debug)]
 pub enum AuthenticationError {
     InvalidToken,
     MissingToken,
+    NotAnAdmin,
+    ProviderDisabled,
+    InvalidCredentials,
+    TooManyAttempts(Duration),
+    Service(ServiceError),
+}
+
+impl From<ServiceError> for AuthenticationError {
+    fn from(err: ServiceError) -> Self {
+        AuthenticationError::Service(err)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -30,18 +65,18 @@ impl AuthenticationService {
     pub fn new(
         configuration_service: Arc<ConfigurationService>,
         user_service: Arc<UserService>,
+        group_service: Arc<GroupService>,
+        totp_service: Arc<TotpService>,
+        metrics_service: Arc<MetricsService>,
     ) -> AuthenticationService {
-        // make sure, we have at least one working provider
-        let new = AuthenticationService {
+        AuthenticationService {
+            login_rate_limiter: LoginRateLimiter::new(configuration_service.clone()),
             configuration_service: configuration_service,
             user_service: user_service,
-        };
-
-        if !new._config_valid() {
-            process::exit(1);
+            group_service,
+            totp_service,
+            metrics_service,
         }
-
-        new
     }
 
     pub fn local_enabled(&self) -> bool {
@@ -51,16 +86,98 @@ impl AuthenticationService {
         }
     }
 
-    pub async fn authenticate_local(&self, username: &str, password: &str) -> Option<String> {
-        self.configuration_service.auth_local()?;
+    pub async fn authenticate_local(
+        &self,
+        username: &str,
+        password: &str,
+        client_ip: Option<IpAddr>,
+    ) -> Result<LocalAuthentication, AuthenticationError> {
+        let result = self
+            ._authenticate_local(username, password, client_ip)
+            .await;
+        self._record_attempt("local", &result);
+        result
+    }
+
+    async fn _authenticate_local(
+        &self,
+        username: &str,
+        password: &str,
+        client_ip: Option<IpAddr>,
+    ) -> Result<LocalAuthentication, AuthenticationError> {
+        self.configuration_service
+            .auth_local()
+            .ok_or(AuthenticationError::ProviderDisabled)?;
 
-        Some(
-            self.generate_jwt(
-                self.user_service
-                    .check_username_and_password(username, password)
-                    .await?,
-            ),
-        )
+        // check before verifying the password, bcrypt is expensive
+        if let Some(retry_after) = self.login_rate_limiter.check(username, client_ip) {
+            return Err(AuthenticationError::TooManyAttempts(retry_after));
+        }
+
+        let user_id = self
+            .user_service
+            .check_username_and_password(username, password)
+            .await?;
+
+        let user_id = match user_id {
+            Some(user_id) => user_id,
+            None => {
+                self.login_rate_limiter.record_failure(username, client_ip);
+                return Err(AuthenticationError::InvalidCredentials);
+            }
+        };
+
+        if self.totp_service.is_enabled(&user_id).await? {
+            return Ok(LocalAuthentication::TotpChallenge(
+                self._generate_totp_challenge(user_id),
+            ));
+        }
+
+        self.login_rate_limiter.record_success(username);
+        Ok(LocalAuthentication::Token(self.generate_jwt(user_id)))
+    }
+
+    /// Exchanges a challenge issued by `authenticate_local` and a totp or recovery code for a token.
+    pub async fn authenticate_totp(
+        &self,
+        challenge: String,
+        code: &str,
+        client_ip: Option<IpAddr>,
+    ) -> Result<String, AuthenticationError> {
+        let result = self._authenticate_totp(challenge, code, client_ip).await;
+        self._record_attempt("totp", &result);
+        result
+    }
+
+    async fn _authenticate_totp(
+        &self,
+        challenge: String,
+        code: &str,
+        client_ip: Option<IpAddr>,
+    ) -> Result<String, AuthenticationError> {
+        self.configuration_service
+            .auth_local()
+            .ok_or(AuthenticationError::ProviderDisabled)?;
+
+        let user_id = self._validate_totp_challenge(challenge)?;
+        let user = self
+            .user_service
+            .get_user_by_id(user_id)
+            .await?
+            .ok_or(AuthenticationError::InvalidToken)?;
+
+        if let Some(retry_after) = self.login_rate_limiter.check(&user.username, client_ip) {
+            return Err(AuthenticationError::TooManyAttempts(retry_after));
+        }
+
+        if !self.totp_service.verify(&user.id, code).await? {
+            self.login_rate_limiter
+                .record_failure(&user.username, client_ip);
+            return Err(AuthenticationError::InvalidCredentials);
+        }
+
+        self.login_rate_limiter.record_success(&user.username);
+        Ok(self.generate_jwt(user.id))
     }
 
     pub fn proxy_enabled(&self) -> bool {
@@ -70,49 +187,168 @@ impl AuthenticationService {
         }
     }
 
-    pub async fn authenticate_proxy(&self, headers: HashMap<String, String>) -> Option<String> {
-        let config = self.configuration_service.auth_proxy()?;
+    pub fn forward_auth_enabled(&self) -> bool {
+        match self.configuration_service.auth_proxy() {
+            Some(c) => c.forward_auth,
+            None => false,
+        }
+    }
 
-        let username = headers.get(&config.headers_username.as_ref().unwrap().to_lowercase())?;
-        let mut nickname = username;
-        if config.headers_nickname.is_some() {
-            nickname = headers.get(&config.headers_nickname.as_ref().unwrap().to_lowercase())?;
+    pub async fn authenticate_proxy(
+        &self,
+        headers: HashMap<String, String>,
+        remote_ip: Option<IpAddr>,
+    ) -> Result<Option<String>, AuthenticationError> {
+        let user = self.authenticate_proxy_user(headers, remote_ip).await;
+        if self.proxy_enabled() {
+            self.metrics_service
+                .record_authentication("proxy", matches!(user, Ok(Some(_))));
         }
 
-        let user = self
+        Ok(user?.map(|user| self.generate_jwt(user.id)))
+    }
+
+    /// Authenticates a request by the headers set by the proxy, without a token
+    pub async fn authenticate_forward_auth(
+        &self,
+        headers: HashMap<String, String>,
+        remote_ip: Option<IpAddr>,
+    ) -> Result<Option<User>, AuthenticationError> {
+        if !self.forward_auth_enabled() {
+            return Ok(None);
+        }
+
+        let user = self.authenticate_proxy_user(headers, remote_ip).await;
+        // requests without proxy headers may still use a token, so they are no failed attempt
+        match &user {
+            Ok(Some(_)) => self
+                .metrics_service
+                .record_authentication("forward_auth", true),
+            Err(_) => self
+                .metrics_service
+                .record_authentication("forward_auth", false),
+            Ok(None) => {}
+        }
+
+        user
+    }
+
+    async fn authenticate_proxy_user(
+        &self,
+        headers: HashMap<String, String>,
+        remote_ip: Option<IpAddr>,
+    ) -> Result<Option<User>, AuthenticationError> {
+        let config = match self.configuration_service.auth_proxy() {
+            Some(config) => config,
+            None => return Ok(None),
+        };
+
+        if !self._is_trusted_proxy(config, &headers, remote_ip) {
+            return Ok(None);
+        }
+
+        let username = headers.get(&config.headers_username.as_ref().unwrap().to_lowercase());
+        let nickname = match &config.headers_nickname {
+            Some(header) => headers.get(&header.to_lowercase()),
+            None => username,
+        };
+
+        let (username, nickname) = match (username, nickname) {
+            (Some(username), Some(nickname)) => (username, nickname),
+            _ => return Ok(None),
+        };
+
+        let user = match self
             ._get_or_create_user(username.to_owned(), nickname.to_owned())
-            .await;
+            .await?
+        {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+
+        if let Some(header) = &config.groups.header {
+            // a missing header means the user is in no groups at all
+            let groups = headers
+                .get(&header.to_lowercase())
+                .map(|value| config.groups.parse_header(value))
+                .unwrap_or_default();
+
+            // the headers are sent with every request, so only changes are written
+            let joined_groups = self
+                .group_service
+                .get_external_group_names_of_user(&user.id, "proxy")
+                .await?;
+            let is_in_sync = groups.iter().all(|group| joined_groups.contains(group))
+                && (!config.groups.remove_stale || joined_groups.len() == groups.len());
 
-        Some(self.generate_jwt(user.id))
+            if !is_in_sync {
+                self.group_service
+                    .sync_external_groups_of_user(
+                        &user.id,
+                        "proxy",
+                        groups,
+                        config.groups.remove_stale,
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(Some(user))
     }
 
-    async fn _get_or_create_user(&self, username: String, nickname: String) -> User {
-        let user = self.user_service.get_user_by_username(&username).await;
+    async fn _get_or_create_user(
+        &self,
+        username: String,
+        nickname: String,
+    ) -> Result<Option<User>, ServiceError> {
+        let user = self.user_service.get_user_by_username(&username).await?;
         if let Some(u) = user {
-            return u.into();
+            // None if the user is disabled
+            return self.user_service.get_user_by_id(u.id).await;
         }
 
-        self.user_service
-            .create_user(username, nickname, uuid::Uuid::new_v4().to_string())
-            .await
-            .unwrap()
+        Ok(Some(
+            self.user_service
+                .create_user(
+                    username,
+                    nickname,
+                    uuid::Uuid::new_v4().to_string(),
+                    false,
+                    None,
+                )
+                .await?,
+        ))
     }
 
-    fn _config_valid(&self) -> bool {
-        if let Some(c) = self.configuration_service.auth_proxy() {
-            if c.headers_username.is_none() {
-                println!("Error: You have enabled proxy authentication but not specified the username header!");
-                return false;
+    fn _record_attempt<T>(&self, provider: &str, result: &Result<T, AuthenticationError>) {
+        match result {
+            Ok(_) => self.metrics_service.record_authentication(provider, true),
+            Err(AuthenticationError::ProviderDisabled) => {}
+            Err(_) => self.metrics_service.record_authentication(provider, false),
+        }
+    }
+
+    // the remote ip has to be the one of the connection, headers like X-Real-IP can be spoofed
+    fn _is_trusted_proxy(
+        &self,
+        config: &ProxyAuthConfig,
+        headers: &HashMap<String, String>,
+        remote_ip: Option<IpAddr>,
+    ) -> bool {
+        if let Some(trusted_proxies) = &config.trusted_proxies {
+            match remote_ip {
+                Some(ip) if trusted_proxies.contains(&ip) => {}
+                _ => return false,
             }
-            return true;
         }
 
-        if self.local_enabled() {
-            return true;
+        if let (Some(header), Some(secret)) = (&config.headers_secret, &config.secret) {
+            if headers.get(&header.to_lowercase()) != Some(secret) {
+                return false;
+            }
         }
 
-        println!("Error: at least one authentication provider must be enabled!");
-        false
+        true
     }
 
     pub async fn validate_jwt(
@@ -120,7 +356,7 @@ impl AuthenticationService {
         token: String,
     ) -> Result<JwtClaims, jsonwebtoken::errors::Error> {
         let mut validation = Validation::new(Algorithm::HS256);
-        validation.set_issuer(&["de:itsblue:money-balancer"]);
+        validation.set_issuer(&[JWT_ISSUER]);
         validation.validate_exp = false;
 
         let token = jsonwebtoken::decode::<JwtClaims>(
@@ -137,7 +373,7 @@ impl AuthenticationService {
             &jsonwebtoken::Header::default(),
             &JwtClaims {
                 id: user_id.to_owned(),
-                iss: "de:itsblue:money-balancer".to_string(),
+                iss: JWT_ISSUER.to_string(),
                 exp: 0,
             },
             &jsonwebtoken::EncodingKey::from_secret(
@@ -147,4 +383,36 @@ impl AuthenticationService {
 
         jwt.unwrap()
     }
+
+    fn _generate_totp_challenge(&self, user_id: String) -> String {
+        let expires_at = SystemTime::now() + TOTP_CHALLENGE_LIFETIME;
+
+        jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &JwtClaims {
+                id: user_id,
+                iss: TOTP_CHALLENGE_ISSUER.to_string(),
+                exp: expires_at.duration_since(UNIX_EPOCH).unwrap().as_secs() as usize,
+            },
+            &jsonwebtoken::EncodingKey::from_secret(
+                self.configuration_service.jwt_secret().as_bytes(),
+            ),
+        )
+        .unwrap()
+    }
+
+    fn _validate_totp_challenge(&self, challenge: String) -> Result<String, AuthenticationError> {
+        // the different issuer makes sure, challenges can't be used as tokens and vice versa
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[TOTP_CHALLENGE_ISSUER]);
+
+        let challenge = jsonwebtoken::decode::<JwtClaims>(
+            &challenge,
+            &DecodingKey::from_secret(self.configuration_service.jwt_secret().as_bytes()),
+            &validation,
+        )
+        .map_err(|_| AuthenticationError::InvalidToken)?;
+
+        Ok(challenge.claims.id)
+    }
 }
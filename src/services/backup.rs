@@ -0,0 +1,270 @@
+use crate::model;
+use migration::{Migrator, MigratorTrait};
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::*;
+use sea_orm_migration::seaql_migrations;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Increased whenever the layout of [`Backup`] itself changes
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+/// Rows are restored in batches to stay below the bind parameter limits of the backends
+const RESTORE_BATCH_SIZE: usize = 100;
+
+/// A dump of all tables, independent of the database backend it was created from.
+/// The tables are listed in an order which satisfies their foreign keys. Pending webhook
+/// deliveries are sent once the restored instance is started.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Backup {
+    pub format_version: u32,
+    /// The latest migration which was applied to the dumped database
+    pub migration: String,
+    pub created_at: u64,
+    pub users: Vec<model::user::Model>,
+    pub groups: Vec<model::group::Model>,
+    pub group_members: Vec<model::group_member::Model>,
+    pub transactions: Vec<model::transaction::Model>,
+    pub debts: Vec<model::debt::Model>,
+    pub user_totps: Vec<model::user_totp::Model>,
+    pub user_totp_recovery_codes: Vec<model::user_totp_recovery_code::Model>,
+    pub invite_codes: Vec<model::invite_code::Model>,
+    pub friends: Vec<model::friend::Model>,
+    pub friend_requests: Vec<model::friend_request::Model>,
+    pub direct_transactions: Vec<model::direct_transaction::Model>,
+    pub direct_debts: Vec<model::direct_debt::Model>,
+    pub group_webhooks: Vec<model::group_webhook::Model>,
+    pub group_webhook_deliveries: Vec<model::group_webhook_delivery::Model>,
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+    UnsupportedFormat(u32),
+    /// The backup was created at a different migration than this version expects
+    MigrationMismatch {
+        backup: String,
+        expected: String,
+    },
+    DatabaseNotEmpty,
+    Database(DbErr),
+}
+
+impl From<DbErr> for BackupError {
+    fn from(err: DbErr) -> Self {
+        BackupError::Database(err)
+    }
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::UnsupportedFormat(version) => {
+                write!(f, "the backup format version {} is not supported", version)
+            }
+            BackupError::MigrationMismatch { backup, expected } => write!(
+                f,
+                "the backup was created at migration {}, but this version of money-balancer expects {}. Restore it with the matching version and upgrade afterwards",
+                backup, expected
+            ),
+            BackupError::DatabaseNotEmpty => {
+                write!(f, "backups can only be restored into an empty database")
+            }
+            BackupError::Database(err) => write!(f, "database error: {}", err),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BackupService {
+    db: Arc<DatabaseConnection>,
+}
+
+impl BackupService {
+    pub fn new(db: Arc<DatabaseConnection>) -> BackupService {
+        BackupService { db }
+    }
+
+    /// Dumps all tables. They are read in a single transaction, so the backup is consistent
+    /// even while the server is running.
+    pub async fn create_backup(&self) -> Result<Backup, BackupError> {
+        let txn = self.db.begin().await?;
+
+        // postgres only sees the data committed before each statement by default
+        if txn.get_database_backend() == DbBackend::Postgres {
+            txn.execute(Statement::from_string(
+                DbBackend::Postgres,
+                "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY".to_owned(),
+            ))
+            .await?;
+        }
+
+        let migration = seaql_migrations::Entity::find()
+            .order_by_desc(seaql_migrations::Column::Version)
+            .one(&txn)
+            .await?
+            .map(|migration| migration.version)
+            .unwrap_or_default();
+
+        let backup = Backup {
+            format_version: BACKUP_FORMAT_VERSION,
+            migration,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            users: model::user::Entity::find()
+                .order_by_asc(model::user::Column::Id)
+                .all(&txn)
+                .await?,
+            groups: model::group::Entity::find()
+                .order_by_asc(model::group::Column::Id)
+                .all(&txn)
+                .await?,
+            group_members: model::group_member::Entity::find()
+                .order_by_asc(model::group_member::Column::GroupId)
+                .order_by_asc(model::group_member::Column::UserId)
+                .all(&txn)
+                .await?,
+            transactions: model::transaction::Entity::find()
+                .order_by_asc(model::transaction::Column::Id)
+                .all(&txn)
+                .await?,
+            debts: model::debt::Entity::find()
+                .order_by_asc(model::debt::Column::TransactionId)
+                .order_by_asc(model::debt::Column::DebtorId)
+                .all(&txn)
+                .await?,
+            user_totps: model::user_totp::Entity::find()
+                .order_by_asc(model::user_totp::Column::UserId)
+                .all(&txn)
+                .await?,
+            user_totp_recovery_codes: model::user_totp_recovery_code::Entity::find()
+                .order_by_asc(model::user_totp_recovery_code::Column::UserId)
+                .order_by_asc(model::user_totp_recovery_code::Column::CodeHash)
+                .all(&txn)
+                .await?,
+            invite_codes: model::invite_code::Entity::find()
+                .order_by_asc(model::invite_code::Column::Code)
+                .all(&txn)
+                .await?,
+            friends: model::friend::Entity::find()
+                .order_by_asc(model::friend::Column::UserId)
+                .order_by_asc(model::friend::Column::FriendId)
+                .all(&txn)
+                .await?,
+            friend_requests: model::friend_request::Entity::find()
+                .order_by_asc(model::friend_request::Column::UserId)
+                .order_by_asc(model::friend_request::Column::FriendId)
+                .all(&txn)
+                .await?,
+            direct_transactions: model::direct_transaction::Entity::find()
+                .order_by_asc(model::direct_transaction::Column::Id)
+                .all(&txn)
+                .await?,
+            direct_debts: model::direct_debt::Entity::find()
+                .order_by_asc(model::direct_debt::Column::TransactionId)
+                .order_by_asc(model::direct_debt::Column::DebtorId)
+                .all(&txn)
+                .await?,
+            group_webhooks: model::group_webhook::Entity::find()
+                .order_by_asc(model::group_webhook::Column::Id)
+                .all(&txn)
+                .await?,
+            group_webhook_deliveries: model::group_webhook_delivery::Entity::find()
+                .order_by_asc(model::group_webhook_delivery::Column::Id)
+                .all(&txn)
+                .await?,
+        };
+
+        txn.commit().await?;
+
+        Ok(backup)
+    }
+
+    /// Applies all migrations and inserts the backup. The database must not contain any users
+    /// or groups yet and the backup has to be created at the latest migration.
+    pub async fn restore_backup(&self, backup: Backup) -> Result<(), BackupError> {
+        if backup.format_version != BACKUP_FORMAT_VERSION {
+            return Err(BackupError::UnsupportedFormat(backup.format_version));
+        }
+
+        let expected = Migrator::migrations()
+            .last()
+            .map(|migration| migration.name().to_owned())
+            .unwrap_or_default();
+        if backup.migration != expected {
+            return Err(BackupError::MigrationMismatch {
+                backup: backup.migration,
+                expected,
+            });
+        }
+
+        Migrator::up(self.db.as_ref(), None).await?;
+
+        let txn = self.db.begin().await?;
+
+        if model::user::Entity::find().one(&txn).await?.is_some()
+            || model::group::Entity::find().one(&txn).await?.is_some()
+        {
+            return Err(BackupError::DatabaseNotEmpty);
+        }
+
+        Self::_insert_all::<_, model::user::ActiveModel>(&txn, backup.users).await?;
+        Self::_insert_all::<_, model::group::ActiveModel>(&txn, backup.groups).await?;
+        Self::_insert_all::<_, model::group_member::ActiveModel>(&txn, backup.group_members)
+            .await?;
+        Self::_insert_all::<_, model::transaction::ActiveModel>(&txn, backup.transactions).await?;
+        Self::_insert_all::<_, model::debt::ActiveModel>(&txn, backup.debts).await?;
+        Self::_insert_all::<_, model::user_totp::ActiveModel>(&txn, backup.user_totps).await?;
+        Self::_insert_all::<_, model::user_totp_recovery_code::ActiveModel>(
+            &txn,
+            backup.user_totp_recovery_codes,
+        )
+        .await?;
+        Self::_insert_all::<_, model::invite_code::ActiveModel>(&txn, backup.invite_codes).await?;
+        Self::_insert_all::<_, model::friend::ActiveModel>(&txn, backup.friends).await?;
+        Self::_insert_all::<_, model::friend_request::ActiveModel>(&txn, backup.friend_requests)
+            .await?;
+        Self::_insert_all::<_, model::direct_transaction::ActiveModel>(
+            &txn,
+            backup.direct_transactions,
+        )
+        .await?;
+        Self::_insert_all::<_, model::direct_debt::ActiveModel>(&txn, backup.direct_debts).await?;
+        Self::_insert_all::<_, model::group_webhook::ActiveModel>(&txn, backup.group_webhooks)
+            .await?;
+        Self::_insert_all::<_, model::group_webhook_delivery::ActiveModel>(
+            &txn,
+            backup.group_webhook_deliveries,
+        )
+        .await?;
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    async fn _insert_all<C, A>(
+        db: &C,
+        models: Vec<<A::Entity as EntityTrait>::Model>,
+    ) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait,
+        A: ActiveModelTrait + ActiveModelBehavior + Send,
+        <A::Entity as EntityTrait>::Model: IntoActiveModel<A>,
+    {
+        for batch in models.chunks(RESTORE_BATCH_SIZE) {
+            A::Entity::insert_many(
+                batch
+                    .iter()
+                    .cloned()
+                    .map(IntoActiveModel::into_active_model),
+            )
+            .exec(db)
+            .await?;
+        }
+
+        Ok(())
+    }
+}
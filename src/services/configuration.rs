@@ -1,6 +1,129 @@
-use std::{env, process};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fmt, fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use envconfig::Envconfig;
+use ipnet::IpNet;
+
+const VARIABLE_PREFIX: &str = "MONEYBALANCER";
+/// Path of the optional config file, can be overridden by the `--config` flag
+const CONFIG_PATH_VARIABLE: &str = "MONEYBALANCER_CONFIG_PATH";
+/// Variables with this suffix contain the path of a file holding the value, e.g. a Docker secret
+const SECRET_FILE_SUFFIX: &str = "_FILE";
+
+/// Comma separated list of ip addresses and networks, e.g. `10.0.0.1,192.168.0.0/16`
+#[derive(Debug, Clone)]
+pub struct IpNetList(Vec<IpNet>);
+
+impl IpNetList {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(ip))
+    }
+}
+
+impl FromStr for IpNetList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| format!("invalid ip address or network: {}", entry))
+            })
+            .collect::<Result<Vec<IpNet>, String>>()
+            .map(IpNetList)
+    }
+}
+
+/// Comma separated list of strings, e.g. `alice,bob`
+#[derive(Debug)]
+pub struct StringList(Vec<String>);
+
+impl StringList {
+    pub fn contains(&self, value: &str) -> bool {
+        self.0.iter().any(|v| v == value)
+    }
+}
+
+impl FromStr for StringList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(StringList(
+            s.split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        ))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RegistrationPolicy {
+    /// everyone can sign up
+    Open,
+    /// nobody can sign up, users have to be created by an admin or the proxy
+    Closed,
+    /// signing up requires an invite code created by an admin
+    Invite,
+    /// everyone can sign up, but the account has to be enabled by an admin
+    Approval,
+}
+
+impl FromStr for RegistrationPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "open" => Ok(RegistrationPolicy::Open),
+            "closed" => Ok(RegistrationPolicy::Closed),
+            "invite" => Ok(RegistrationPolicy::Invite),
+            "approval" => Ok(RegistrationPolicy::Approval),
+            _ => Err(format!(
+                "invalid registration policy: {} (expected open, closed, invite or approval)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Envconfig, Debug)]
+pub struct RegistrationConfig {
+    #[envconfig(from = "MONEYBALANCER_REGISTRATION_POLICY", default = "open")]
+    pub policy: RegistrationPolicy,
+    #[envconfig(from = "MONEYBALANCER_REGISTRATION_ALLOWED_DOMAINS")]
+    pub allowed_domains: Option<StringList>,
+}
+
+impl RegistrationConfig {
+    /// Usernames have to be e-mail addresses of one of the allowed domains, if any are configured
+    pub fn is_username_allowed(&self, username: &str) -> bool {
+        let allowed_domains = match &self.allowed_domains {
+            Some(allowed_domains) => allowed_domains,
+            None => return true,
+        };
+
+        match username.rsplit_once('@') {
+            Some((local, domain)) => {
+                !local.is_empty()
+                    && allowed_domains
+                        .0
+                        .iter()
+                        .any(|allowed| allowed.eq_ignore_ascii_case(domain))
+            }
+            None => false,
+        }
+    }
+}
 
 #[derive(Envconfig, Debug)]
 pub struct ProxyAuthConfig {
@@ -10,12 +133,90 @@ pub struct ProxyAuthConfig {
     pub headers_username: Option<String>,
     #[envconfig(from = "MONEYBALANCER_AUTH_PROXY_HEADERS_NICKNAME")]
     pub headers_nickname: Option<String>,
+    #[envconfig(from = "MONEYBALANCER_AUTH_PROXY_FORWARD_AUTH", default = "false")]
+    pub forward_auth: bool,
+    #[envconfig(from = "MONEYBALANCER_AUTH_PROXY_TRUSTED_PROXIES")]
+    pub trusted_proxies: Option<IpNetList>,
+    #[envconfig(from = "MONEYBALANCER_AUTH_PROXY_HEADERS_SECRET")]
+    pub headers_secret: Option<String>,
+    #[envconfig(from = "MONEYBALANCER_AUTH_PROXY_SECRET")]
+    pub secret: Option<String>,
+    #[envconfig(nested = true)]
+    pub groups: ProxyGroupsConfig,
+}
+
+#[derive(Envconfig, Debug)]
+pub struct ProxyGroupsConfig {
+    #[envconfig(from = "MONEYBALANCER_AUTH_PROXY_HEADERS_GROUPS")]
+    pub header: Option<String>,
+    #[envconfig(from = "MONEYBALANCER_AUTH_PROXY_GROUPS_SEPARATOR", default = ",")]
+    pub separator: String,
+    #[envconfig(from = "MONEYBALANCER_AUTH_PROXY_GROUPS_PREFIX", default = "")]
+    pub prefix: String,
+    #[envconfig(
+        from = "MONEYBALANCER_AUTH_PROXY_GROUPS_REMOVE_STALE",
+        default = "false"
+    )]
+    pub remove_stale: bool,
+}
+
+impl ProxyGroupsConfig {
+    /// Parses the groups header and returns the names of all groups matching the prefix,
+    /// with the prefix removed.
+    pub fn parse_header(&self, value: &str) -> Vec<String> {
+        let mut groups = value
+            .split(self.separator.as_str())
+            .map(str::trim)
+            .filter_map(|group| group.strip_prefix(self.prefix.as_str()))
+            .filter(|group| !group.is_empty())
+            .map(str::to_owned)
+            .collect::<Vec<String>>();
+        groups.sort();
+        groups.dedup();
+        groups
+    }
 }
 
 #[derive(Envconfig, Debug)]
 struct LocalAuthConfig {
     #[envconfig(from = "MONEYBALANCER_AUTH_LOCAL_ENABLED", default = "true")]
     enabled: bool,
+    #[envconfig(nested = true)]
+    rate_limit: RateLimitConfig,
+    #[envconfig(
+        from = "MONEYBALANCER_AUTH_LOCAL_TOTP_ISSUER",
+        default = "money-balancer"
+    )]
+    totp_issuer: String,
+}
+
+#[derive(Envconfig, Debug)]
+pub struct RateLimitConfig {
+    #[envconfig(from = "MONEYBALANCER_AUTH_LOCAL_RATE_LIMIT_ENABLED", default = "true")]
+    pub enabled: bool,
+    #[envconfig(
+        from = "MONEYBALANCER_AUTH_LOCAL_RATE_LIMIT_MAX_ATTEMPTS_PER_USERNAME",
+        default = "5"
+    )]
+    pub max_attempts_per_username: u32,
+    #[envconfig(
+        from = "MONEYBALANCER_AUTH_LOCAL_RATE_LIMIT_MAX_ATTEMPTS_PER_IP",
+        default = "20"
+    )]
+    pub max_attempts_per_ip: u32,
+    #[envconfig(
+        from = "MONEYBALANCER_AUTH_LOCAL_RATE_LIMIT_LOCKOUT_SECONDS",
+        default = "30"
+    )]
+    pub lockout_seconds: u64,
+    #[envconfig(
+        from = "MONEYBALANCER_AUTH_LOCAL_RATE_LIMIT_MAX_LOCKOUT_SECONDS",
+        default = "3600"
+    )]
+    pub max_lockout_seconds: u64,
+    /// Proxies whose `X-Real-IP` header is used as the ip of the client
+    #[envconfig(from = "MONEYBALANCER_AUTH_LOCAL_RATE_LIMIT_TRUSTED_PROXIES")]
+    pub trusted_proxies: Option<IpNetList>,
 }
 
 #[derive(Envconfig, Debug)]
@@ -26,34 +227,233 @@ struct AuthConfig {
     local: LocalAuthConfig,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("invalid log format: {} (expected text or json)", s)),
+        }
+    }
+}
+
+#[derive(Envconfig, Debug)]
+pub struct LoggingConfig {
+    /// Filter directives, e.g. `info,money_balancer::routes=debug,sqlx=info`
+    #[envconfig(from = "MONEYBALANCER_LOG_LEVEL", default = "info")]
+    pub level: String,
+    #[envconfig(from = "MONEYBALANCER_LOG_FORMAT", default = "text")]
+    pub format: LogFormat,
+}
+
+#[derive(Envconfig, Debug)]
+pub struct MetricsConfig {
+    #[envconfig(from = "MONEYBALANCER_METRICS_ENABLED", default = "false")]
+    enabled: bool,
+    /// If set, `/metrics` requires it as a bearer token
+    #[envconfig(from = "MONEYBALANCER_METRICS_TOKEN")]
+    pub token: Option<String>,
+}
+
+#[derive(Envconfig, Debug)]
+pub struct WebhookConfig {
+    #[envconfig(from = "MONEYBALANCER_WEBHOOKS_ENABLED", default = "false")]
+    enabled: bool,
+    /// Including the first attempt
+    #[envconfig(from = "MONEYBALANCER_WEBHOOKS_MAX_ATTEMPTS", default = "5")]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every further retry
+    #[envconfig(from = "MONEYBALANCER_WEBHOOKS_RETRY_DELAY_SECONDS", default = "10")]
+    pub retry_delay_seconds: u64,
+    #[envconfig(from = "MONEYBALANCER_WEBHOOKS_TIMEOUT_SECONDS", default = "10")]
+    pub timeout_seconds: u64,
+    /// Internal networks webhooks may be sent to, all other targets have to be public
+    #[envconfig(from = "MONEYBALANCER_WEBHOOKS_ALLOWED_NETWORKS")]
+    pub allowed_networks: Option<IpNetList>,
+}
+
+#[derive(Envconfig, Debug)]
+pub struct IdempotencyConfig {
+    /// How long the response to a request with an `Idempotency-Key` is replayed
+    #[envconfig(from = "MONEYBALANCER_IDEMPOTENCY_WINDOW_SECONDS", default = "86400")]
+    pub window_seconds: u64,
+}
+
+#[derive(Envconfig, Debug, Clone)]
+pub struct DatabaseConfig {
+    #[envconfig(
+        from = "MONEYBALANCER_DATABASE_URL",
+        default = "sqlite:./money-balancer.sqlite?mode=rwc"
+    )]
+    pub url: String,
+    #[envconfig(from = "MONEYBALANCER_DATABASE_MAX_CONNECTIONS", default = "10")]
+    pub max_connections: u32,
+    #[envconfig(from = "MONEYBALANCER_DATABASE_MIN_CONNECTIONS", default = "1")]
+    pub min_connections: u32,
+    #[envconfig(
+        from = "MONEYBALANCER_DATABASE_CONNECT_TIMEOUT_SECONDS",
+        default = "10"
+    )]
+    pub connect_timeout_seconds: u64,
+    #[envconfig(from = "MONEYBALANCER_DATABASE_IDLE_TIMEOUT_SECONDS", default = "600")]
+    pub idle_timeout_seconds: u64,
+}
+
+/// All problems found while loading the configuration, so they can be fixed at once
+#[derive(Debug, Default)]
+pub struct ConfigurationReport {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl fmt::Display for ConfigurationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "The configuration is invalid:")?;
+        for error in &self.errors {
+            writeln!(f, "  Error: {}", error)?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "  Warning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Envconfig, Debug)]
 pub struct ConfigurationService {
     #[envconfig(from = "MONEYBALANCER_JWT_SECRET")]
     jwt_secret: String,
 
+    #[envconfig(nested = true)]
+    database: DatabaseConfig,
+
+    #[envconfig(from = "MONEYBALANCER_ADMIN_USERNAMES")]
+    admin_usernames: Option<StringList>,
+
     #[envconfig(nested = true)]
     auth: AuthConfig,
+
+    #[envconfig(nested = true)]
+    registration: RegistrationConfig,
+
+    #[envconfig(nested = true)]
+    logging: LoggingConfig,
+
+    #[envconfig(nested = true)]
+    metrics: MetricsConfig,
+
+    #[envconfig(nested = true)]
+    webhooks: WebhookConfig,
+
+    #[envconfig(nested = true)]
+    idempotency: IdempotencyConfig,
 }
 
 impl ConfigurationService {
-    pub fn new() -> ConfigurationService {
+    /// Loads the config file (if any), then the `MONEYBALANCER_*` environment variables,
+    /// which take precedence. Fails with a report of all problems if the result is invalid.
+    pub fn load(config_path: Option<&Path>) -> Result<ConfigurationService, ConfigurationReport> {
         ConfigurationService::_load_deprecated_variables();
 
-        let res = ConfigurationService::init_from_env();
+        let mut report = ConfigurationReport::default();
+
+        let config_path = config_path
+            .map(Path::to_path_buf)
+            .or_else(|| env::var_os(CONFIG_PATH_VARIABLE).map(PathBuf::from));
+        let mut variables = match &config_path {
+            Some(path) => ConfigurationService::_read_config_file(path, &mut report),
+            None => HashMap::new(),
+        };
+        ConfigurationService::_resolve_secret_files(&mut variables, &mut report);
+
+        let mut env_variables = env::vars()
+            .filter(|(name, _)| name.starts_with(VARIABLE_PREFIX))
+            .collect::<HashMap<String, String>>();
+        ConfigurationService::_resolve_secret_files(&mut env_variables, &mut report);
+        variables.extend(env_variables);
 
-        if let Ok(c) = res {
-            return c;
+        let configuration = ConfigurationService::_init_from_variables(variables, &mut report);
+        if let Some(configuration) = &configuration {
+            report.errors.extend(configuration._validate());
+            report.warnings.extend(configuration.warnings());
         }
 
-        println!("Error loading config:");
-        println!("{}", res.unwrap_err().to_string());
-        process::exit(1);
+        match configuration {
+            Some(configuration) if report.errors.is_empty() => Ok(configuration),
+            _ => Err(report),
+        }
+    }
+
+    /// Settings which work, but are most likely not intended
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+
+        if let Some(c) = self.auth_proxy() {
+            if c.trusted_proxies.is_none() && c.secret.is_none() {
+                warnings.push("proxy authentication is enabled without trusted proxies or a secret. Everyone who can reach money-balancer directly can impersonate any user!".to_owned());
+            }
+        }
+
+        warnings
     }
 
     pub fn jwt_secret(&self) -> &str {
         &self.jwt_secret
     }
 
+    pub fn database(&self) -> &DatabaseConfig {
+        &self.database
+    }
+
+    /// Users who are made admins on startup or when they are created
+    pub fn is_configured_admin(&self, username: &str) -> bool {
+        match &self.admin_usernames {
+            Some(admin_usernames) => admin_usernames.contains(username),
+            None => false,
+        }
+    }
+
+    pub fn admin_usernames(&self) -> Vec<String> {
+        match &self.admin_usernames {
+            Some(admin_usernames) => admin_usernames.0.clone(),
+            None => vec![],
+        }
+    }
+
+    pub fn logging(&self) -> &LoggingConfig {
+        &self.logging
+    }
+
+    pub fn metrics(&self) -> Option<&MetricsConfig> {
+        match self.metrics.enabled {
+            false => None,
+            true => Some(&self.metrics),
+        }
+    }
+
+    pub fn webhooks(&self) -> Option<&WebhookConfig> {
+        match self.webhooks.enabled {
+            false => None,
+            true => Some(&self.webhooks),
+        }
+    }
+
+    pub fn idempotency(&self) -> &IdempotencyConfig {
+        &self.idempotency
+    }
+
+    pub fn registration(&self) -> &RegistrationConfig {
+        &self.registration
+    }
+
     pub fn auth_local(&self) -> Option<()> {
         match self.auth.local.enabled {
             false => None,
@@ -61,6 +461,14 @@ impl ConfigurationService {
         }
     }
 
+    pub fn auth_local_rate_limit(&self) -> &RateLimitConfig {
+        &self.auth.local.rate_limit
+    }
+
+    pub fn totp_issuer(&self) -> &str {
+        &self.auth.local.totp_issuer
+    }
+
     pub fn auth_proxy(&self) -> Option<&ProxyAuthConfig> {
         match self.auth.proxy.enabled {
             false => None,
@@ -68,6 +476,187 @@ impl ConfigurationService {
         }
     }
 
+    fn _validate(&self) -> Vec<String> {
+        let mut errors = vec![];
+
+        if self.jwt_secret.is_empty() {
+            errors.push("MONEYBALANCER_JWT_SECRET must not be empty".to_owned());
+        }
+
+        if self.database.min_connections > self.database.max_connections {
+            errors.push("MONEYBALANCER_DATABASE_MIN_CONNECTIONS must not exceed MONEYBALANCER_DATABASE_MAX_CONNECTIONS".to_owned());
+        }
+
+        if self.webhooks.max_attempts == 0 {
+            errors.push("MONEYBALANCER_WEBHOOKS_MAX_ATTEMPTS must be at least 1".to_owned());
+        }
+
+        if self.idempotency.window_seconds == 0 {
+            errors.push("MONEYBALANCER_IDEMPOTENCY_WINDOW_SECONDS must be at least 1".to_owned());
+        }
+
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.logging.level) {
+            errors.push(format!("MONEYBALANCER_LOG_LEVEL is invalid: {}", e));
+        }
+
+        if let Some(c) = self.auth_proxy() {
+            if c.headers_username.is_none() {
+                errors.push("proxy authentication is enabled, but MONEYBALANCER_AUTH_PROXY_HEADERS_USERNAME is not set".to_owned());
+            }
+            if c.headers_secret.is_some() != c.secret.is_some() {
+                errors.push("MONEYBALANCER_AUTH_PROXY_HEADERS_SECRET and MONEYBALANCER_AUTH_PROXY_SECRET have to be set together".to_owned());
+            }
+        } else if self.auth_local().is_none() {
+            errors.push("at least one authentication provider must be enabled".to_owned());
+        }
+
+        errors
+    }
+
+    /// Reads a TOML file and maps its keys to the names of the environment variables,
+    /// e.g. `max_connections` in the `[database]` table to `MONEYBALANCER_DATABASE_MAX_CONNECTIONS`
+    fn _read_config_file(path: &Path, report: &mut ConfigurationReport) -> HashMap<String, String> {
+        let mut variables = HashMap::new();
+
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                report.errors.push(format!(
+                    "cannot read the config file {}: {}",
+                    path.display(),
+                    e
+                ));
+                return variables;
+            }
+        };
+
+        match content.parse::<toml::Value>() {
+            Ok(value) => {
+                ConfigurationService::_flatten(VARIABLE_PREFIX, &value, &mut variables, report)
+            }
+            Err(e) => report.errors.push(format!(
+                "cannot parse the config file {}: {}",
+                path.display(),
+                e
+            )),
+        }
+
+        variables
+    }
+
+    fn _flatten(
+        name: &str,
+        value: &toml::Value,
+        variables: &mut HashMap<String, String>,
+        report: &mut ConfigurationReport,
+    ) {
+        let value = match value {
+            toml::Value::Table(table) => {
+                for (key, value) in table {
+                    let name = format!("{}_{}", name, key.to_uppercase().replace('-', "_"));
+                    ConfigurationService::_flatten(&name, value, variables, report);
+                }
+                return;
+            }
+            // lists are comma separated in the environment variables
+            toml::Value::Array(values) => values
+                .iter()
+                .map(ConfigurationService::_scalar_to_string)
+                .collect::<Option<Vec<String>>>()
+                .map(|values| values.join(",")),
+            value => ConfigurationService::_scalar_to_string(value),
+        };
+
+        match value {
+            Some(value) => {
+                variables.insert(name.to_owned(), value);
+            }
+            None => report
+                .errors
+                .push(format!("{} must be a value or a list of values", name)),
+        }
+    }
+
+    fn _scalar_to_string(value: &toml::Value) -> Option<String> {
+        match value {
+            toml::Value::String(value) => Some(value.to_owned()),
+            toml::Value::Integer(value) => Some(value.to_string()),
+            toml::Value::Float(value) => Some(value.to_string()),
+            toml::Value::Boolean(value) => Some(value.to_string()),
+            toml::Value::Datetime(value) => Some(value.to_string()),
+            toml::Value::Array(_) | toml::Value::Table(_) => None,
+        }
+    }
+
+    /// Replaces every `<NAME>_FILE` variable by `<NAME>` with the content of the file
+    fn _resolve_secret_files(
+        variables: &mut HashMap<String, String>,
+        report: &mut ConfigurationReport,
+    ) {
+        let secret_files = variables
+            .keys()
+            .filter(|name| name.ends_with(SECRET_FILE_SUFFIX))
+            .cloned()
+            .collect::<Vec<String>>();
+
+        for file_variable in secret_files {
+            let path = variables.remove(&file_variable).unwrap();
+            let name = file_variable
+                .strip_suffix(SECRET_FILE_SUFFIX)
+                .unwrap()
+                .to_owned();
+
+            if variables.contains_key(&name) {
+                report.errors.push(format!(
+                    "{} and {} must not be set both",
+                    name, file_variable
+                ));
+                continue;
+            }
+
+            match fs::read_to_string(&path) {
+                Ok(value) => {
+                    variables.insert(name, value.trim_end_matches(&['\r', '\n'][..]).to_owned());
+                }
+                Err(e) => report
+                    .errors
+                    .push(format!("cannot read {} ({}): {}", file_variable, path, e)),
+            }
+        }
+    }
+
+    /// envconfig stops at the first invalid variable. To report all of them, the invalid ones
+    /// are replaced and loading is retried.
+    fn _init_from_variables(
+        mut variables: HashMap<String, String>,
+        report: &mut ConfigurationReport,
+    ) -> Option<ConfigurationService> {
+        let mut reported = HashSet::new();
+
+        loop {
+            let name = match ConfigurationService::init_from_hashmap(&variables) {
+                Ok(configuration) => return Some(configuration),
+                Err(envconfig::Error::EnvVarMissing { name }) => {
+                    report.errors.push(format!("{} is missing", name));
+                    // a placeholder, so the missing variable is not reported as invalid as well
+                    variables.insert(name.to_owned(), "<missing>".to_owned());
+                    name
+                }
+                Err(envconfig::Error::ParseError { name }) => {
+                    let value = variables.remove(name).unwrap_or_default();
+                    report
+                        .errors
+                        .push(format!("{} has an invalid value: {}", name, value));
+                    name
+                }
+            };
+
+            if !reported.insert(name) {
+                return None;
+            }
+        }
+    }
+
     // used for backwards compatibility
     fn _load_deprecated_variables() {
         if env::var("JWT_SECRET").is_ok() && env::var("MONEYBALANCER_JWT_SECRET").is_err() {
@@ -75,3 +664,72 @@ impl ConfigurationService {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigurationService, RegistrationPolicy};
+    use serial_test::serial;
+    use std::{env, fs};
+
+    #[test]
+    #[serial]
+    fn test_config_file_and_report() {
+        let dir = env::temp_dir().join("money-balancer-config-test");
+        fs::create_dir_all(&dir).unwrap();
+        let secret = dir.join("jwt_secret");
+        fs::write(&secret, "from-file\n").unwrap();
+        let config = dir.join("config.toml");
+        fs::write(
+            &config,
+            format!(
+                r#"
+admin_usernames = ["alice", "bob"]
+jwt_secret_file = "{}"
+
+[database]
+max_connections = 3
+
+[registration]
+policy = "closed"
+"#,
+                secret.display()
+            ),
+        )
+        .unwrap();
+
+        env::remove_var("MONEYBALANCER_JWT_SECRET");
+        env::set_var("MONEYBALANCER_REGISTRATION_POLICY", "invite");
+        let configuration = ConfigurationService::load(Some(&config)).unwrap();
+        assert_eq!(configuration.jwt_secret(), "from-file");
+        assert_eq!(configuration.database().max_connections, 3);
+        assert_eq!(
+            configuration.registration().policy,
+            RegistrationPolicy::Invite
+        );
+        assert!(configuration.is_configured_admin("bob"));
+
+        // all problems are reported at once
+        env::set_var("MONEYBALANCER_REGISTRATION_POLICY", "sometimes");
+        env::set_var("MONEYBALANCER_DATABASE_MIN_CONNECTIONS", "5");
+        env::set_var("MONEYBALANCER_AUTH_PROXY_ENABLED", "true");
+        let report = ConfigurationService::load(Some(&config)).unwrap_err();
+        assert_eq!(report.errors.len(), 3, "{:?}", report.errors);
+        assert!(report.errors[0].contains("MONEYBALANCER_REGISTRATION_POLICY"));
+        assert!(report.errors[1].contains("MONEYBALANCER_DATABASE_MIN_CONNECTIONS"));
+        assert!(report.errors[2].contains("MONEYBALANCER_AUTH_PROXY_HEADERS_USERNAME"));
+        assert_eq!(report.warnings.len(), 1);
+
+        env::remove_var("MONEYBALANCER_REGISTRATION_POLICY");
+        env::remove_var("MONEYBALANCER_DATABASE_MIN_CONNECTIONS");
+        env::remove_var("MONEYBALANCER_AUTH_PROXY_ENABLED");
+
+        env::set_var("MONEYBALANCER_JWT_SECRET", "secret");
+        env::set_var("MONEYBALANCER_JWT_SECRET_FILE", &secret);
+        let report = ConfigurationService::load(None).unwrap_err();
+        assert_eq!(report.errors.len(), 1);
+        env::remove_var("MONEYBALANCER_JWT_SECRET_FILE");
+
+        let report = ConfigurationService::load(Some(&dir.join("missing.toml"))).unwrap_err();
+        assert!(report.errors[0].contains("missing.toml"));
+    }
+}
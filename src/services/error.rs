@@ -0,0 +1,117 @@
+use ::serde::{Deserialize, Serialize};
+use sea_orm::DbErr;
+use std::fmt;
+use utoipa::ToSchema;
+
+pub type ServiceResult<T> = Result<T, ServiceError>;
+
+/// A single invalid field of a request
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct FieldError {
+    #[schema(example = "debtor_ids")]
+    pub field: String,
+    /// machine-readable reason, e.g. `empty` or `too_long`
+    #[schema(example = "duplicate")]
+    pub code: String,
+    pub message: String,
+}
+
+/// Errors of the services. The codes are stable and meant to be handled by clients.
+#[derive(Debug)]
+pub enum ServiceError {
+    /// The resource does not exist or is not visible to the user
+    NotFound(&'static str),
+    /// The resource is visible to the user, but the action is not allowed
+    Forbidden(&'static str),
+    Conflict(&'static str),
+    /// The request is well-formed, but contradicts an earlier one
+    Unprocessable(&'static str),
+    Validation(Vec<FieldError>),
+    Database(DbErr),
+}
+
+impl From<DbErr> for ServiceError {
+    fn from(err: DbErr) -> Self {
+        ServiceError::Database(err)
+    }
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceError::NotFound(code)
+            | ServiceError::Forbidden(code)
+            | ServiceError::Conflict(code)
+            | ServiceError::Unprocessable(code) => write!(f, "{}", code),
+            ServiceError::Validation(errors) => {
+                let errors = errors
+                    .iter()
+                    .map(|error| format!("{}: {}", error.field, error.message))
+                    .collect::<Vec<String>>();
+                write!(f, "{}", errors.join(", "))
+            }
+            ServiceError::Database(err) => write!(f, "database error: {}", err),
+        }
+    }
+}
+
+/// Whether a statement failed because of a unique constraint. sea-orm only passes on
+/// the message of the database, which differs between the backends.
+pub fn is_unique_violation(err: &DbErr) -> bool {
+    let message = match err {
+        DbErr::Exec(message) | DbErr::Query(message) => message,
+        _ => return false,
+    };
+
+    [
+        // sqlite
+        "UNIQUE constraint failed",
+        // postgres
+        "duplicate key value violates unique constraint",
+        // mysql
+        "Duplicate entry",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+/// Collects the invalid fields of a request
+#[derive(Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn new() -> Validator {
+        Validator::default()
+    }
+
+    /// Records an error for the field unless `is_valid` holds
+    pub fn check(&mut self, is_valid: bool, field: &str, code: &str, message: &str) -> &mut Self {
+        if !is_valid {
+            self.errors.push(FieldError {
+                field: field.to_owned(),
+                code: code.to_owned(),
+                message: message.to_owned(),
+            });
+        }
+
+        self
+    }
+
+    pub fn finish(&mut self) -> ServiceResult<()> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ServiceError::Validation(std::mem::take(&mut self.errors)))
+        }
+    }
+}
+
+/// Shorthand for a validation error of a single field
+pub fn invalid_field(field: &str, code: &str, message: &str) -> ServiceError {
+    Validator::new()
+        .check(false, field, code, message)
+        .finish()
+        .unwrap_err()
+}
@@ -0,0 +1,176 @@
+use rocket::serde::Serialize;
+use std::cmp::Reverse;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+use zip::write::SimpleFileOptions;
+
+use super::error::ServiceResult;
+use super::friend::{Friend, FriendService};
+use super::group::{Group, GroupService, Transaction};
+use super::invite::{InviteCode, InviteService};
+use super::totp::TotpService;
+use super::user::User;
+
+pub const EXPORT_FILE_NAME: &str = "money-balancer-export";
+
+#[derive(Serialize, ToSchema)]
+pub struct ExportedProfile {
+    pub id: String,
+    pub username: String,
+    pub nickname: String,
+    pub is_admin: bool,
+    pub is_totp_enabled: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityKind {
+    Paid,
+    Owes,
+}
+
+/// A single entry of the timeline of a user, derived from their transactions
+#[derive(Serialize, ToSchema)]
+pub struct ExportedActivity {
+    pub timestamp: i64,
+    pub kind: ActivityKind,
+    pub transaction_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<String>,
+    pub description: String,
+    pub amount: i64,
+}
+
+/// All personal data which is stored about a user.
+/// Passwords, TOTP secrets and recovery codes are never exported.
+#[derive(Serialize, ToSchema)]
+pub struct UserExport {
+    pub exported_at: u64,
+    pub profile: ExportedProfile,
+    pub memberships: Vec<Group>,
+    pub friends: Vec<Friend>,
+    pub transactions: Vec<Transaction>,
+    pub direct_transactions: Vec<Transaction>,
+    pub invite_codes: Vec<InviteCode>,
+    pub activity: Vec<ExportedActivity>,
+}
+
+#[derive(Debug)]
+pub struct ExportService {
+    group_service: Arc<GroupService>,
+    friend_service: Arc<FriendService>,
+    invite_service: Arc<InviteService>,
+    totp_service: Arc<TotpService>,
+}
+
+impl ExportService {
+    pub fn new(
+        group_service: Arc<GroupService>,
+        friend_service: Arc<FriendService>,
+        invite_service: Arc<InviteService>,
+        totp_service: Arc<TotpService>,
+    ) -> ExportService {
+        ExportService {
+            group_service,
+            friend_service,
+            invite_service,
+            totp_service,
+        }
+    }
+
+    pub async fn export_user(&self, user: User) -> ServiceResult<UserExport> {
+        let transactions = self
+            .group_service
+            .get_transactions_of_user(&user.id)
+            .await?;
+        let direct_transactions = self
+            .friend_service
+            .get_transactions_of_user(&user.id)
+            .await?;
+
+        let mut activity = transactions
+            .iter()
+            .chain(direct_transactions.iter())
+            .flat_map(|transaction| Self::_activity_of_transaction(&user.id, transaction))
+            .collect::<Vec<ExportedActivity>>();
+        activity.sort_by_key(|a| Reverse(a.timestamp));
+
+        Ok(UserExport {
+            exported_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            profile: ExportedProfile {
+                is_totp_enabled: self.totp_service.is_enabled(&user.id).await?,
+                id: user.id.to_owned(),
+                username: user.username,
+                nickname: user.nickname,
+                is_admin: user.is_admin,
+            },
+            memberships: self
+                .group_service
+                .get_groups_of_user(user.id.to_owned())
+                .await?,
+            friends: self.friend_service.get_friends_of_user(&user.id).await?,
+            invite_codes: self
+                .invite_service
+                .get_invite_codes_of_user(&user.id)
+                .await?,
+            transactions,
+            direct_transactions,
+            activity,
+        })
+    }
+
+    /// Packs the export as a single JSON file into a zip archive
+    pub fn to_zip(&self, export: &UserExport) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+
+        zip.start_file(
+            format!("{}.json", EXPORT_FILE_NAME),
+            SimpleFileOptions::default(),
+        )
+        .expect("error creating export archive");
+        zip.write_all(
+            rocket::serde::json::to_string(export)
+                .expect("error serializing export")
+                .as_bytes(),
+        )
+        .expect("error writing export archive");
+
+        zip.finish()
+            .expect("error finishing export archive")
+            .into_inner()
+    }
+
+    fn _activity_of_transaction(user_id: &str, transaction: &Transaction) -> Vec<ExportedActivity> {
+        let mut activity = Vec::new();
+        let activity_entry = |kind, amount| ExportedActivity {
+            timestamp: transaction.timestamp,
+            kind,
+            transaction_id: transaction.id.to_owned(),
+            group_id: transaction.group_id.to_owned(),
+            description: transaction.description.to_owned(),
+            amount,
+        };
+
+        if transaction.creditor_id == user_id {
+            activity.push(activity_entry(
+                ActivityKind::Paid,
+                transaction.debts.iter().map(|debt| debt.amount).sum(),
+            ));
+        }
+
+        if let Some(debt) = transaction
+            .debts
+            .iter()
+            .find(|debt| debt.debtor_id == user_id)
+        {
+            activity.push(activity_entry(ActivityKind::Owes, debt.amount));
+        }
+
+        activity
+    }
+}
@@ -0,0 +1,484 @@
+use crate::model;
+use ::serde::{Deserialize, Serialize};
+use sea_orm::sea_query::Condition;
+use sea_orm::*;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use utoipa::ToSchema;
+
+use super::error::{invalid_field, is_unique_violation, ServiceError, ServiceResult};
+use super::group::{
+    get_count_of_unequally_charged_debts, split_amount, sum_as_i64, timestamp_or_now,
+    validate_transaction, Debt, Transaction,
+};
+use super::user::UserService;
+
+/// A friend of the user and the balance of their direct ledger.
+/// A positive amount is owed to the user, a negative amount is owed by the user.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Friend {
+    pub id: String,
+    pub nickname: String,
+    pub amount: i64,
+}
+
+/// A pending friend request, either sent by the user or to the user
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct FriendRequest {
+    /// id of the other user
+    pub id: String,
+    pub nickname: String,
+    /// whether the other user sent the request, so the user can accept it
+    pub is_incoming: bool,
+}
+
+#[derive(FromQueryResult)]
+struct BalanceWithFriend {
+    counterparty_id: String,
+    amount: i64,
+}
+
+/// Direct ledgers between two users, which are not tied to a group.
+#[derive(Debug)]
+pub struct FriendService {
+    db: Arc<DatabaseConnection>,
+    user_service: Arc<UserService>,
+}
+
+impl FriendService {
+    pub fn new(db: Arc<DatabaseConnection>, user_service: Arc<UserService>) -> FriendService {
+        FriendService { db, user_service }
+    }
+
+    pub async fn get_friends_of_user(&self, user_id: &str) -> ServiceResult<Vec<Friend>> {
+        let balances = self._get_balances_of_user(user_id).await?;
+
+        let friend_ids = model::friend::Entity::find()
+            .filter(model::friend::Column::UserId.eq(user_id))
+            .all(self.db.as_ref())
+            .await?
+            .into_iter()
+            .map(|friend| friend.friend_id)
+            .collect::<Vec<String>>();
+
+        Ok(model::user::Entity::find()
+            .filter(model::user::Column::Id.is_in(friend_ids))
+            .order_by_asc(model::user::Column::Nickname)
+            .all(self.db.as_ref())
+            .await?
+            .into_iter()
+            .map(|friend| Friend {
+                amount: *balances.get(&friend.id).unwrap_or(&0),
+                id: friend.id,
+                nickname: friend.nickname,
+            })
+            .collect::<Vec<Friend>>())
+    }
+
+    pub async fn get_friend_of_user(
+        &self,
+        user_id: &str,
+        friend_id: &str,
+    ) -> ServiceResult<Friend> {
+        self.get_friends_of_user(user_id)
+            .await?
+            .into_iter()
+            .find(|friend| friend.id == friend_id)
+            .ok_or(ServiceError::NotFound("friend_not_found"))
+    }
+
+    /// Sends a friend request to the other user or accepts the request of the other user.
+    /// Friendships are mutual, so both become friends of each other once the request is
+    /// accepted. Returns `None` while the request is pending.
+    pub async fn add_friend(
+        &self,
+        user_id: &str,
+        friend_id: &str,
+    ) -> ServiceResult<Option<Friend>> {
+        if user_id == friend_id {
+            return Err(invalid_field(
+                "friend_id",
+                "self",
+                "Users cannot befriend themselves",
+            ));
+        }
+
+        if self
+            .user_service
+            .get_user_by_id(friend_id.to_owned())
+            .await?
+            .is_none()
+        {
+            return Err(ServiceError::NotFound("user_not_found"));
+        }
+
+        if self._is_friend_of_user(user_id, friend_id).await? {
+            return self.get_friend_of_user(user_id, friend_id).await.map(Some);
+        }
+
+        let txn = self.db.begin().await?;
+
+        let is_accepted =
+            model::friend_request::Entity::delete_by_id((friend_id.to_owned(), user_id.to_owned()))
+                .exec(&txn)
+                .await?
+                .rows_affected
+                > 0;
+
+        if !is_accepted {
+            // nothing was changed
+            drop(txn);
+
+            let res = model::friend_request::Entity::insert(model::friend_request::ActiveModel {
+                user_id: ActiveValue::Set(user_id.to_owned()),
+                friend_id: ActiveValue::Set(friend_id.to_owned()),
+            })
+            .exec(self.db.as_ref())
+            .await;
+
+            // the request was already sent
+            return match res {
+                Err(e) if !is_unique_violation(&e) => Err(e.into()),
+                _ => Ok(None),
+            };
+        }
+
+        model::friend::Entity::insert_many([
+            model::friend::ActiveModel {
+                user_id: ActiveValue::Set(user_id.to_owned()),
+                friend_id: ActiveValue::Set(friend_id.to_owned()),
+            },
+            model::friend::ActiveModel {
+                user_id: ActiveValue::Set(friend_id.to_owned()),
+                friend_id: ActiveValue::Set(user_id.to_owned()),
+            },
+        ])
+        .exec(&txn)
+        .await?;
+
+        txn.commit().await?;
+
+        self.get_friend_of_user(user_id, friend_id).await.map(Some)
+    }
+
+    /// The pending friend requests sent by or to the user
+    pub async fn get_friend_requests_of_user(
+        &self,
+        user_id: &str,
+    ) -> ServiceResult<Vec<FriendRequest>> {
+        let requests = model::friend_request::Entity::find()
+            .filter(
+                Condition::any()
+                    .add(model::friend_request::Column::UserId.eq(user_id))
+                    .add(model::friend_request::Column::FriendId.eq(user_id)),
+            )
+            .all(self.db.as_ref())
+            .await?;
+
+        let incoming = requests
+            .iter()
+            .filter(|request| request.friend_id == user_id)
+            .map(|request| request.user_id.to_owned())
+            .collect::<HashSet<String>>();
+        let other_ids = requests
+            .into_iter()
+            .map(|request| match request.user_id == user_id {
+                true => request.friend_id,
+                false => request.user_id,
+            });
+
+        Ok(model::user::Entity::find()
+            .filter(model::user::Column::Id.is_in(other_ids))
+            .order_by_asc(model::user::Column::Nickname)
+            .all(self.db.as_ref())
+            .await?
+            .into_iter()
+            .map(|user| FriendRequest {
+                is_incoming: incoming.contains(&user.id),
+                id: user.id,
+                nickname: user.nickname,
+            })
+            .collect::<Vec<FriendRequest>>())
+    }
+
+    /// Declines a request of the other user or withdraws a request sent to them
+    pub async fn delete_friend_request(&self, user_id: &str, other_id: &str) -> ServiceResult<()> {
+        let res = model::friend_request::Entity::delete_many()
+            .filter(
+                Condition::any()
+                    .add(
+                        Condition::all()
+                            .add(model::friend_request::Column::UserId.eq(user_id))
+                            .add(model::friend_request::Column::FriendId.eq(other_id)),
+                    )
+                    .add(
+                        Condition::all()
+                            .add(model::friend_request::Column::UserId.eq(other_id))
+                            .add(model::friend_request::Column::FriendId.eq(user_id)),
+                    ),
+            )
+            .exec(self.db.as_ref())
+            .await?;
+
+        match res.rows_affected {
+            0 => Err(ServiceError::NotFound("friend_request_not_found")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Ends the friendship for both users. The ledger is kept and shows up again
+    /// when they become friends again.
+    pub async fn remove_friend(&self, user_id: &str, friend_id: &str) -> ServiceResult<()> {
+        let res = model::friend::Entity::delete_many()
+            .filter(
+                Condition::any()
+                    .add(
+                        Condition::all()
+                            .add(model::friend::Column::UserId.eq(user_id))
+                            .add(model::friend::Column::FriendId.eq(friend_id)),
+                    )
+                    .add(
+                        Condition::all()
+                            .add(model::friend::Column::UserId.eq(friend_id))
+                            .add(model::friend::Column::FriendId.eq(user_id)),
+                    ),
+            )
+            .exec(self.db.as_ref())
+            .await?;
+
+        match res.rows_affected {
+            0 => Err(ServiceError::NotFound("friend_not_found")),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn get_transactions_with_friend(
+        &self,
+        user_id: &str,
+        friend_id: &str,
+    ) -> ServiceResult<Vec<Transaction>> {
+        if !self._is_friend_of_user(user_id, friend_id).await? {
+            return Err(ServiceError::NotFound("friend_not_found"));
+        }
+
+        let (first_user_id, second_user_id) = Self::_ordered_pair(user_id, friend_id);
+
+        self._get_transactions_help(
+            model::direct_transaction::Entity::find()
+                .filter(model::direct_transaction::Column::FirstUserId.eq(first_user_id))
+                .filter(model::direct_transaction::Column::SecondUserId.eq(second_user_id)),
+        )
+        .await
+    }
+
+    /// All direct transactions of the user, including those with former friends
+    pub async fn get_transactions_of_user(&self, user_id: &str) -> ServiceResult<Vec<Transaction>> {
+        self._get_transactions_help(
+            model::direct_transaction::Entity::find().filter(
+                Condition::any()
+                    .add(model::direct_transaction::Column::FirstUserId.eq(user_id))
+                    .add(model::direct_transaction::Column::SecondUserId.eq(user_id)),
+            ),
+        )
+        .await
+    }
+
+    /// The debtors have to be the user, the friend or both of them.
+    pub async fn create_transaction(
+        &self,
+        user_id: &str,
+        friend_id: &str,
+        debtor_ids: Vec<String>,
+        amount: u64,
+        description: String,
+        timestamp: Option<i64>,
+    ) -> ServiceResult<Transaction> {
+        if !self._is_friend_of_user(user_id, friend_id).await? {
+            return Err(ServiceError::NotFound("friend_not_found"));
+        }
+
+        validate_transaction(&debtor_ids, amount, &description, timestamp)
+            .check(
+                debtor_ids
+                    .iter()
+                    .all(|debtor| debtor == user_id || debtor == friend_id),
+                "debtor_ids",
+                "not_a_party",
+                "The debtors have to be the user, the friend or both of them",
+            )
+            .finish()?;
+
+        let (first_user_id, second_user_id) = Self::_ordered_pair(user_id, friend_id);
+        let transaction_id = uuid::Uuid::new_v4().to_string();
+
+        let txn = self.db.begin().await?;
+
+        let count_of_unequally_charged_debts = get_count_of_unequally_charged_debts(
+            &txn,
+            model::direct_debt::Entity::find()
+                .inner_join(model::direct_transaction::Entity)
+                .filter(model::direct_transaction::Column::FirstUserId.eq(first_user_id))
+                .filter(model::direct_transaction::Column::SecondUserId.eq(second_user_id)),
+            model::direct_debt::Column::DebtorId,
+            model::direct_debt::Column::WasSplitUnequally,
+        )
+        .await?;
+
+        model::direct_transaction::Entity::insert(model::direct_transaction::ActiveModel {
+            id: ActiveValue::Set(transaction_id.to_owned()),
+            first_user_id: ActiveValue::Set(first_user_id.to_owned()),
+            second_user_id: ActiveValue::Set(second_user_id.to_owned()),
+            creditor_id: ActiveValue::Set(user_id.to_owned()),
+            timestamp: ActiveValue::Set(timestamp_or_now(timestamp)),
+            description: ActiveValue::Set(description),
+        })
+        .exec(&txn)
+        .await?;
+
+        let debts = split_amount(debtor_ids, amount, &count_of_unequally_charged_debts)
+            .into_iter()
+            .map(
+                |(debtor, amount, was_split_unequally)| model::direct_debt::ActiveModel {
+                    transaction_id: ActiveValue::Set(transaction_id.to_owned()),
+                    debtor_id: ActiveValue::Set(debtor),
+                    amount: ActiveValue::Set(amount as i64),
+                    was_split_unequally: ActiveValue::Set(was_split_unequally),
+                },
+            );
+
+        model::direct_debt::Entity::insert_many(debts)
+            .exec(&txn)
+            .await?;
+
+        txn.commit().await?;
+
+        Ok(self
+            ._get_transactions_help(model::direct_transaction::Entity::find_by_id(
+                transaction_id,
+            ))
+            .await?
+            .pop()
+            .unwrap())
+    }
+
+    /// Only the creditor can delete a transaction
+    pub async fn delete_transaction(
+        &self,
+        user_id: &str,
+        friend_id: &str,
+        transaction_id: &str,
+    ) -> ServiceResult<()> {
+        let (first_user_id, second_user_id) = Self::_ordered_pair(user_id, friend_id);
+
+        let res = model::direct_transaction::Entity::delete_many()
+            .filter(model::direct_transaction::Column::Id.eq(transaction_id))
+            .filter(model::direct_transaction::Column::FirstUserId.eq(first_user_id))
+            .filter(model::direct_transaction::Column::SecondUserId.eq(second_user_id))
+            .filter(model::direct_transaction::Column::CreditorId.eq(user_id))
+            .exec(self.db.as_ref())
+            .await?;
+
+        match res.rows_affected {
+            0 => Err(ServiceError::NotFound("transaction_not_found")),
+            _ => Ok(()),
+        }
+    }
+
+    async fn _get_transactions_help(
+        &self,
+        select: Select<model::direct_transaction::Entity>,
+    ) -> ServiceResult<Vec<Transaction>> {
+        Ok(select
+            .find_with_related(model::direct_debt::Entity)
+            .order_by(model::direct_transaction::Column::Timestamp, Order::Desc)
+            .all(self.db.as_ref())
+            .await?
+            .into_iter()
+            .map(|(transaction, debt)| Transaction {
+                id: transaction.id,
+                group_id: None,
+                timestamp: transaction.timestamp,
+                description: transaction.description,
+                creditor_id: transaction.creditor_id,
+                debts: debt
+                    .into_iter()
+                    .map(|debt| Debt {
+                        debtor_id: debt.debtor_id,
+                        amount: debt.amount,
+                        was_split_unequally: debt.was_split_unequally,
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<Transaction>>())
+    }
+
+    /// Balance of the user with each other user they share a direct ledger with
+    async fn _get_balances_of_user(&self, user_id: &str) -> ServiceResult<HashMap<String, i64>> {
+        let backend = self.db.get_database_backend();
+
+        let debts_of_user = model::direct_debt::Entity::find()
+            .select_only()
+            .column_as(
+                model::direct_transaction::Column::CreditorId,
+                "counterparty_id",
+            )
+            .column_as(
+                sum_as_i64(model::direct_debt::Column::Amount, backend),
+                "amount",
+            )
+            .inner_join(model::direct_transaction::Entity)
+            .filter(model::direct_debt::Column::DebtorId.eq(user_id))
+            .filter(model::direct_transaction::Column::CreditorId.ne(user_id))
+            .group_by(model::direct_transaction::Column::CreditorId)
+            .into_model::<BalanceWithFriend>()
+            .all(self.db.as_ref())
+            .await?;
+
+        let credits_of_user = model::direct_debt::Entity::find()
+            .select_only()
+            .column_as(model::direct_debt::Column::DebtorId, "counterparty_id")
+            .column_as(
+                sum_as_i64(model::direct_debt::Column::Amount, backend),
+                "amount",
+            )
+            .inner_join(model::direct_transaction::Entity)
+            .filter(model::direct_transaction::Column::CreditorId.eq(user_id))
+            .filter(model::direct_debt::Column::DebtorId.ne(user_id))
+            .group_by(model::direct_debt::Column::DebtorId)
+            .into_model::<BalanceWithFriend>()
+            .all(self.db.as_ref())
+            .await?;
+
+        let mut balances: HashMap<String, i64> = HashMap::new();
+
+        for credit in credits_of_user {
+            *balances.entry(credit.counterparty_id).or_insert(0) += credit.amount;
+        }
+
+        for debt in debts_of_user {
+            *balances.entry(debt.counterparty_id).or_insert(0) -= debt.amount;
+        }
+
+        Ok(balances)
+    }
+
+    async fn _is_friend_of_user(&self, user_id: &str, friend_id: &str) -> ServiceResult<bool> {
+        Ok(
+            model::friend::Entity::find_by_id((user_id.to_owned(), friend_id.to_owned()))
+                .one(self.db.as_ref())
+                .await?
+                .is_some(),
+        )
+    }
+
+    /// Direct transactions are stored with the smaller user id first
+    fn _ordered_pair<'a>(user_id: &'a str, friend_id: &'a str) -> (&'a str, &'a str) {
+        if user_id < friend_id {
+            (user_id, friend_id)
+        } else {
+            (friend_id, user_id)
+        }
+    }
+}
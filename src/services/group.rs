@@ -1,69 +1,206 @@
 use crate::model::{self};
 use ::serde::{Deserialize, Serialize};
 use futures::future;
+use sea_orm::sea_query::{Alias, Query, SimpleExpr};
 use sea_orm::*;
+use std::cmp::Reverse;
 use std::time::{SystemTime, UNIX_EPOCH};
-use std::{collections::HashMap, sync::Arc};
-
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, Weak},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use utoipa::ToSchema;
+
+use super::error::{ServiceError, ServiceResult, Validator};
 use super::user::User;
+use super::webhook::WebhookService;
+
+/// Maximum length of group names and transaction descriptions in characters
+pub const MAX_TEXT_LENGTH: usize = 255;
+
+/// Events of a group buffered for every subscriber, slower subscribers miss events
+const EVENT_BUFFER_SIZE: usize = 128;
+
+/// The event channels of the groups which currently have subscribers
+type EventSenders = Mutex<HashMap<String, broadcast::Sender<GroupEvent>>>;
+
+/// Maximum amount of a transaction in minor units. Larger integers cannot be represented
+/// exactly by JavaScript clients.
+pub const MAX_AMOUNT: u64 = (1 << 53) - 1;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// How far transactions may be dated into the future. Catches timestamps in milliseconds
+/// instead of seconds, which are about 50,000 years ahead.
+pub const MAX_TIMESTAMP_AHEAD_SECONDS: i64 = 100 * 365 * 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct GroupMember {
-    id: String,
-    nickname: String,
-    is_owner: bool,
+    pub id: String,
+    pub nickname: String,
+    pub is_owner: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct Group {
-    id: String,
-    name: String,
-    members: Vec<GroupMember>,
+    pub id: String,
+    pub name: String,
+    pub members: Vec<GroupMember>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Debug, ToSchema)]
 pub struct Debt {
     pub debtor_id: String,
-    pub amount: i32,
+    pub amount: i64,
     pub was_split_unequally: bool,
 }
 
-#[derive(Serialize)]
+/// A transaction either belongs to a group or to the direct ledger of two friends,
+/// in which case `group_id` is omitted.
+#[derive(Serialize, Debug, ToSchema)]
 pub struct Transaction {
-    id: String,
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<String>,
+    pub timestamp: i64,
+    pub description: String,
+    pub creditor_id: String,
+    pub debts: Vec<Debt>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct GroupBalance {
+    pub group_id: String,
+    pub name: String,
+    pub amount: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct UserBalance {
+    pub user_id: String,
+    pub amount: i64,
+}
+
+/// Balance of a user across all of their groups.
+/// Positive amounts are owed to the user, negative amounts are owed by the user.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Balance {
+    pub amount: i64,
+    pub groups: Vec<GroupBalance>,
+    pub users: Vec<UserBalance>,
+}
+
+/// A change of a group, published after it has been committed
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event", content = "data")]
+pub enum GroupEvent {
+    #[serde(rename = "transaction.created")]
+    TransactionCreated(Transaction),
+    #[serde(rename = "transaction.deleted")]
+    TransactionDeleted { id: String },
+    #[serde(rename = "member.added")]
+    MemberAdded(GroupMember),
+    #[serde(rename = "member.removed")]
+    MemberRemoved { id: String },
+    /// Only sent to subscribers, the webhooks are deleted with the group
+    #[serde(rename = "group.deleted")]
+    GroupDeleted,
+    /// Not sent at all, ends the subscriptions of a user who was disabled or deleted
+    #[serde(skip)]
+    AccessRevoked { user_id: String },
+}
+
+impl GroupEvent {
+    pub const NAMES: [&'static str; 4] = [
+        "transaction.created",
+        "transaction.deleted",
+        "member.added",
+        "member.removed",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GroupEvent::TransactionCreated(_) => "transaction.created",
+            GroupEvent::TransactionDeleted { .. } => "transaction.deleted",
+            GroupEvent::MemberAdded(_) => "member.added",
+            GroupEvent::MemberRemoved { .. } => "member.removed",
+            GroupEvent::GroupDeleted => "group.deleted",
+            GroupEvent::AccessRevoked { .. } => "access.revoked",
+        }
+    }
+
+    pub fn changes_balances(&self) -> bool {
+        matches!(
+            self,
+            GroupEvent::TransactionCreated(_) | GroupEvent::TransactionDeleted { .. }
+        )
+    }
+}
+
+/// Receives the events of one group, see [`GroupService::subscribe_to_group`]
+pub struct GroupEventReceiver {
     group_id: String,
-    timestamp: u32,
-    description: String,
-    creditor_id: String,
-    debts: Vec<Debt>,
+    receiver: broadcast::Receiver<GroupEvent>,
+    senders: Weak<EventSenders>,
 }
 
-pub enum TransactionCreationError {
-    GroupNotFound,
-    DebtorNotInGroup,
+impl GroupEventReceiver {
+    /// Returns `None` once the group was deleted or the service is gone and `Some(Err(_))`
+    /// with the number of missed events if the receiver could not keep up
+    pub async fn recv(&mut self) -> Option<Result<GroupEvent, u64>> {
+        match self.receiver.recv().await {
+            Ok(event) => Some(Ok(event)),
+            Err(RecvError::Lagged(missed)) => Some(Err(missed)),
+            Err(RecvError::Closed) => None,
+        }
+    }
+}
+
+impl Drop for GroupEventReceiver {
+    /// Removes the channel of the group with its last subscriber
+    fn drop(&mut self) {
+        let senders = match self.senders.upgrade() {
+            Some(senders) => senders,
+            None => return,
+        };
+        let mut senders = senders.lock().unwrap();
+
+        // this receiver is only dropped afterwards. The channel of a deleted group is
+        // already gone, its id is never subscribed to again.
+        let is_last = match senders.get(&self.group_id) {
+            Some(sender) => sender.receiver_count() <= 1,
+            None => false,
+        };
+        if is_last {
+            senders.remove(&self.group_id);
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct GroupService {
     db: Arc<DatabaseConnection>,
+    webhook_service: Arc<WebhookService>,
+    /// Shared with the receivers, so they can remove the channel of their group
+    events: Arc<EventSenders>,
 }
 
 #[derive(FromQueryResult)]
 struct CountOfUnequallyChargedDebts {
     debtor_id: String,
-    count_of_unequally_charged_debts: u32,
+    count_of_unequally_charged_debts: i64,
 }
 
 #[derive(FromQueryResult)]
 struct DebtWithUserInGroup {
-    creditor_id: String,
-    debt: i32,
+    counterparty_id: String,
+    amount: i64,
 }
 
 #[derive(FromQueryResult)]
-struct CreditWithUserInGroup {
-    debtor_id: String,
-    credit: i32,
+struct BalanceWithUserInGroup {
+    group_id: String,
+    counterparty_id: String,
+    amount: i64,
 }
 
 impl Clone for Debt {
@@ -81,7 +218,7 @@ impl Clone for Transaction {
         Transaction {
             id: self.id.clone(),
             group_id: self.group_id.clone(),
-            timestamp: self.timestamp.clone(),
+            timestamp: self.timestamp,
             description: self.description.clone(),
             creditor_id: self.creditor_id.clone(),
             debts: self.debts.clone(),
@@ -90,29 +227,49 @@ impl Clone for Transaction {
 }
 
 impl GroupService {
-    pub fn new(db: Arc<DatabaseConnection>) -> GroupService {
-        GroupService { db: db }
+    pub fn new(db: Arc<DatabaseConnection>, webhook_service: Arc<WebhookService>) -> GroupService {
+        GroupService {
+            db,
+            webhook_service,
+            events: Arc::new(Mutex::new(HashMap::new())),
+        }
     }
 
-    pub async fn create_group(&self, name: String, owner: User) -> Group {
+    pub async fn create_group(&self, name: String, owner: User) -> ServiceResult<Group> {
+        let name = name.trim().to_owned();
+
+        Validator::new()
+            .check(
+                !name.is_empty(),
+                "name",
+                "empty",
+                "The name must not be empty",
+            )
+            .check(
+                name.chars().count() <= MAX_TEXT_LENGTH,
+                "name",
+                "too_long",
+                &format!("The name must not exceed {} characters", MAX_TEXT_LENGTH),
+            )
+            .finish()?;
+
         let new_group_id = uuid::Uuid::new_v4().to_string();
 
         let new_group = model::group::ActiveModel {
             id: ActiveValue::Set(new_group_id.to_owned()),
             name: ActiveValue::Set(name.to_owned()),
+            external_id: ActiveValue::Set(None),
         };
 
-        model::group::Entity::insert(new_group)
-            .exec(self.db.as_ref())
-            .await
-            .expect("error creating group");
+        let txn = self.db.begin().await?;
 
-        assert!(
-            self.create_group_member(new_group_id.to_owned(), owner.id.to_owned(), true)
-                .await
-        );
+        model::group::Entity::insert(new_group).exec(&txn).await?;
+
+        Self::_create_group_member(&txn, &new_group_id, &owner.id, true).await?;
 
-        Group {
+        txn.commit().await?;
+
+        Ok(Group {
             id: new_group_id,
             name: name,
             members: vec![GroupMember {
@@ -120,7 +277,7 @@ impl GroupService {
                 nickname: owner.nickname,
                 is_owner: true,
             }],
-        }
+        })
     }
 
     pub async fn create_group_member(
@@ -128,60 +285,202 @@ impl GroupService {
         group_id: String,
         user_id: String,
         is_owner: bool,
-    ) -> bool {
-        let res = model::group_member::Entity::insert(model::group_member::ActiveModel {
-            user_id: ActiveValue::Set(user_id),
-            group_id: ActiveValue::Set(group_id),
-            is_owner: ActiveValue::Set(if is_owner { 1 } else { 0 }),
-        })
-        .exec(self.db.as_ref())
-        .await;
+    ) -> ServiceResult<()> {
+        model::group::Entity::find_by_id(group_id.to_owned())
+            .one(self.db.as_ref())
+            .await?
+            .ok_or(ServiceError::NotFound("group_not_found"))?;
+
+        if Self::_is_user_member_of_group(self.db.as_ref(), &group_id, &user_id).await? {
+            return Err(ServiceError::Conflict("already_a_member"));
+        }
+
+        Self::_create_group_member(self.db.as_ref(), &group_id, &user_id, is_owner).await?;
+
+        self._publish_member_added(&group_id, &user_id).await
+    }
+
+    /// Makes the user a member of the groups with the given names, which come from an external
+    /// source like the groups header of an authentication proxy. Missing groups are created.
+    /// If `remove_stale` is set, the user is removed from all other groups of the same source.
+    pub async fn sync_external_groups_of_user(
+        &self,
+        user_id: &str,
+        source: &str,
+        group_names: Vec<String>,
+        remove_stale: bool,
+    ) -> ServiceResult<()> {
+        let external_ids = group_names
+            .iter()
+            .map(|name| format!("{}:{}", source, name))
+            .collect::<Vec<String>>();
+
+        let txn = self.db.begin().await?;
+
+        let mut joined_group_ids = vec![];
+        for (name, external_id) in group_names.into_iter().zip(external_ids.iter()) {
+            let group_id = Self::_get_or_create_external_group(&txn, name, external_id).await?;
+
+            if !Self::_is_user_member_of_group(&txn, &group_id, user_id).await? {
+                Self::_create_group_member(&txn, &group_id, user_id, false).await?;
+                joined_group_ids.push(group_id);
+            }
+        }
+
+        let left_group_ids = match remove_stale {
+            true => {
+                Self::_remove_stale_external_groups_of_user(&txn, user_id, source, external_ids)
+                    .await?
+            }
+            false => vec![],
+        };
+
+        txn.commit().await?;
+
+        for group_id in joined_group_ids {
+            self._publish_member_added(&group_id, user_id).await?;
+        }
+        for group_id in left_group_ids {
+            self._publish(
+                &group_id,
+                GroupEvent::MemberRemoved {
+                    id: user_id.to_owned(),
+                },
+            )
+            .await;
+        }
+
+        Ok(())
+    }
 
-        match res {
-            Ok(_) => true,
-            Err(_) => false,
+    /// The names of the groups of the source which the user is a member of
+    pub async fn get_external_group_names_of_user(
+        &self,
+        user_id: &str,
+        source: &str,
+    ) -> ServiceResult<HashSet<String>> {
+        let prefix = format!("{}:", source);
+
+        Ok(model::group_member::Entity::find()
+            .filter(model::group_member::Column::UserId.eq(user_id))
+            .find_also_related(model::group::Entity)
+            .filter(model::group::Column::ExternalId.like(&format!("{}%", prefix)))
+            .all(self.db.as_ref())
+            .await?
+            .into_iter()
+            .filter_map(|(_, group)| group?.external_id)
+            .filter_map(|external_id| external_id.strip_prefix(&prefix).map(str::to_owned))
+            .collect())
+    }
+
+    async fn _remove_stale_external_groups_of_user<C: ConnectionTrait>(
+        db: &C,
+        user_id: &str,
+        source: &str,
+        external_ids: Vec<String>,
+    ) -> ServiceResult<Vec<String>> {
+        let stale_group_ids = model::group_member::Entity::find()
+            .filter(model::group_member::Column::UserId.eq(user_id))
+            .find_also_related(model::group::Entity)
+            .filter(model::group::Column::ExternalId.like(&format!("{}:%", source)))
+            .filter(model::group::Column::ExternalId.is_not_in(external_ids))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|(group_member, _)| group_member.group_id)
+            .collect::<Vec<String>>();
+
+        if stale_group_ids.is_empty() {
+            return Ok(vec![]);
         }
+
+        model::group_member::Entity::delete_many()
+            .filter(model::group_member::Column::UserId.eq(user_id))
+            .filter(model::group_member::Column::GroupId.is_in(stale_group_ids.clone()))
+            .exec(db)
+            .await?;
+
+        Ok(stale_group_ids)
     }
 
-    pub async fn get_groups_of_user(&self, user_id: String) -> Vec<Group> {
+    pub async fn get_groups_of_user(&self, user_id: String) -> ServiceResult<Vec<Group>> {
         let groups = model::group_member::Entity::find()
             .filter(model::group_member::Column::UserId.eq(user_id))
             .find_also_related(model::group::Entity)
             .all(self.db.as_ref())
-            .await
-            .expect("failed to query groups of user")
+            .await?
+            .into_iter()
+            .filter_map(|(_, group)| group)
+            .map(|group| self._populate_group_members_and_debt(group));
+
+        future::try_join_all(groups).await
+    }
+
+    pub async fn get_all_groups(&self) -> ServiceResult<Vec<Group>> {
+        let groups = model::group::Entity::find()
+            .order_by_asc(model::group::Column::Name)
+            .all(self.db.as_ref())
+            .await?
             .into_iter()
-            .map(|(_, group)| self._populate_group_members_and_debt(group.unwrap()));
+            .map(|group| self._populate_group_members_and_debt(group));
 
-        future::join_all(groups).await
+        future::try_join_all(groups).await
     }
 
-    pub async fn get_group_of_user(&self, group_id: String, user_id: String) -> Option<Group> {
+    /// Deletes the group including all of its transactions and memberships
+    pub async fn delete_group(&self, group_id: &str) -> ServiceResult<()> {
+        let res = model::group::Entity::delete_by_id(group_id.to_owned())
+            .exec(self.db.as_ref())
+            .await?;
+
+        if res.rows_affected == 0 {
+            return Err(ServiceError::NotFound("group_not_found"));
+        }
+
+        // the subscriptions end after this event
+        let sender = self.events.lock().unwrap().remove(group_id);
+        if let Some(sender) = sender {
+            let _ = sender.send(GroupEvent::GroupDeleted);
+        }
+
+        Ok(())
+    }
+
+    /// Ends the subscriptions of a user who was disabled or deleted
+    pub fn revoke_access_of_user(&self, user_id: &str) {
+        // the user might be subscribed to any group
+        for sender in self.events.lock().unwrap().values() {
+            let _ = sender.send(GroupEvent::AccessRevoked {
+                user_id: user_id.to_owned(),
+            });
+        }
+    }
+
+    pub async fn get_group_of_user(
+        &self,
+        group_id: String,
+        user_id: String,
+    ) -> ServiceResult<Group> {
         let group = model::group_member::Entity::find()
             .filter(model::group_member::Column::UserId.eq(user_id))
             .filter(model::group_member::Column::GroupId.eq(group_id))
             .find_also_related(model::group::Entity)
             .one(self.db.as_ref())
-            .await
-            .expect("error querying group")
-            .map(|(_, group)| group.unwrap());
+            .await?
+            .and_then(|(_, group)| group)
+            .ok_or(ServiceError::NotFound("group_not_found"))?;
 
-        match group {
-            None => None,
-            Some(group) => Some(self._populate_group_members_and_debt(group).await),
-        }
+        self._populate_group_members_and_debt(group).await
     }
 
     pub async fn get_members_of_group_of_user(
         &self,
         group_id: String,
         user_id: String,
-    ) -> Option<Vec<GroupMember>> {
-        if !self._is_user_member_of_group(&group_id, &user_id).await {
-            return None;
-        }
+    ) -> ServiceResult<Vec<GroupMember>> {
+        Self::_ensure_user_is_member_of_group(self.db.as_ref(), &group_id, &user_id).await?;
 
-        Some(self._get_group_members(&group_id).await)
+        Self::_get_group_members(self.db.as_ref(), &group_id).await
     }
 
     pub async fn create_transaction(
@@ -189,116 +488,229 @@ impl GroupService {
         group_id: String,
         creditor_id: String,
         debtor_ids: Vec<String>,
-        amount: u32,
+        amount: u64,
         description: String,
-        timestamp: Option<u32>,
-    ) -> Result<Transaction, TransactionCreationError> {
-        let members = self
-            ._get_group_members(&group_id)
-            .await
+        timestamp: Option<i64>,
+    ) -> ServiceResult<Transaction> {
+        let txn = self.db.begin().await?;
+
+        let members = Self::_get_group_members(&txn, &group_id)
+            .await?
             .into_iter()
             .map(|m| m.id)
             .collect::<Vec<String>>();
 
-        println!("{:?}", members);
-
-        if members.len() == 0 || !members.contains(&creditor_id) {
-            return Err(TransactionCreationError::GroupNotFound);
+        if members.is_empty() || !members.contains(&creditor_id) {
+            return Err(ServiceError::NotFound("group_not_found"));
         }
 
-        let mut all_debtors_in_group = true;
+        validate_transaction(&debtor_ids, amount, &description, timestamp)
+            .check(
+                debtor_ids.iter().all(|debtor| members.contains(debtor)),
+                "debtor_ids",
+                "not_a_member",
+                "All debtors have to be members of the group",
+            )
+            .finish()?;
+
+        let transaction = Self::_create_transaction_with_debt(
+            &txn,
+            group_id.clone(),
+            creditor_id,
+            debtor_ids,
+            amount,
+            description,
+            timestamp,
+        )
+        .await?;
 
-        for debtor in &debtor_ids {
-            all_debtors_in_group = all_debtors_in_group && members.contains(debtor);
-        }
+        txn.commit().await?;
 
-        if !all_debtors_in_group {
-            return Err(TransactionCreationError::DebtorNotInGroup);
-        }
+        self._publish(
+            &group_id,
+            GroupEvent::TransactionCreated(transaction.clone()),
+        )
+        .await;
 
-        Ok(self
-            ._create_transaction_with_debt(
-                group_id,
-                creditor_id,
-                debtor_ids,
-                amount,
-                description,
-                timestamp,
-            )
-            .await)
+        Ok(transaction)
     }
 
     pub async fn get_transactions_of_group_of_user(
         &self,
         group_id: String,
         user_id: String,
-    ) -> Option<Vec<Transaction>> {
-        if !self._is_user_member_of_group(&group_id, &user_id).await {
-            return None;
-        }
+    ) -> ServiceResult<Vec<Transaction>> {
+        Self::_ensure_user_is_member_of_group(self.db.as_ref(), &group_id, &user_id).await?;
 
-        Some(
-            self._get_tansactions_help(
-                model::transaction::Entity::find()
-                    .filter(model::transaction::Column::GroupId.eq(group_id)),
-            )
-            .await,
+        Self::_get_tansactions_help(
+            self.db.as_ref(),
+            model::transaction::Entity::find()
+                .filter(model::transaction::Column::GroupId.eq(group_id)),
         )
+        .await
+    }
+
+    /// All transactions of all groups which the user either paid or owes on
+    pub async fn get_transactions_of_user(&self, user_id: &str) -> ServiceResult<Vec<Transaction>> {
+        Self::_get_tansactions_help(
+            self.db.as_ref(),
+            model::transaction::Entity::find().filter(
+                Condition::any()
+                    .add(model::transaction::Column::CreditorId.eq(user_id))
+                    .add(
+                        model::transaction::Column::Id.in_subquery(
+                            Query::select()
+                                .column(model::debt::Column::TransactionId)
+                                .from(model::debt::Entity)
+                                .and_where(model::debt::Column::DebtorId.eq(user_id))
+                                .to_owned(),
+                        ),
+                    ),
+            ),
+        )
+        .await
     }
 
     pub async fn get_debts_of_user_in_group(
         &self,
         group_id: &str,
         user_id: &str,
-    ) -> Option<Vec<Debt>> {
-        if !self._is_user_member_of_group(group_id, user_id).await {
-            return None;
-        }
+    ) -> ServiceResult<Vec<Debt>> {
+        Self::_ensure_user_is_member_of_group(self.db.as_ref(), group_id, user_id).await?;
+
+        let backend = self.db.get_database_backend();
 
         let debts_of_user = model::debt::Entity::find()
-            .find_also_related(model::transaction::Entity)
+            .select_only()
+            .column_as(model::transaction::Column::CreditorId, "counterparty_id")
+            .column_as(sum_as_i64(model::debt::Column::Amount, backend), "amount")
+            .inner_join(model::transaction::Entity)
             .filter(model::transaction::Column::GroupId.eq(group_id))
             .filter(model::debt::Column::DebtorId.eq(user_id))
             .group_by(model::transaction::Column::CreditorId)
-            .column_as(model::debt::Column::Amount.sum(), "B_debt")
-            .into_model::<model::debt::Model, DebtWithUserInGroup>()
+            .into_model::<DebtWithUserInGroup>()
             .all(self.db.as_ref())
-            .await
-            .expect("error querying debt of user in group")
+            .await?
             .into_iter()
-            .map(|(_, debt)| {
-                let debt = debt.unwrap();
-                (debt.creditor_id, debt.debt)
-            })
-            .collect::<HashMap<String, i32>>();
+            .map(|debt| (debt.counterparty_id, debt.amount))
+            .collect::<HashMap<String, i64>>();
 
         let credits_of_user = model::debt::Entity::find()
-            .find_also_related(model::transaction::Entity)
+            .select_only()
+            .column_as(model::debt::Column::DebtorId, "counterparty_id")
+            .column_as(sum_as_i64(model::debt::Column::Amount, backend), "amount")
+            .inner_join(model::transaction::Entity)
             .filter(model::transaction::Column::GroupId.eq(group_id))
             .filter(model::transaction::Column::CreditorId.eq(user_id))
             .group_by(model::debt::Column::DebtorId)
-            .column_as(model::debt::Column::Amount.sum(), "A_credit")
-            .into_model::<CreditWithUserInGroup, model::transaction::Model>()
+            .into_model::<DebtWithUserInGroup>()
             .all(self.db.as_ref())
-            .await
-            .expect("error querying credit of user in group")
+            .await?
             .into_iter()
-            .map(|(credit, _)| (credit.debtor_id, credit.credit))
-            .collect::<HashMap<String, i32>>();
-
-        Some(
-            self._get_group_members(&group_id)
-                .await
-                .into_iter()
-                .filter(|member| member.id != user_id)
-                .map(|member| Debt {
-                    debtor_id: member.id.to_owned(),
-                    amount: credits_of_user.get(&member.id).unwrap_or(&0)
-                        - debts_of_user.get(&member.id).unwrap_or(&0),
-                    was_split_unequally: false,
-                })
-                .collect::<Vec<Debt>>(),
-        )
+            .map(|credit| (credit.counterparty_id, credit.amount))
+            .collect::<HashMap<String, i64>>();
+
+        Ok(Self::_get_group_members(self.db.as_ref(), group_id)
+            .await?
+            .into_iter()
+            .filter(|member| member.id != user_id)
+            .map(|member| Debt {
+                debtor_id: member.id.to_owned(),
+                amount: credits_of_user.get(&member.id).unwrap_or(&0)
+                    - debts_of_user.get(&member.id).unwrap_or(&0),
+                was_split_unequally: false,
+            })
+            .collect::<Vec<Debt>>())
+    }
+
+    /// Nets the debts and credits of the user across all of their groups,
+    /// using one query for all debts and one for all credits.
+    pub async fn get_balance_of_user(&self, user_id: &str) -> ServiceResult<Balance> {
+        let backend = self.db.get_database_backend();
+        // debts in groups the user was removed from are not part of the balance, like the
+        // groups themselves
+        let groups_of_user = Query::select()
+            .column(model::group_member::Column::GroupId)
+            .from(model::group_member::Entity)
+            .and_where(model::group_member::Column::UserId.eq(user_id))
+            .to_owned();
+
+        let debts_of_user = model::debt::Entity::find()
+            .select_only()
+            .column(model::transaction::Column::GroupId)
+            .column_as(model::transaction::Column::CreditorId, "counterparty_id")
+            .column_as(sum_as_i64(model::debt::Column::Amount, backend), "amount")
+            .inner_join(model::transaction::Entity)
+            .filter(model::debt::Column::DebtorId.eq(user_id))
+            .filter(model::transaction::Column::CreditorId.ne(user_id))
+            .filter(model::transaction::Column::GroupId.in_subquery(groups_of_user.to_owned()))
+            .group_by(model::transaction::Column::GroupId)
+            .group_by(model::transaction::Column::CreditorId)
+            .into_model::<BalanceWithUserInGroup>()
+            .all(self.db.as_ref())
+            .await?;
+
+        let credits_of_user = model::debt::Entity::find()
+            .select_only()
+            .column(model::transaction::Column::GroupId)
+            .column_as(model::debt::Column::DebtorId, "counterparty_id")
+            .column_as(sum_as_i64(model::debt::Column::Amount, backend), "amount")
+            .inner_join(model::transaction::Entity)
+            .filter(model::transaction::Column::CreditorId.eq(user_id))
+            .filter(model::debt::Column::DebtorId.ne(user_id))
+            .filter(model::transaction::Column::GroupId.in_subquery(groups_of_user))
+            .group_by(model::transaction::Column::GroupId)
+            .group_by(model::debt::Column::DebtorId)
+            .into_model::<BalanceWithUserInGroup>()
+            .all(self.db.as_ref())
+            .await?;
+
+        let mut amount_per_group: HashMap<String, i64> = HashMap::new();
+        let mut amount_per_user: HashMap<String, i64> = HashMap::new();
+
+        let balances = credits_of_user
+            .into_iter()
+            .chain(
+                debts_of_user
+                    .into_iter()
+                    .map(|debt| BalanceWithUserInGroup {
+                        amount: -debt.amount,
+                        ..debt
+                    }),
+            );
+
+        for balance in balances {
+            *amount_per_group.entry(balance.group_id).or_insert(0) += balance.amount;
+            *amount_per_user.entry(balance.counterparty_id).or_insert(0) += balance.amount;
+        }
+
+        let groups = model::group_member::Entity::find()
+            .filter(model::group_member::Column::UserId.eq(user_id))
+            .find_also_related(model::group::Entity)
+            .order_by_asc(model::group::Column::Name)
+            .all(self.db.as_ref())
+            .await?
+            .into_iter()
+            .filter_map(|(_, group)| group)
+            .map(|group| GroupBalance {
+                amount: *amount_per_group.get(&group.id).unwrap_or(&0),
+                group_id: group.id,
+                name: group.name,
+            })
+            .collect::<Vec<GroupBalance>>();
+
+        let mut users = amount_per_user
+            .into_iter()
+            .filter(|(_, amount)| *amount != 0)
+            .map(|(user_id, amount)| UserBalance { user_id, amount })
+            .collect::<Vec<UserBalance>>();
+        users.sort_by(|a, b| a.user_id.cmp(&b.user_id));
+
+        Ok(Balance {
+            amount: users.iter().map(|user| user.amount).sum(),
+            groups,
+            users,
+        })
     }
 
     pub async fn delete_transaction(
@@ -306,53 +718,127 @@ impl GroupService {
         group_id: &str,
         user_id: &str,
         transaction_id: &str,
-    ) -> bool {
+    ) -> ServiceResult<()> {
+        let txn = self.db.begin().await?;
+
         let transaction_to_delete = model::transaction::Entity::find()
             .filter(model::transaction::Column::Id.eq(transaction_id))
             .filter(model::transaction::Column::GroupId.eq(group_id))
             .filter(model::transaction::Column::CreditorId.eq(user_id))
+            .one(&txn)
+            .await?
+            .ok_or(ServiceError::NotFound("transaction_not_found"))?;
+
+        model::debt::Entity::delete_many()
+            .filter(model::debt::Column::TransactionId.eq(transaction_id))
+            .exec(&txn)
+            .await?;
+
+        transaction_to_delete.delete(&txn).await?;
+
+        txn.commit().await?;
+
+        self._publish(
+            group_id,
+            GroupEvent::TransactionDeleted {
+                id: transaction_id.to_owned(),
+            },
+        )
+        .await;
+
+        Ok(())
+    }
+
+    /// Members which are not owners are reported as forbidden, other users as not found
+    pub async fn ensure_user_is_owner_of_group(
+        &self,
+        group_id: &str,
+        user_id: &str,
+    ) -> ServiceResult<()> {
+        let member = model::group_member::Entity::find()
+            .filter(model::group_member::Column::GroupId.eq(group_id))
+            .filter(model::group_member::Column::UserId.eq(user_id))
             .one(self.db.as_ref())
-            .await
-            .expect("error querying transaction");
+            .await?
+            .ok_or(ServiceError::NotFound("group_not_found"))?;
 
-        match transaction_to_delete {
-            None => false,
-            Some(t) => {
-                t.delete(self.db.as_ref())
-                    .await
-                    .expect("error deleting transaction")
-                    .rows_affected
-                    > 0
-            }
+        match member.is_owner {
+            true => Ok(()),
+            false => Err(ServiceError::Forbidden("not_an_owner")),
         }
     }
 
-    async fn _get_transaction_by_id(&self, transaction_id: String) -> Option<Transaction> {
-        match self
-            ._get_tansactions_help(model::transaction::Entity::find_by_id(transaction_id))
-            .await
-            .first()
-        {
-            Some(t) => Some(t.clone()),
-            None => None,
+    /// Events of the group from now on, only members can subscribe
+    pub async fn subscribe_to_group(
+        &self,
+        group_id: &str,
+        user_id: &str,
+    ) -> ServiceResult<GroupEventReceiver> {
+        Self::_ensure_user_is_member_of_group(self.db.as_ref(), group_id, user_id).await?;
+
+        let receiver = self
+            .events
+            .lock()
+            .unwrap()
+            .entry(group_id.to_owned())
+            .or_insert_with(|| broadcast::channel(EVENT_BUFFER_SIZE).0)
+            .subscribe();
+
+        Ok(GroupEventReceiver {
+            group_id: group_id.to_owned(),
+            receiver,
+            senders: Arc::downgrade(&self.events),
+        })
+    }
+
+    async fn _publish(&self, group_id: &str, event: GroupEvent) {
+        self.webhook_service.dispatch(group_id, &event).await;
+
+        // only groups with subscribers have a channel
+        if let Some(sender) = self.events.lock().unwrap().get(group_id) {
+            let _ = sender.send(event);
         }
     }
 
-    async fn _get_tansactions_help(
-        &self,
+    async fn _publish_member_added(&self, group_id: &str, user_id: &str) -> ServiceResult<()> {
+        let member = Self::_get_group_members(self.db.as_ref(), group_id)
+            .await?
+            .into_iter()
+            .find(|member| member.id == user_id);
+
+        if let Some(member) = member {
+            self._publish(group_id, GroupEvent::MemberAdded(member))
+                .await;
+        }
+
+        Ok(())
+    }
+
+    async fn _get_transaction_by_id<C: ConnectionTrait>(
+        db: &C,
+        transaction_id: String,
+    ) -> ServiceResult<Option<Transaction>> {
+        Ok(
+            Self::_get_tansactions_help(db, model::transaction::Entity::find_by_id(transaction_id))
+                .await?
+                .pop(),
+        )
+    }
+
+    async fn _get_tansactions_help<C: ConnectionTrait>(
+        db: &C,
         select: Select<model::transaction::Entity>,
-    ) -> Vec<Transaction> {
-        select
+    ) -> ServiceResult<Vec<Transaction>> {
+        Ok(select
             .find_with_related(model::debt::Entity)
             .order_by(model::transaction::Column::Timestamp, Order::Desc)
-            .all(self.db.as_ref())
-            .await
-            .expect("error querying transaction")
+            .all(db)
+            .await?
             .into_iter()
             .map(|(transaction, debt)| Transaction {
                 id: transaction.id,
-                group_id: transaction.group_id,
-                timestamp: transaction.timestamp as u32,
+                group_id: Some(transaction.group_id),
+                timestamp: transaction.timestamp,
                 description: transaction.description,
                 creditor_id: transaction.creditor_id,
                 debts: debt
@@ -360,33 +846,23 @@ impl GroupService {
                     .map(|debt| Debt {
                         debtor_id: debt.debtor_id,
                         amount: debt.amount,
-                        was_split_unequally: debt.was_split_unequally == 1,
+                        was_split_unequally: debt.was_split_unequally,
                     })
                     .collect(),
             })
-            .collect::<Vec<Transaction>>()
+            .collect::<Vec<Transaction>>())
     }
 
-    async fn _create_transaction(
-        &self,
+    async fn _create_transaction<C: ConnectionTrait>(
+        db: &C,
         group_id: &str,
         creditor_id: String,
         description: String,
-        timestamp: Option<u32>,
-    ) -> String {
+        timestamp: Option<i64>,
+    ) -> ServiceResult<String> {
         let new_transaction_id = uuid::Uuid::new_v4().to_string();
 
-        let timestamp: i32 = timestamp
-            .unwrap_or(
-                SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs()
-                    .try_into()
-                    .unwrap(),
-            )
-            .try_into()
-            .unwrap();
+        let timestamp = timestamp_or_now(timestamp);
 
         let new_transaction = model::transaction::ActiveModel {
             id: ActiveValue::Set(new_transaction_id.to_owned()),
@@ -397,177 +873,479 @@ impl GroupService {
         };
 
         model::transaction::Entity::insert(new_transaction)
-            .exec(self.db.as_ref())
-            .await
-            .expect("error creating transaction");
+            .exec(db)
+            .await?;
 
-        new_transaction_id
+        Ok(new_transaction_id)
     }
 
-    async fn _create_transaction_with_debt(
-        &self,
+    async fn _create_transaction_with_debt<C: ConnectionTrait>(
+        db: &C,
         group_id: String,
         creditor_id: String,
         debtor_ids: Vec<String>,
-        amount: u32,
+        amount: u64,
         description: String,
-        timestamp: Option<u32>,
-    ) -> Transaction {
-        let transaction_id = self
-            ._create_transaction(&group_id, creditor_id, description, timestamp)
-            .await;
+        timestamp: Option<i64>,
+    ) -> ServiceResult<Transaction> {
+        let transaction_id =
+            Self::_create_transaction(db, &group_id, creditor_id, description, timestamp).await?;
 
-        let debts = self
-            ._calculate_debt_of_debtors(&group_id, debtor_ids, amount)
-            .await
+        let debts = Self::_calculate_debt_of_debtors(db, &group_id, debtor_ids, amount)
+            .await?
             .into_iter()
             .map(
                 |(debtor, amount, was_split_unequally)| model::debt::ActiveModel {
                     transaction_id: ActiveValue::Set(transaction_id.to_owned()),
                     debtor_id: ActiveValue::Set(debtor.to_owned()),
-                    amount: ActiveValue::Set(amount as i32),
-                    was_split_unequally: ActiveValue::Set(if was_split_unequally { 1 } else { 0 }),
+                    amount: ActiveValue::Set(amount as i64),
+                    was_split_unequally: ActiveValue::Set(was_split_unequally),
                 },
             )
             .collect::<Vec<model::debt::ActiveModel>>();
 
         for debt in debts {
-            model::debt::Entity::insert(debt)
-                .exec(self.db.as_ref())
-                .await
-                .expect("error creating debt");
+            model::debt::Entity::insert(debt).exec(db).await?;
         }
 
-        self._get_transaction_by_id(transaction_id).await.unwrap()
+        Ok(Self::_get_transaction_by_id(db, transaction_id)
+            .await?
+            .unwrap())
     }
 
-    async fn _calculate_debt_of_debtors(
-        &self,
+    async fn _calculate_debt_of_debtors<C: ConnectionTrait>(
+        db: &C,
         group_id: &str,
         debtor_ids: Vec<String>,
-        amount: u32,
-    ) -> Vec<(String, u32, bool)> {
-        let mut debtor_ids = debtor_ids.clone();
-        debtor_ids.sort();
-        let debtor_count: u32 = debtor_ids.len() as u32;
-        let amount_per_debtor: u32 = amount / debtor_count;
-        let mut amount_to_split_unequally: u32 = amount - amount_per_debtor * debtor_count;
-        let mut unequally_charged_debtors = Vec::new();
-        let mut potentially_unequally_charged_debtors = self
-            ._get_order_of_debtors_to_be_unequally_charged(group_id, &debtor_ids)
-            .await;
-
-        while amount_to_split_unequally > 0 {
-            let debtor = potentially_unequally_charged_debtors.pop().unwrap();
-            unequally_charged_debtors.push(debtor.clone());
-            amount_to_split_unequally -= 1;
-        }
+        amount: u64,
+    ) -> ServiceResult<Vec<(String, u64, bool)>> {
+        let count_of_unequally_charged_debts = get_count_of_unequally_charged_debts(
+            db,
+            model::debt::Entity::find()
+                .inner_join(model::transaction::Entity)
+                .filter(model::transaction::Column::GroupId.eq(group_id)),
+            model::debt::Column::DebtorId,
+            model::debt::Column::WasSplitUnequally,
+        )
+        .await?;
 
-        debtor_ids
-            .into_iter()
-            .map(|debtor| {
-                if unequally_charged_debtors.contains(&debtor) {
-                    (debtor, amount_per_debtor + 1, true)
-                } else {
-                    (debtor, amount_per_debtor, false)
-                }
-            })
-            .collect::<Vec<(String, u32, bool)>>()
+        Ok(split_amount(
+            debtor_ids,
+            amount,
+            &count_of_unequally_charged_debts,
+        ))
     }
 
-    async fn _get_order_of_debtors_to_be_unequally_charged(
-        &self,
-        group_id: &str,
-        debtor_ids: &Vec<String>,
-    ) -> Vec<String> {
-        let count_of_unequally_charged_debts = self
-            ._get_count_of_unequally_charged_debts_of_debtors_in_group(group_id)
-            .await;
+    async fn _get_or_create_external_group<C: ConnectionTrait>(
+        db: &C,
+        name: String,
+        external_id: &str,
+    ) -> ServiceResult<String> {
+        let group = model::group::Entity::find()
+            .filter(model::group::Column::ExternalId.eq(external_id))
+            .one(db)
+            .await?;
+
+        if let Some(group) = group {
+            return Ok(group.id);
+        }
 
-        let mut next_charged_debtors = debtor_ids
-            .into_iter()
-            .map(|debtor| {
-                (
-                    debtor.to_owned(),
-                    count_of_unequally_charged_debts
-                        .get(debtor)
-                        .or(Some(&0))
-                        .unwrap()
-                        .to_owned(),
-                )
-            })
-            .collect::<Vec<(String, u32)>>();
+        let new_group_id = uuid::Uuid::new_v4().to_string();
 
-        next_charged_debtors.sort_by(|a, b| b.1.cmp(&a.1));
+        model::group::Entity::insert(model::group::ActiveModel {
+            id: ActiveValue::Set(new_group_id.to_owned()),
+            name: ActiveValue::Set(name),
+            external_id: ActiveValue::Set(Some(external_id.to_owned())),
+        })
+        .exec(db)
+        .await?;
 
-        next_charged_debtors
-            .into_iter()
-            .filter(|(debtor, _)| debtor_ids.contains(debtor))
-            .map(|(debtor, _)| debtor)
-            .collect::<Vec<String>>()
+        Ok(new_group_id)
     }
 
-    async fn _get_count_of_unequally_charged_debts_of_debtors_in_group(
+    async fn _populate_group_members_and_debt(
         &self,
-        group_id: &str,
-    ) -> HashMap<String, u32> {
-        model::debt::Entity::find()
-            .find_also_related(model::transaction::Entity)
-            .filter(model::transaction::Column::GroupId.eq(group_id))
-            .group_by(model::debt::Column::DebtorId)
-            .column_as(
-                model::debt::Column::WasSplitUnequally.sum(),
-                "A_count_of_unequally_charged_debts",
-            )
-            .into_model::<CountOfUnequallyChargedDebts, model::transaction::Model>()
-            .all(self.db.as_ref())
-            .await
-            .expect("error getting counts of unequally charged debts")
-            .into_iter()
-            .map(|(debt, _)| (debt.debtor_id, debt.count_of_unequally_charged_debts))
-            .collect::<HashMap<String, u32>>()
-    }
-
-    async fn _populate_group_members_and_debt(&self, group: model::group::Model) -> Group {
-        let members = self._get_group_members(&group.id).await;
+        group: model::group::Model,
+    ) -> ServiceResult<Group> {
+        let members = Self::_get_group_members(self.db.as_ref(), &group.id).await?;
 
-        Group {
+        Ok(Group {
             id: group.id,
             name: group.name,
             members: members,
-        }
+        })
     }
 
-    async fn _get_group_members(&self, group_id: &str) -> Vec<GroupMember> {
-        model::group_member::Entity::find()
+    async fn _get_group_members<C: ConnectionTrait>(
+        db: &C,
+        group_id: &str,
+    ) -> ServiceResult<Vec<GroupMember>> {
+        Ok(model::group_member::Entity::find()
             .filter(model::group_member::Column::GroupId.eq(group_id))
             .find_also_related(model::user::Entity)
-            .all(self.db.as_ref())
-            .await
-            .expect("error loading group members")
+            .all(db)
+            .await?
             .into_iter()
-            .map(|(group_member, user)| {
-                let user = user.unwrap();
-                GroupMember {
+            .filter_map(|(group_member, user)| {
+                user.map(|user| GroupMember {
                     id: user.id,
                     nickname: user.nickname,
-                    is_owner: group_member.is_owner == 1,
-                }
+                    is_owner: group_member.is_owner,
+                })
             })
-            .collect::<Vec<GroupMember>>()
+            .collect::<Vec<GroupMember>>())
     }
 
-    async fn _is_user_member_of_group(&self, group_id: &str, user_id: &str) -> bool {
+    async fn _create_group_member<C: ConnectionTrait>(
+        db: &C,
+        group_id: &str,
+        user_id: &str,
+        is_owner: bool,
+    ) -> ServiceResult<()> {
+        model::group_member::Entity::insert(model::group_member::ActiveModel {
+            user_id: ActiveValue::Set(user_id.to_owned()),
+            group_id: ActiveValue::Set(group_id.to_owned()),
+            is_owner: ActiveValue::Set(is_owner),
+        })
+        .exec(db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn _is_user_member_of_group<C: ConnectionTrait>(
+        db: &C,
+        group_id: &str,
+        user_id: &str,
+    ) -> ServiceResult<bool> {
         let res = model::group_member::Entity::find()
             .filter(model::group_member::Column::GroupId.eq(group_id))
             .filter(model::group_member::Column::UserId.eq(user_id))
-            .one(self.db.as_ref())
-            .await
-            .expect("error querying user membership");
+            .one(db)
+            .await?;
 
-        match res {
-            Some(_) => true,
-            None => false,
+        Ok(res.is_some())
+    }
+
+    /// Groups of other users are reported as not found, so their ids are not leaked
+    async fn _ensure_user_is_member_of_group<C: ConnectionTrait>(
+        db: &C,
+        group_id: &str,
+        user_id: &str,
+    ) -> ServiceResult<()> {
+        match Self::_is_user_member_of_group(db, group_id, user_id).await? {
+            true => Ok(()),
+            false => Err(ServiceError::NotFound("group_not_found")),
         }
     }
 }
+
+/// Validates the debtors, amount and description of a new transaction.
+/// The returned validator can be extended with further checks.
+pub fn validate_transaction(
+    debtor_ids: &[String],
+    amount: u64,
+    description: &str,
+    timestamp: Option<i64>,
+) -> Validator {
+    let mut unique_debtor_ids = debtor_ids.to_vec();
+    unique_debtor_ids.sort();
+    unique_debtor_ids.dedup();
+
+    let mut validator = Validator::new();
+    validator
+        .check(
+            !debtor_ids.is_empty(),
+            "debtor_ids",
+            "empty",
+            "At least one debtor is required",
+        )
+        .check(
+            unique_debtor_ids.len() == debtor_ids.len(),
+            "debtor_ids",
+            "duplicate",
+            "Each debtor may only be listed once",
+        )
+        .check(
+            amount > 0,
+            "amount",
+            "zero",
+            "The amount must be greater than zero",
+        )
+        .check(
+            amount <= MAX_AMOUNT,
+            "amount",
+            "too_large",
+            &format!("The amount must not exceed {}", MAX_AMOUNT),
+        )
+        .check(
+            description.chars().count() <= MAX_TEXT_LENGTH,
+            "description",
+            "too_long",
+            &format!(
+                "The description must not exceed {} characters",
+                MAX_TEXT_LENGTH
+            ),
+        );
+
+    if let Some(timestamp) = timestamp {
+        validator
+            .check(
+                timestamp >= 0,
+                "timestamp",
+                "negative",
+                "The timestamp must not be before 1970",
+            )
+            .check(
+                timestamp <= timestamp_or_now(None) + MAX_TIMESTAMP_AHEAD_SECONDS,
+                "timestamp",
+                "too_far_in_future",
+                "The timestamp must be in seconds and not more than 100 years ahead",
+            );
+    }
+
+    validator
+}
+
+/// Splits the amount equally between the debtors. The cents which cannot be split equally
+/// are charged to the debtors who were charged unequally the least often so far.
+pub fn split_amount(
+    debtor_ids: Vec<String>,
+    amount: u64,
+    count_of_unequally_charged_debts: &HashMap<String, u32>,
+) -> Vec<(String, u64, bool)> {
+    let mut debtor_ids = debtor_ids.clone();
+    debtor_ids.sort();
+    let debtor_count: u64 = debtor_ids.len() as u64;
+    let amount_per_debtor: u64 = amount / debtor_count;
+    let mut amount_to_split_unequally: u64 = amount - amount_per_debtor * debtor_count;
+    let mut unequally_charged_debtors = Vec::new();
+    let mut potentially_unequally_charged_debtors =
+        get_order_of_debtors_to_be_unequally_charged(&debtor_ids, count_of_unequally_charged_debts);
+
+    while amount_to_split_unequally > 0 {
+        let debtor = potentially_unequally_charged_debtors.pop().unwrap();
+        unequally_charged_debtors.push(debtor.clone());
+        amount_to_split_unequally -= 1;
+    }
+
+    debtor_ids
+        .into_iter()
+        .map(|debtor| {
+            if unequally_charged_debtors.contains(&debtor) {
+                (debtor, amount_per_debtor + 1, true)
+            } else {
+                (debtor, amount_per_debtor, false)
+            }
+        })
+        .collect::<Vec<(String, u64, bool)>>()
+}
+
+/// How often each debtor of the selected debts was charged unequally, as needed by
+/// [`split_amount`]. Called with the transaction which inserts the split debts.
+pub async fn get_count_of_unequally_charged_debts<C, E>(
+    db: &C,
+    debts: Select<E>,
+    debtor_id: E::Column,
+    was_split_unequally: E::Column,
+) -> ServiceResult<HashMap<String, u32>>
+where
+    C: ConnectionTrait,
+    E: EntityTrait,
+{
+    Ok(debts
+        .select_only()
+        .column(debtor_id)
+        .column_as(debtor_id.count(), "count_of_unequally_charged_debts")
+        .filter(was_split_unequally.eq(true))
+        .group_by(debtor_id)
+        .into_model::<CountOfUnequallyChargedDebts>()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|debt| (debt.debtor_id, debt.count_of_unequally_charged_debts as u32))
+        .collect::<HashMap<String, u32>>())
+}
+
+fn get_order_of_debtors_to_be_unequally_charged(
+    debtor_ids: &[String],
+    count_of_unequally_charged_debts: &HashMap<String, u32>,
+) -> Vec<String> {
+    let mut next_charged_debtors = debtor_ids
+        .iter()
+        .map(|debtor| {
+            (
+                debtor.to_owned(),
+                count_of_unequally_charged_debts
+                    .get(debtor)
+                    .unwrap_or(&0)
+                    .to_owned(),
+            )
+        })
+        .collect::<Vec<(String, u32)>>();
+
+    next_charged_debtors.sort_by_key(|(_, count)| Reverse(*count));
+
+    next_charged_debtors
+        .into_iter()
+        .filter(|(debtor, _)| debtor_ids.contains(debtor))
+        .map(|(debtor, _)| debtor)
+        .collect::<Vec<String>>()
+}
+
+/// `SUM()` returns `NUMERIC` on Postgres and `DECIMAL` on MySQL, so the result is cast back
+/// to a 64-bit integer, which can be decoded on all backends.
+pub fn sum_as_i64<C: ColumnTrait>(column: C, backend: DbBackend) -> SimpleExpr {
+    let integer_type = match backend {
+        DbBackend::MySql => "SIGNED",
+        _ => "BIGINT",
+    };
+
+    column.sum().cast_as(Alias::new(integer_type))
+}
+
+pub fn timestamp_or_now(timestamp: Option<i64>) -> i64 {
+    timestamp.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GroupEvent, GroupService};
+    use crate::build_test_rocket;
+    use crate::services::friend::FriendService;
+    use crate::services::user::UserService;
+    use futures::executor::block_on;
+    use serial_test::serial;
+    use std::sync::Arc;
+
+    #[test]
+    #[serial]
+    fn test_negative_balances() {
+        let rocket = build_test_rocket();
+        let user_service = rocket.state::<Arc<UserService>>().unwrap();
+        let group_service = rocket.state::<Arc<GroupService>>().unwrap();
+        let friend_service = rocket.state::<Arc<FriendService>>().unwrap();
+
+        let create_user = |username: &str| {
+            block_on(user_service.create_user(
+                username.to_owned(),
+                username.to_owned(),
+                username.to_owned(),
+                false,
+                None,
+            ))
+            .unwrap()
+        };
+        let alice = create_user("alice");
+        let alice_id = alice.id.to_owned();
+        let bob = create_user("bob");
+
+        let group = block_on(group_service.create_group("Trip".to_owned(), alice)).unwrap();
+        block_on(group_service.create_group_member(group.id.to_owned(), bob.id.to_owned(), false))
+            .unwrap();
+        block_on(friend_service.add_friend(&alice_id, &bob.id)).unwrap();
+        block_on(friend_service.add_friend(&bob.id, &alice_id)).unwrap();
+
+        // the sums exceed 32 bits, bob's balance is negative
+        for amount in [3_000_000_000, 3_000_000_000] {
+            block_on(group_service.create_transaction(
+                group.id.to_owned(),
+                alice_id.to_owned(),
+                vec![bob.id.to_owned()],
+                amount,
+                "Car".to_owned(),
+                None,
+            ))
+            .unwrap();
+            block_on(friend_service.create_transaction(
+                &alice_id,
+                &bob.id,
+                vec![bob.id.to_owned()],
+                amount,
+                "Car".to_owned(),
+                None,
+            ))
+            .unwrap();
+        }
+        block_on(group_service.create_transaction(
+            group.id.to_owned(),
+            bob.id.to_owned(),
+            vec![alice_id.to_owned()],
+            1,
+            "Gum".to_owned(),
+            None,
+        ))
+        .unwrap();
+
+        let debts = block_on(group_service.get_debts_of_user_in_group(&group.id, &bob.id)).unwrap();
+        assert_eq!(debts[0].debtor_id, alice_id);
+        assert_eq!(debts[0].amount, -5_999_999_999);
+
+        let balance = block_on(group_service.get_balance_of_user(&bob.id)).unwrap();
+        assert_eq!(balance.amount, -5_999_999_999);
+        assert_eq!(balance.users[0].amount, -5_999_999_999);
+
+        let friend = block_on(friend_service.get_friend_of_user(&bob.id, &alice_id)).unwrap();
+        assert_eq!(friend.amount, -6_000_000_000);
+        let friend = block_on(friend_service.get_friend_of_user(&alice_id, &bob.id)).unwrap();
+        assert_eq!(friend.amount, 6_000_000_000);
+    }
+
+    #[test]
+    #[serial]
+    fn test_event_channels_per_group() {
+        let rocket = build_test_rocket();
+        let user_service = rocket.state::<Arc<UserService>>().unwrap();
+        let group_service = rocket.state::<Arc<GroupService>>().unwrap();
+
+        let alice = block_on(user_service.create_user(
+            "alice".to_owned(),
+            "alice".to_owned(),
+            "alice".to_owned(),
+            false,
+            None,
+        ))
+        .unwrap();
+        let alice_id = alice.id.to_owned();
+        let trip = block_on(group_service.create_group("Trip".to_owned(), alice)).unwrap();
+        let alice = block_on(user_service.get_user_by_id(alice_id.to_owned()))
+            .unwrap()
+            .unwrap();
+        let dinner = block_on(group_service.create_group("Dinner".to_owned(), alice)).unwrap();
+
+        let mut first = block_on(group_service.subscribe_to_group(&trip.id, &alice_id)).unwrap();
+        let second = block_on(group_service.subscribe_to_group(&trip.id, &alice_id)).unwrap();
+        let create_transaction = |group_id: &str| {
+            block_on(group_service.create_transaction(
+                group_id.to_owned(),
+                alice_id.to_owned(),
+                vec![alice_id.to_owned()],
+                10,
+                "Fuel".to_owned(),
+                None,
+            ))
+            .unwrap()
+        };
+
+        // events of other groups do not reach the subscribers
+        create_transaction(&dinner.id);
+        let transaction = create_transaction(&trip.id);
+        match block_on(first.recv()) {
+            Some(Ok(GroupEvent::TransactionCreated(event))) => {
+                assert_eq!(event.id, transaction.id)
+            }
+            _ => panic!("expected the transaction of the trip"),
+        }
+
+        // the channel is removed with its last subscriber
+        drop(first);
+        assert_eq!(group_service.events.lock().unwrap().len(), 1);
+        drop(second);
+        assert!(group_service.events.lock().unwrap().is_empty());
+    }
+}
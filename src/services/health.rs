@@ -0,0 +1,128 @@
+use migration::{Migrator, MigratorTrait};
+use rocket::serde::Serialize;
+use sea_orm::*;
+use sea_orm_migration::seaql_migrations;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::warn;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DatabaseCheck {
+    pub status: HealthStatus,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MigrationsCheck {
+    pub status: HealthStatus,
+    /// The latest applied migration
+    pub current: Option<String>,
+    pub pending: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReadinessChecks {
+    pub database: DatabaseCheck,
+    pub migrations: MigrationsCheck,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Readiness {
+    pub status: HealthStatus,
+    pub checks: ReadinessChecks,
+}
+
+#[derive(Debug)]
+pub struct HealthService {
+    db: Arc<DatabaseConnection>,
+}
+
+impl HealthService {
+    pub fn new(db: Arc<DatabaseConnection>) -> HealthService {
+        HealthService { db }
+    }
+
+    /// Ready means the database is reachable and all migrations of this version are applied.
+    /// Only reads, so it is safe to call on every probe.
+    pub async fn readiness(&self) -> Readiness {
+        let database = match self._ping().await {
+            Ok(_) => HealthStatus::Up,
+            Err(e) => {
+                // the details are only logged, the endpoint is public
+                warn!(error = %e, "the database is not reachable");
+                HealthStatus::Down
+            }
+        };
+
+        let migrations = match database {
+            HealthStatus::Up => self._check_migrations().await,
+            HealthStatus::Down => MigrationsCheck {
+                status: HealthStatus::Down,
+                current: None,
+                pending: vec![],
+            },
+        };
+
+        let status = match (database, migrations.status) {
+            (HealthStatus::Up, HealthStatus::Up) => HealthStatus::Up,
+            _ => HealthStatus::Down,
+        };
+
+        Readiness {
+            status,
+            checks: ReadinessChecks {
+                database: DatabaseCheck { status: database },
+                migrations,
+            },
+        }
+    }
+
+    async fn _ping(&self) -> Result<(), DbErr> {
+        let backend = self.db.get_database_backend();
+        self.db
+            .execute(Statement::from_string(backend, "SELECT 1".to_owned()))
+            .await
+            .map(|_| ())
+    }
+
+    // `Migrator::get_pending_migrations` would create the migrations table if it is missing
+    async fn _check_migrations(&self) -> MigrationsCheck {
+        let applied = match seaql_migrations::Entity::find()
+            .order_by_asc(seaql_migrations::Column::Version)
+            .all(self.db.as_ref())
+            .await
+        {
+            Ok(applied) => applied,
+            Err(e) => {
+                warn!(error = %e, "reading the applied migrations failed");
+                vec![]
+            }
+        };
+
+        let current = applied.last().map(|migration| migration.version.clone());
+        let applied: HashSet<String> = applied
+            .into_iter()
+            .map(|migration| migration.version)
+            .collect();
+        let pending: Vec<String> = Migrator::migrations()
+            .iter()
+            .map(|migration| migration.name().to_owned())
+            .filter(|name| !applied.contains(name))
+            .collect();
+
+        MigrationsCheck {
+            status: match pending.is_empty() {
+                true => HealthStatus::Up,
+                false => HealthStatus::Down,
+            },
+            current,
+            pending,
+        }
+    }
+}
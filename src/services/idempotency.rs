@@ -0,0 +1,178 @@
+use crate::model;
+use sea_orm::*;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+use super::configuration::ConfigurationService;
+use super::error::{invalid_field, ServiceError, ServiceResult};
+use super::group::timestamp_or_now;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+pub const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+pub const IDEMPOTENCY_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// A response which is replayed for retries of a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredResponse {
+    pub status: u16,
+    /// json, `None` for responses without a body
+    pub body: Option<String>,
+}
+
+/// Remembers the responses to mutating requests with an `Idempotency-Key`, so clients can
+/// retry them without making the change twice. Keys are scoped to the user and bound to
+/// the method, path and body of the first request.
+#[derive(Debug)]
+pub struct IdempotencyService {
+    db: Arc<DatabaseConnection>,
+    window_seconds: i64,
+}
+
+impl IdempotencyService {
+    pub fn new(
+        db: Arc<DatabaseConnection>,
+        configuration_service: Arc<ConfigurationService>,
+    ) -> IdempotencyService {
+        IdempotencyService {
+            db,
+            window_seconds: configuration_service.idempotency().window_seconds as i64,
+        }
+    }
+
+    /// Claims the key for the request. Returns the stored response if the request was
+    /// already made, otherwise the caller has to `complete` or `release` the key.
+    /// `body` is the json body of the request, it only has to match for retries.
+    pub async fn begin(
+        &self,
+        user_id: &str,
+        key: &str,
+        request: &str,
+        body: &str,
+    ) -> ServiceResult<Option<StoredResponse>> {
+        if key.is_empty() || key.chars().count() > MAX_IDEMPOTENCY_KEY_LENGTH {
+            return Err(invalid_field(
+                IDEMPOTENCY_KEY_HEADER,
+                "invalid",
+                &format!(
+                    "The idempotency key must have between 1 and {} characters",
+                    MAX_IDEMPOTENCY_KEY_LENGTH
+                ),
+            ));
+        }
+
+        let request_hash = format!("{:x}", Sha256::digest(body.as_bytes()));
+        let now = timestamp_or_now(None);
+
+        if let Some(existing) = self._find(user_id, key).await? {
+            if existing.created_at >= now - self.window_seconds {
+                return self._resume(existing, request, &request_hash).await;
+            }
+
+            // expired, but not removed yet
+            model::idempotency_key::Entity::delete_many()
+                .filter(model::idempotency_key::Column::UserId.eq(user_id))
+                .filter(model::idempotency_key::Column::Key.eq(key))
+                .filter(model::idempotency_key::Column::CreatedAt.eq(existing.created_at))
+                .exec(self.db.as_ref())
+                .await?;
+        }
+
+        let inserted = model::idempotency_key::ActiveModel {
+            user_id: ActiveValue::Set(user_id.to_owned()),
+            key: ActiveValue::Set(key.to_owned()),
+            request: ActiveValue::Set(request.to_owned()),
+            response_status: ActiveValue::Set(None),
+            response_body: ActiveValue::Set(None),
+            created_at: ActiveValue::Set(now),
+            request_hash: ActiveValue::Set(request_hash.to_owned()),
+        }
+        .insert(self.db.as_ref())
+        .await;
+
+        match inserted {
+            Ok(_) => Ok(None),
+            // a concurrent request with the same key claimed it first
+            Err(e) => match self._find(user_id, key).await? {
+                Some(existing) => self._resume(existing, request, &request_hash).await,
+                None => Err(e.into()),
+            },
+        }
+    }
+
+    /// Stores the response of a claimed key. Failures are only logged, the change itself
+    /// has already been made and retries are rejected with `idempotency_key_in_use`
+    /// until the key expires.
+    pub async fn complete(&self, user_id: &str, key: &str, response: &StoredResponse) {
+        let res = model::idempotency_key::ActiveModel {
+            user_id: ActiveValue::Unchanged(user_id.to_owned()),
+            key: ActiveValue::Unchanged(key.to_owned()),
+            response_status: ActiveValue::Set(Some(response.status as i32)),
+            response_body: ActiveValue::Set(response.body.to_owned()),
+            ..Default::default()
+        }
+        .update(self.db.as_ref())
+        .await;
+
+        if let Err(e) = res {
+            warn!(user_id, error = %e, "storing the response of an idempotent request failed");
+        }
+    }
+
+    /// Releases a claimed key after the request failed, so it can be retried
+    pub async fn release(&self, user_id: &str, key: &str) -> ServiceResult<()> {
+        model::idempotency_key::Entity::delete_by_id((user_id.to_owned(), key.to_owned()))
+            .exec(self.db.as_ref())
+            .await?;
+
+        Ok(())
+    }
+
+    async fn _find(
+        &self,
+        user_id: &str,
+        key: &str,
+    ) -> ServiceResult<Option<model::idempotency_key::Model>> {
+        Ok(
+            model::idempotency_key::Entity::find_by_id((user_id.to_owned(), key.to_owned()))
+                .one(self.db.as_ref())
+                .await?,
+        )
+    }
+
+    /// Returns the stored response of a retry. A key without a response is never taken
+    /// over, its request may have made the change without storing the response.
+    async fn _resume(
+        &self,
+        existing: model::idempotency_key::Model,
+        request: &str,
+        request_hash: &str,
+    ) -> ServiceResult<Option<StoredResponse>> {
+        if existing.request != request || existing.request_hash != request_hash {
+            return Err(ServiceError::Unprocessable("idempotency_key_reused"));
+        }
+
+        match existing.response_status {
+            Some(status) => Ok(Some(StoredResponse {
+                status: status as u16,
+                body: existing.response_body,
+            })),
+            None => Err(ServiceError::Conflict("idempotency_key_in_use")),
+        }
+    }
+
+    /// Removes the keys which are older than the window. Runs periodically instead of on
+    /// every request, expired keys which were not removed yet are ignored by `begin`.
+    pub async fn remove_expired_keys(&self) -> ServiceResult<()> {
+        model::idempotency_key::Entity::delete_many()
+            .filter(
+                model::idempotency_key::Column::CreatedAt
+                    .lt(timestamp_or_now(None) - self.window_seconds),
+            )
+            .exec(self.db.as_ref())
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+use crate::model;
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::serde::Serialize;
+use sea_orm::sea_query::Expr;
+use sea_orm::*;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use super::error::{ServiceError, ServiceResult};
+
+const INVITE_CODE_LENGTH: usize = 16;
+
+#[derive(Serialize, ToSchema)]
+pub struct InviteCode {
+    pub code: String,
+    pub created_by: String,
+    pub used_by: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct InviteService {
+    db: Arc<DatabaseConnection>,
+}
+
+impl From<model::invite_code::Model> for InviteCode {
+    fn from(invite_code: model::invite_code::Model) -> Self {
+        InviteCode {
+            code: invite_code.code,
+            created_by: invite_code.created_by,
+            used_by: invite_code.used_by,
+        }
+    }
+}
+
+impl InviteService {
+    pub fn new(db: Arc<DatabaseConnection>) -> InviteService {
+        InviteService { db }
+    }
+
+    pub async fn create_invite_code(&self, created_by: &str) -> ServiceResult<InviteCode> {
+        let code = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(INVITE_CODE_LENGTH)
+            .map(char::from)
+            .collect::<String>();
+
+        model::invite_code::Entity::insert(model::invite_code::ActiveModel {
+            code: ActiveValue::Set(code.to_owned()),
+            created_by: ActiveValue::Set(created_by.to_owned()),
+            used_by: ActiveValue::Set(None),
+        })
+        .exec(self.db.as_ref())
+        .await?;
+
+        Ok(InviteCode {
+            code,
+            created_by: created_by.to_owned(),
+            used_by: None,
+        })
+    }
+
+    pub async fn get_invite_codes(&self) -> ServiceResult<Vec<InviteCode>> {
+        Ok(model::invite_code::Entity::find()
+            .all(self.db.as_ref())
+            .await?
+            .into_iter()
+            .map(|invite_code| invite_code.into())
+            .collect())
+    }
+
+    /// Invite codes which were created or used by the user
+    pub async fn get_invite_codes_of_user(&self, user_id: &str) -> ServiceResult<Vec<InviteCode>> {
+        Ok(model::invite_code::Entity::find()
+            .filter(
+                Condition::any()
+                    .add(model::invite_code::Column::CreatedBy.eq(user_id))
+                    .add(model::invite_code::Column::UsedBy.eq(user_id)),
+            )
+            .all(self.db.as_ref())
+            .await?
+            .into_iter()
+            .map(|invite_code| invite_code.into())
+            .collect())
+    }
+
+    pub async fn delete_invite_code(&self, code: &str) -> ServiceResult<()> {
+        let res = model::invite_code::Entity::delete_by_id(code.to_owned())
+            .exec(self.db.as_ref())
+            .await?;
+
+        match res.rows_affected {
+            0 => Err(ServiceError::NotFound("invite_code_not_found")),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn is_invite_code_valid(&self, code: &str) -> ServiceResult<bool> {
+        Ok(model::invite_code::Entity::find_by_id(code.to_owned())
+            .filter(model::invite_code::Column::UsedBy.is_null())
+            .one(self.db.as_ref())
+            .await?
+            .is_some())
+    }
+}
+
+/// Marks an unused invite code as used by the user. Returns false if the code
+/// does not exist or was already used.
+pub async fn redeem_invite_code<C: ConnectionTrait>(
+    db: &C,
+    code: &str,
+    user_id: &str,
+) -> ServiceResult<bool> {
+    Ok(model::invite_code::Entity::update_many()
+        .col_expr(model::invite_code::Column::UsedBy, Expr::value(user_id))
+        .filter(model::invite_code::Column::Code.eq(code))
+        .filter(model::invite_code::Column::UsedBy.is_null())
+        .exec(db)
+        .await?
+        .rows_affected
+        > 0)
+}
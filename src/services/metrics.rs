@@ -0,0 +1,168 @@
+use crate::model;
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use sea_orm::*;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use super::error::ServiceResult;
+
+/// Collects the metrics exposed at `/metrics` in the Prometheus text format
+#[derive(Debug)]
+pub struct MetricsService {
+    /// Set once the connection exists, its queries are measured through this service
+    db: OnceLock<Arc<DatabaseConnection>>,
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    db_query_duration: HistogramVec,
+    authentications: IntCounterVec,
+    entities: IntGaugeVec,
+}
+
+impl MetricsService {
+    pub fn new() -> MetricsService {
+        let registry = Registry::new_custom(Some("moneybalancer".to_owned()), None).unwrap();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Number of answered http requests"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time until a http request was answered",
+            ),
+            &["method", "route"],
+        )
+        .unwrap();
+        let db_query_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "db_query_duration_seconds",
+                "Execution time of sql statements",
+            )
+            .buckets(vec![
+                0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+            ]),
+            &["statement", "failed"],
+        )
+        .unwrap();
+        let authentications = IntCounterVec::new(
+            Opts::new(
+                "authentications_total",
+                "Number of authentication attempts per provider",
+            ),
+            &["provider", "result"],
+        )
+        .unwrap();
+        let entities = IntGaugeVec::new(
+            Opts::new(
+                "entities",
+                "Number of stored users, groups and transactions",
+            ),
+            &["kind"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry
+            .register(Box::new(http_request_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_query_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(authentications.clone()))
+            .unwrap();
+        registry.register(Box::new(entities.clone())).unwrap();
+
+        MetricsService {
+            db: OnceLock::new(),
+            registry,
+            http_requests,
+            http_request_duration,
+            db_query_duration,
+            authentications,
+            entities,
+        }
+    }
+
+    /// The connection used to count the entities on every scrape
+    pub fn set_db(&self, db: Arc<DatabaseConnection>) {
+        let _ = self.db.set(db);
+    }
+
+    /// `route` is the pattern of the matched route (e.g. `/api/v1/group/<group_id>`),
+    /// so the number of label values stays bounded
+    pub fn record_request(&self, method: &str, route: &str, status: u16, duration: Duration) {
+        self.http_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .inc();
+        self.http_request_duration
+            .with_label_values(&[method, route])
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn record_query(&self, info: &metric::Info<'_>) {
+        let statement = info
+            .statement
+            .sql
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        let statement = match statement.as_str() {
+            "select" | "insert" | "update" | "delete" => statement.as_str(),
+            _ => "other",
+        };
+
+        self.db_query_duration
+            .with_label_values(&[statement, &info.failed.to_string()])
+            .observe(info.elapsed.as_secs_f64());
+    }
+
+    pub fn record_authentication(&self, provider: &str, success: bool) {
+        let result = match success {
+            true => "success",
+            false => "failure",
+        };
+        self.authentications
+            .with_label_values(&[provider, result])
+            .inc();
+    }
+
+    /// Updates the entity gauges and renders all metrics
+    pub async fn render(&self) -> ServiceResult<String> {
+        if let Some(db) = self.db.get() {
+            let db = db.as_ref();
+            let counts = [
+                (
+                    "users",
+                    model::user::Entity::find()
+                        .filter(model::user::Column::IsDeleted.eq(false))
+                        .count(db)
+                        .await?,
+                ),
+                ("groups", model::group::Entity::find().count(db).await?),
+                (
+                    "transactions",
+                    model::transaction::Entity::find().count(db).await?,
+                ),
+                (
+                    "direct_transactions",
+                    model::direct_transaction::Entity::find().count(db).await?,
+                ),
+            ];
+
+            for (kind, count) in counts {
+                self.entities.with_label_values(&[kind]).set(count as i64);
+            }
+        }
+
+        Ok(TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap())
+    }
+}
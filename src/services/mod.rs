@@ -1,4 +1,15 @@
 pub mod authentication;
+pub mod backup;
 pub mod configuration;
+pub mod error;
+pub mod export;
+pub mod friend;
 pub mod group;
+pub mod health;
+pub mod idempotency;
+pub mod invite;
+pub mod metrics;
+pub mod rate_limit;
+pub mod totp;
 pub mod user;
+pub mod webhook;
@@ -0,0 +1,116 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::configuration::ConfigurationService;
+
+#[derive(Debug)]
+struct FailedAttempts {
+    count: u32,
+    last_attempt: Instant,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone)]
+enum AttemptKey {
+    Username(String),
+    Ip(IpAddr),
+}
+
+/// Keeps track of failed login attempts per username and per client ip
+/// and locks them out with an exponentially growing backoff.
+#[derive(Debug)]
+pub struct LoginRateLimiter {
+    configuration_service: Arc<ConfigurationService>,
+    attempts: Mutex<HashMap<AttemptKey, FailedAttempts>>,
+}
+
+impl LoginRateLimiter {
+    pub fn new(configuration_service: Arc<ConfigurationService>) -> LoginRateLimiter {
+        LoginRateLimiter {
+            configuration_service,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the remaining lockout time if either the username or the ip is locked out.
+    pub fn check(&self, username: &str, ip: Option<IpAddr>) -> Option<Duration> {
+        if !self.configuration_service.auth_local_rate_limit().enabled {
+            return None;
+        }
+
+        let now = Instant::now();
+        let attempts = self.attempts.lock().unwrap();
+
+        Self::_keys(username, ip)
+            .iter()
+            .filter_map(|key| attempts.get(key)?.locked_until)
+            .filter(|locked_until| *locked_until > now)
+            .map(|locked_until| locked_until - now)
+            .max()
+    }
+
+    pub fn record_failure(&self, username: &str, ip: Option<IpAddr>) {
+        let config = self.configuration_service.auth_local_rate_limit();
+        if !config.enabled {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut attempts = self.attempts.lock().unwrap();
+        self._remove_expired(&mut attempts, now);
+
+        for key in Self::_keys(username, ip) {
+            let max_attempts = match key {
+                AttemptKey::Username(_) => config.max_attempts_per_username,
+                AttemptKey::Ip(_) => config.max_attempts_per_ip,
+            };
+
+            let entry = attempts.entry(key).or_insert(FailedAttempts {
+                count: 0,
+                last_attempt: now,
+                locked_until: None,
+            });
+            entry.count += 1;
+            entry.last_attempt = now;
+
+            if entry.count >= max_attempts {
+                let exponent = (entry.count - max_attempts).min(31);
+                let lockout = config
+                    .lockout_seconds
+                    .saturating_mul(1 << exponent)
+                    .min(config.max_lockout_seconds);
+                entry.locked_until = Some(now + Duration::from_secs(lockout));
+            }
+        }
+    }
+
+    pub fn record_success(&self, username: &str) {
+        self.attempts
+            .lock()
+            .unwrap()
+            .remove(&AttemptKey::Username(username.to_owned()));
+    }
+
+    fn _remove_expired(&self, attempts: &mut HashMap<AttemptKey, FailedAttempts>, now: Instant) {
+        let max_lockout = Duration::from_secs(
+            self.configuration_service
+                .auth_local_rate_limit()
+                .max_lockout_seconds,
+        );
+
+        // lockouts never exceed max_lockout, so older entries are irrelevant
+        attempts.retain(|_, a| now - a.last_attempt < max_lockout);
+    }
+
+    fn _keys(username: &str, ip: Option<IpAddr>) -> Vec<AttemptKey> {
+        let mut keys = vec![AttemptKey::Username(username.to_owned())];
+        if let Some(ip) = ip {
+            keys.push(AttemptKey::Ip(ip));
+        }
+        keys
+    }
+}
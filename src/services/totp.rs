@@ -0,0 +1,242 @@
+use crate::model;
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::serde::Serialize;
+use sea_orm::*;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use totp_rs::{Algorithm, Secret, TOTP};
+use utoipa::ToSchema;
+
+use super::configuration::ConfigurationService;
+use super::error::{ServiceError, ServiceResult};
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+#[derive(Serialize, ToSchema)]
+pub struct TotpEnrolment {
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TotpRecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum TotpError {
+    AlreadyEnabled,
+    NotEnrolled,
+    InvalidCode,
+    Service(ServiceError),
+}
+
+impl From<ServiceError> for TotpError {
+    fn from(err: ServiceError) -> Self {
+        TotpError::Service(err)
+    }
+}
+
+impl From<DbErr> for TotpError {
+    fn from(err: DbErr) -> Self {
+        TotpError::Service(err.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct TotpService {
+    db: Arc<DatabaseConnection>,
+    configuration_service: Arc<ConfigurationService>,
+}
+
+impl TotpService {
+    pub fn new(
+        db: Arc<DatabaseConnection>,
+        configuration_service: Arc<ConfigurationService>,
+    ) -> TotpService {
+        TotpService {
+            db,
+            configuration_service,
+        }
+    }
+
+    /// Creates a new, unconfirmed secret for the user.
+    /// It only becomes active once a code generated from it was confirmed.
+    pub async fn begin_enrolment(
+        &self,
+        user_id: &str,
+        username: &str,
+    ) -> Result<TotpEnrolment, TotpError> {
+        if self.is_enabled(user_id).await? {
+            return Err(TotpError::AlreadyEnabled);
+        }
+
+        let secret = Secret::generate_secret().to_encoded().to_string();
+
+        model::user_totp::Entity::delete_many()
+            .filter(model::user_totp::Column::UserId.eq(user_id))
+            .exec(self.db.as_ref())
+            .await?;
+
+        model::user_totp::Entity::insert(model::user_totp::ActiveModel {
+            user_id: ActiveValue::Set(user_id.to_owned()),
+            secret: ActiveValue::Set(secret.to_owned()),
+            is_confirmed: ActiveValue::Set(false),
+        })
+        .exec(self.db.as_ref())
+        .await?;
+
+        Ok(TotpEnrolment {
+            provisioning_uri: self._totp(&secret, username).get_url(),
+            secret,
+        })
+    }
+
+    /// Activates a pending enrolment and generates a fresh set of recovery codes.
+    pub async fn confirm_enrolment(
+        &self,
+        user_id: &str,
+        code: &str,
+    ) -> Result<TotpRecoveryCodes, TotpError> {
+        let totp = self
+            ._get_totp(user_id)
+            .await?
+            .ok_or(TotpError::NotEnrolled)?;
+
+        if totp.is_confirmed {
+            return Err(TotpError::AlreadyEnabled);
+        }
+
+        if !self
+            ._totp(&totp.secret, user_id)
+            .check_current(code)
+            .unwrap()
+        {
+            return Err(TotpError::InvalidCode);
+        }
+
+        let mut totp: model::user_totp::ActiveModel = totp.into();
+        totp.is_confirmed = ActiveValue::Set(true);
+        totp.update(self.db.as_ref()).await?;
+
+        Ok(TotpRecoveryCodes {
+            recovery_codes: self._create_recovery_codes(user_id).await?,
+        })
+    }
+
+    /// Removes the secret and all recovery codes after verifying a code.
+    pub async fn disable(&self, user_id: &str, code: &str) -> Result<(), TotpError> {
+        if !self.is_enabled(user_id).await? {
+            return Err(TotpError::NotEnrolled);
+        }
+
+        if !self.verify(user_id, code).await? {
+            return Err(TotpError::InvalidCode);
+        }
+
+        model::user_totp_recovery_code::Entity::delete_many()
+            .filter(model::user_totp_recovery_code::Column::UserId.eq(user_id))
+            .exec(self.db.as_ref())
+            .await?;
+
+        model::user_totp::Entity::delete_by_id(user_id.to_owned())
+            .exec(self.db.as_ref())
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn is_enabled(&self, user_id: &str) -> ServiceResult<bool> {
+        Ok(match self._get_totp(user_id).await? {
+            Some(totp) => totp.is_confirmed,
+            None => false,
+        })
+    }
+
+    /// Checks a code generated by the authenticator app, or else a recovery code.
+    /// Recovery codes can only be used once.
+    pub async fn verify(&self, user_id: &str, code: &str) -> ServiceResult<bool> {
+        let totp = match self._get_totp(user_id).await? {
+            Some(totp) if totp.is_confirmed => totp,
+            _ => return Ok(false),
+        };
+
+        if self
+            ._totp(&totp.secret, user_id)
+            .check_current(code)
+            .unwrap()
+        {
+            return Ok(true);
+        }
+
+        let consumed = model::user_totp_recovery_code::Entity::delete_many()
+            .filter(model::user_totp_recovery_code::Column::UserId.eq(user_id))
+            .filter(
+                model::user_totp_recovery_code::Column::CodeHash
+                    .eq(Self::_hash_recovery_code(code)),
+            )
+            .exec(self.db.as_ref())
+            .await?
+            .rows_affected
+            > 0;
+
+        Ok(consumed)
+    }
+
+    async fn _get_totp(&self, user_id: &str) -> ServiceResult<Option<model::user_totp::Model>> {
+        Ok(model::user_totp::Entity::find_by_id(user_id.to_owned())
+            .one(self.db.as_ref())
+            .await?)
+    }
+
+    async fn _create_recovery_codes(&self, user_id: &str) -> ServiceResult<Vec<String>> {
+        model::user_totp_recovery_code::Entity::delete_many()
+            .filter(model::user_totp_recovery_code::Column::UserId.eq(user_id))
+            .exec(self.db.as_ref())
+            .await?;
+
+        let codes = (0..RECOVERY_CODE_COUNT)
+            .map(|_| {
+                rand::thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(RECOVERY_CODE_LENGTH)
+                    .map(char::from)
+                    .collect::<String>()
+                    .to_lowercase()
+            })
+            .collect::<Vec<String>>();
+
+        model::user_totp_recovery_code::Entity::insert_many(codes.iter().map(|code| {
+            model::user_totp_recovery_code::ActiveModel {
+                user_id: ActiveValue::Set(user_id.to_owned()),
+                code_hash: ActiveValue::Set(Self::_hash_recovery_code(code)),
+            }
+        }))
+        .exec(self.db.as_ref())
+        .await?;
+
+        Ok(codes)
+    }
+
+    fn _totp(&self, secret: &str, account_name: &str) -> TOTP {
+        // unchecked, as usernames are not validated against the otpauth rules
+        TOTP::new_unchecked(
+            Algorithm::SHA1,
+            6,
+            1,
+            30,
+            Secret::Encoded(secret.to_owned()).to_bytes().unwrap(),
+            Some(self.configuration_service.totp_issuer().to_owned()),
+            account_name.to_owned(),
+        )
+    }
+
+    // recovery codes are random enough, a slow hash like bcrypt is not needed
+    fn _hash_recovery_code(code: &str) -> String {
+        format!(
+            "{:x}",
+            Sha256::digest(code.trim().to_lowercase().as_bytes())
+        )
+    }
+}
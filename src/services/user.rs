@@ -1,19 +1,37 @@
 use crate::model;
 use pwhash::bcrypt;
 use rocket::serde::Serialize;
+use sea_orm::sea_query::{Expr, SimpleExpr};
 use sea_orm::*;
 use std::sync::Arc;
+use utoipa::ToSchema;
+
+use super::configuration::ConfigurationService;
+use super::error::{is_unique_violation, ServiceError, ServiceResult, Validator};
+use super::invite::redeem_invite_code;
 
 #[derive(Serialize)]
 pub struct User {
     pub id: String,
     pub username: String,
     pub nickname: String,
+    pub is_admin: bool,
+}
+
+/// A user as seen by admins
+#[derive(Serialize, ToSchema)]
+pub struct UserDetails {
+    pub id: String,
+    pub username: String,
+    pub nickname: String,
+    pub is_admin: bool,
+    pub is_disabled: bool,
 }
 
 #[derive(Debug)]
 pub struct UserService {
     db: Arc<DatabaseConnection>,
+    configuration_service: Arc<ConfigurationService>,
 }
 
 impl Into<User> for crate::model::user::Model {
@@ -22,39 +40,106 @@ impl Into<User> for crate::model::user::Model {
             id: self.id,
             username: self.username,
             nickname: self.nickname,
+            is_admin: self.is_admin,
+        }
+    }
+}
+
+impl From<crate::model::user::Model> for UserDetails {
+    fn from(user: crate::model::user::Model) -> Self {
+        UserDetails {
+            id: user.id,
+            username: user.username,
+            nickname: user.nickname,
+            is_admin: user.is_admin,
+            is_disabled: user.is_disabled,
         }
     }
 }
 
 impl UserService {
-    pub fn new(db: Arc<DatabaseConnection>) -> UserService {
-        UserService { db: db }
+    pub fn new(
+        db: Arc<DatabaseConnection>,
+        configuration_service: Arc<ConfigurationService>,
+    ) -> UserService {
+        UserService {
+            db,
+            configuration_service,
+        }
     }
 
+    /// Users listed in `MONEYBALANCER_ADMIN_USERNAMES` are made admins, so registrations
+    /// by anonymous users have to reject these usernames.
+    ///
+    /// Users waiting for approval are created disabled. The invite code is redeemed
+    /// together with the creation, so neither happens without the other.
     pub async fn create_user(
         &self,
         username: String,
         nickname: String,
         password: String,
-    ) -> Result<User, ()> {
+        is_disabled: bool,
+        invite_code: Option<&str>,
+    ) -> ServiceResult<User> {
+        Validator::new()
+            .check(
+                !username.trim().is_empty(),
+                "username",
+                "empty",
+                "The username must not be empty",
+            )
+            .check(
+                !nickname.trim().is_empty(),
+                "nickname",
+                "empty",
+                "The nickname must not be empty",
+            )
+            .check(
+                !password.is_empty(),
+                "password",
+                "empty",
+                "The password must not be empty",
+            )
+            .finish()?;
+
+        if self.get_user_by_username(&username).await?.is_some() {
+            return Err(ServiceError::Conflict("username_taken"));
+        }
+
         let new_user_id = uuid::Uuid::new_v4().to_string();
+        let is_admin = self.configuration_service.is_configured_admin(&username);
 
         let new_user = model::user::ActiveModel {
             id: ActiveValue::Set(new_user_id.to_owned()),
             username: ActiveValue::Set(username.to_owned()),
             nickname: ActiveValue::Set(nickname.to_owned()),
             password: ActiveValue::Set(bcrypt::hash(password.to_owned()).unwrap().to_owned()),
+            is_deleted: ActiveValue::Set(false),
+            is_admin: ActiveValue::Set(is_admin),
+            is_disabled: ActiveValue::Set(is_disabled),
         };
 
-        model::user::Entity::insert(new_user)
-            .exec(self.db.as_ref())
-            .await
-            .map_err(|_| ())?;
+        let txn = self.db.begin().await?;
+
+        // queries with a returning clause are not passed to the metric callback within
+        // transactions, so the insert is executed without one
+        txn.execute(model::user::Entity::insert(new_user).build(txn.get_database_backend()))
+            .await?;
+
+        if let Some(code) = invite_code {
+            // the code might have been redeemed by someone else in the meantime
+            if !redeem_invite_code(&txn, code, &new_user_id).await? {
+                return Err(ServiceError::Forbidden("invalid_invite_code"));
+            }
+        }
+
+        txn.commit().await?;
 
         Ok(User {
             id: new_user_id,
             username: username,
             nickname: nickname,
+            is_admin,
         })
     }
 
@@ -62,36 +147,231 @@ impl UserService {
         &self,
         username: &str,
         password: &str,
-    ) -> Option<String> {
+    ) -> ServiceResult<Option<String>> {
         let user = model::user::Entity::find()
             .filter(model::user::Column::Username.eq(username.to_owned()))
+            .filter(model::user::Column::IsDisabled.eq(false))
             .one(self.db.as_ref())
-            .await
-            .expect("Failed to query user!")?;
+            .await?;
 
-        if !bcrypt::verify(password.to_owned(), &user.password) {
-            None
-        } else {
-            Some(user.id)
-        }
+        Ok(user
+            .filter(|user| bcrypt::verify(password, &user.password))
+            .map(|user| user.id))
     }
 
-    pub async fn get_user_by_id(&self, user_id: String) -> Option<User> {
+    pub async fn get_user_by_id(&self, user_id: String) -> ServiceResult<Option<User>> {
         let user = model::user::Entity::find_by_id(user_id)
+            .filter(model::user::Column::IsDeleted.eq(false))
+            .filter(model::user::Column::IsDisabled.eq(false))
             .one(self.db.as_ref())
-            .await
-            .expect("Failed to query user!")?;
+            .await?;
 
-        Some(user.into())
+        Ok(user.map(|user| user.into()))
     }
 
-    pub async fn get_user_by_username(&self, username: &str) -> Option<User> {
+    pub async fn get_user_by_username(&self, username: &str) -> ServiceResult<Option<User>> {
         let user = model::user::Entity::find()
             .filter(model::user::Column::Username.eq(username))
             .one(self.db.as_ref())
+            .await?;
+
+        Ok(user.map(|user| user.into()))
+    }
+
+    pub async fn update_user(
+        &self,
+        user_id: &str,
+        username: Option<String>,
+        nickname: Option<String>,
+    ) -> ServiceResult<User> {
+        let username = username.map(|u| u.trim().to_owned());
+        let nickname = nickname.map(|n| n.trim().to_owned());
+
+        Validator::new()
+            .check(
+                username.as_deref() != Some(""),
+                "username",
+                "empty",
+                "The username must not be empty",
+            )
+            .check(
+                nickname.as_deref() != Some(""),
+                "nickname",
+                "empty",
+                "The nickname must not be empty",
+            )
+            .finish()?;
+
+        // sea-orm cannot update a row without any values
+        if username.is_none() && nickname.is_none() {
+            return self
+                .get_user_by_id(user_id.to_owned())
+                .await?
+                .ok_or(ServiceError::NotFound("user_not_found"));
+        }
+
+        if let Some(username) = &username {
+            if let Some(u) = self.get_user_by_username(username).await? {
+                if u.id != user_id {
+                    return Err(ServiceError::Conflict("username_taken"));
+                }
+            }
+        }
+
+        let mut user = model::user::ActiveModel {
+            id: ActiveValue::Unchanged(user_id.to_owned()),
+            ..Default::default()
+        };
+
+        if let Some(username) = username {
+            user.username = ActiveValue::Set(username);
+        }
+
+        if let Some(nickname) = nickname {
+            user.nickname = ActiveValue::Set(nickname);
+        }
+
+        let user = user
+            .update(self.db.as_ref())
+            .await
+            // the unique constraint catches concurrent updates
+            .map_err(|e| match is_unique_violation(&e) {
+                true => ServiceError::Conflict("username_taken"),
+                false => e.into(),
+            })?;
+
+        Ok(user.into())
+    }
+
+    /// Anonymises the user instead of deleting it,
+    /// so the transactions and debts of the user's groups stay intact.
+    pub async fn delete_user(&self, user_id: &str) -> ServiceResult<()> {
+        let txn = self.db.begin().await?;
+
+        model::user::ActiveModel {
+            id: ActiveValue::Unchanged(user_id.to_owned()),
+            username: ActiveValue::Set(format!("deleted-user-{}", user_id)),
+            nickname: ActiveValue::Set("Deleted user".to_owned()),
+            // no bcrypt hash, so no password will ever match
+            password: ActiveValue::Set("".to_owned()),
+            is_deleted: ActiveValue::Set(true),
+            is_admin: ActiveValue::Set(false),
+            is_disabled: ActiveValue::NotSet,
+        }
+        .update(&txn)
+        .await?;
+
+        model::user_totp::Entity::delete_many()
+            .filter(model::user_totp::Column::UserId.eq(user_id))
+            .exec(&txn)
+            .await?;
+
+        model::user_totp_recovery_code::Entity::delete_many()
+            .filter(model::user_totp_recovery_code::Column::UserId.eq(user_id))
+            .exec(&txn)
+            .await?;
+
+        model::friend_request::Entity::delete_many()
+            .filter(
+                Condition::any()
+                    .add(model::friend_request::Column::UserId.eq(user_id))
+                    .add(model::friend_request::Column::FriendId.eq(user_id)),
+            )
+            .exec(&txn)
+            .await?;
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    /// Makes all users listed in `MONEYBALANCER_ADMIN_USERNAMES` admins
+    pub async fn promote_configured_admins(&self) -> ServiceResult<()> {
+        let admin_usernames = self.configuration_service.admin_usernames();
+        if admin_usernames.is_empty() {
+            return Ok(());
+        }
+
+        model::user::Entity::update_many()
+            .col_expr(model::user::Column::IsAdmin, Expr::value(true))
+            .filter(model::user::Column::Username.is_in(admin_usernames))
+            .exec(self.db.as_ref())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Lists all users, including disabled ones. `search` is matched against username and nickname.
+    pub async fn list_users(&self, search: Option<&str>) -> ServiceResult<Vec<UserDetails>> {
+        let mut select =
+            model::user::Entity::find().filter(model::user::Column::IsDeleted.eq(false));
+
+        if let Some(search) = search {
+            let pattern = format!("%{}%", search);
+            select = select.filter(
+                Condition::any()
+                    .add(model::user::Column::Username.like(&pattern))
+                    .add(model::user::Column::Nickname.like(&pattern)),
+            );
+        }
+
+        Ok(select
+            .order_by_asc(model::user::Column::Username)
+            .all(self.db.as_ref())
+            .await?
+            .into_iter()
+            .map(|user| user.into())
+            .collect())
+    }
+
+    pub async fn set_user_disabled(&self, user_id: &str, is_disabled: bool) -> ServiceResult<()> {
+        self._update_existing_user(
+            user_id,
+            model::user::Column::IsDisabled,
+            Expr::value(is_disabled),
+        )
+        .await
+    }
+
+    pub async fn set_user_admin(&self, user_id: &str, is_admin: bool) -> ServiceResult<()> {
+        self._update_existing_user(user_id, model::user::Column::IsAdmin, Expr::value(is_admin))
             .await
-            .expect("Failed to query user!")?;
+    }
+
+    pub async fn set_password(&self, user_id: &str, password: &str) -> ServiceResult<()> {
+        Validator::new()
+            .check(
+                !password.is_empty(),
+                "password",
+                "empty",
+                "The password must not be empty",
+            )
+            .finish()?;
+
+        self._update_existing_user(
+            user_id,
+            model::user::Column::Password,
+            Expr::value(bcrypt::hash(password).unwrap()),
+        )
+        .await
+    }
 
-        Some(user.into())
+    async fn _update_existing_user(
+        &self,
+        user_id: &str,
+        column: model::user::Column,
+        value: SimpleExpr,
+    ) -> ServiceResult<()> {
+        let res = model::user::Entity::update_many()
+            .col_expr(column, value)
+            .filter(model::user::Column::Id.eq(user_id))
+            .filter(model::user::Column::IsDeleted.eq(false))
+            .exec(self.db.as_ref())
+            .await?;
+
+        match res.rows_affected {
+            0 => Err(ServiceError::NotFound("user_not_found")),
+            _ => Ok(()),
+        }
     }
 }
@@ -0,0 +1,754 @@
+use crate::model;
+use hmac::{Hmac, Mac};
+use hyper::body::Body;
+use hyper::header::{CONTENT_TYPE, HOST, USER_AGENT};
+use hyper::{Request, Uri};
+use sea_orm::sea_query::Expr;
+use sea_orm::*;
+use serde::Serialize;
+use sha2::Sha256;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_util::compat::{FuturesAsyncReadCompatExt, TokioAsyncReadCompatExt};
+use tracing::{debug, warn};
+use utoipa::ToSchema;
+
+use super::configuration::{ConfigurationService, IpNetList, WebhookConfig};
+use super::error::{invalid_field, ServiceError, ServiceResult, Validator};
+use super::group::{timestamp_or_now, GroupEvent, MAX_TEXT_LENGTH};
+
+pub const MAX_WEBHOOKS_PER_GROUP: usize = 10;
+/// Older deliveries are removed from the log
+const KEPT_DELIVERIES_PER_WEBHOOK: u64 = 50;
+
+pub const SIGNATURE_HEADER: &str = "X-MoneyBalancer-Signature";
+pub const EVENT_HEADER: &str = "X-MoneyBalancer-Event";
+pub const DELIVERY_HEADER: &str = "X-MoneyBalancer-Delivery";
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Webhook {
+    pub id: String,
+    #[schema(example = "https://chat.example.com/hooks/money")]
+    pub url: String,
+    /// the subscribed event types
+    #[schema(example = json!(["transaction.created", "transaction.deleted"]))]
+    pub events: Vec<String>,
+    pub created_at: i64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// not sent yet or waiting for a retry
+    Pending,
+    Delivered,
+    /// all attempts failed
+    Failed,
+}
+
+impl DeliveryStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+
+    fn from_str(status: &str) -> DeliveryStatus {
+        match status {
+            "delivered" => DeliveryStatus::Delivered,
+            "failed" => DeliveryStatus::Failed,
+            _ => DeliveryStatus::Pending,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct WebhookDelivery {
+    /// also sent in the `X-MoneyBalancer-Delivery` header
+    pub id: String,
+    #[schema(example = "transaction.created")]
+    pub event: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// status code of the last response
+    pub response_status: Option<u16>,
+    /// why the last attempt failed
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl From<model::group_webhook_delivery::Model> for WebhookDelivery {
+    fn from(delivery: model::group_webhook_delivery::Model) -> Self {
+        WebhookDelivery {
+            id: delivery.id,
+            event: delivery.event,
+            status: DeliveryStatus::from_str(&delivery.status),
+            attempts: delivery.attempts as u32,
+            response_status: delivery.response_status.map(|status| status as u16),
+            error: delivery.error,
+            created_at: delivery.created_at,
+            updated_at: delivery.updated_at,
+        }
+    }
+}
+
+/// The body of a delivery, the event is flattened into `event` and `data`
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    id: &'a str,
+    group_id: &'a str,
+    timestamp: i64,
+    #[serde(flatten)]
+    event: &'a GroupEvent,
+}
+
+#[derive(Debug, Clone)]
+struct DeliverySettings {
+    max_attempts: u32,
+    retry_delay: Duration,
+    timeout: Duration,
+    allowed_networks: Option<IpNetList>,
+}
+
+impl From<&WebhookConfig> for DeliverySettings {
+    fn from(config: &WebhookConfig) -> Self {
+        DeliverySettings {
+            max_attempts: config.max_attempts,
+            retry_delay: Duration::from_secs(config.retry_delay_seconds),
+            timeout: Duration::from_secs(config.timeout_seconds),
+            allowed_networks: config.allowed_networks.clone(),
+        }
+    }
+}
+
+/// Sends group events to the webhooks of the group. Deliveries are stored before they are
+/// sent by a worker thread, so the requests of the users never wait for the receivers.
+#[derive(Debug)]
+pub struct WebhookService {
+    db: Arc<DatabaseConnection>,
+    /// Ids of deliveries to send, only set if webhooks are enabled
+    queue: Option<mpsc::UnboundedSender<String>>,
+    allowed_networks: Option<IpNetList>,
+}
+
+impl WebhookService {
+    pub fn new(
+        db: Arc<DatabaseConnection>,
+        configuration_service: Arc<ConfigurationService>,
+    ) -> WebhookService {
+        let queue = configuration_service.webhooks().map(|config| {
+            DeliveryWorker {
+                db: db.clone(),
+                settings: config.into(),
+            }
+            .start()
+        });
+
+        let allowed_networks = configuration_service
+            .webhooks()
+            .and_then(|config| config.allowed_networks.clone());
+
+        WebhookService {
+            db,
+            queue,
+            allowed_networks,
+        }
+    }
+
+    pub async fn get_webhooks_of_group(&self, group_id: &str) -> ServiceResult<Vec<Webhook>> {
+        Ok(model::group_webhook::Entity::find()
+            .filter(model::group_webhook::Column::GroupId.eq(group_id))
+            .order_by_asc(model::group_webhook::Column::CreatedAt)
+            .all(self.db.as_ref())
+            .await?
+            .into_iter()
+            .map(Self::_to_webhook)
+            .collect())
+    }
+
+    /// The secret is only used to sign the deliveries and never returned.
+    /// The url has to resolve to public addresses, unless their network is allowed.
+    pub async fn create_webhook(
+        &self,
+        group_id: &str,
+        url: String,
+        events: Vec<String>,
+        secret: String,
+    ) -> ServiceResult<Webhook> {
+        let url = url.trim().to_owned();
+        let uri = url.parse::<Uri>().ok().filter(|uri| {
+            matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some()
+        });
+
+        Validator::new()
+            .check(
+                uri.is_some(),
+                "url",
+                "invalid",
+                "The url must be an absolute http or https url",
+            )
+            .check(
+                url.chars().count() <= MAX_TEXT_LENGTH,
+                "url",
+                "too_long",
+                &format!("The url must not exceed {} characters", MAX_TEXT_LENGTH),
+            )
+            .check(
+                !events.is_empty(),
+                "events",
+                "empty",
+                "At least one event type has to be subscribed",
+            )
+            .check(
+                events
+                    .iter()
+                    .all(|event| GroupEvent::NAMES.contains(&event.as_str())),
+                "events",
+                "unknown",
+                &format!(
+                    "The event types have to be one of {}",
+                    GroupEvent::NAMES.join(", ")
+                ),
+            )
+            .check(
+                !secret.is_empty(),
+                "secret",
+                "empty",
+                "The secret must not be empty",
+            )
+            .check(
+                secret.chars().count() <= MAX_TEXT_LENGTH,
+                "secret",
+                "too_long",
+                &format!("The secret must not exceed {} characters", MAX_TEXT_LENGTH),
+            )
+            .finish()?;
+
+        if let Err(e) = resolve_target(&uri.unwrap(), self.allowed_networks.as_ref()).await {
+            return Err(invalid_field(
+                "url",
+                "not_allowed",
+                &format!("The url must point to a public address: {}", e),
+            ));
+        }
+
+        let count = model::group_webhook::Entity::find()
+            .filter(model::group_webhook::Column::GroupId.eq(group_id))
+            .count(self.db.as_ref())
+            .await?;
+        if count >= MAX_WEBHOOKS_PER_GROUP {
+            return Err(ServiceError::Conflict("too_many_webhooks"));
+        }
+
+        let mut events = events;
+        events.sort();
+        events.dedup();
+
+        let webhook = model::group_webhook::ActiveModel {
+            id: ActiveValue::Set(uuid::Uuid::new_v4().to_string()),
+            group_id: ActiveValue::Set(group_id.to_owned()),
+            url: ActiveValue::Set(url),
+            events: ActiveValue::Set(events.join(",")),
+            secret: ActiveValue::Set(secret),
+            created_at: ActiveValue::Set(timestamp_or_now(None)),
+        }
+        .insert(self.db.as_ref())
+        .await?;
+
+        Ok(Self::_to_webhook(webhook))
+    }
+
+    pub async fn delete_webhook(&self, group_id: &str, webhook_id: &str) -> ServiceResult<()> {
+        let res = model::group_webhook::Entity::delete_many()
+            .filter(model::group_webhook::Column::Id.eq(webhook_id))
+            .filter(model::group_webhook::Column::GroupId.eq(group_id))
+            .exec(self.db.as_ref())
+            .await?;
+
+        match res.rows_affected {
+            0 => Err(ServiceError::NotFound("webhook_not_found")),
+            _ => Ok(()),
+        }
+    }
+
+    /// The latest deliveries first
+    pub async fn get_deliveries_of_webhook(
+        &self,
+        group_id: &str,
+        webhook_id: &str,
+    ) -> ServiceResult<Vec<WebhookDelivery>> {
+        model::group_webhook::Entity::find()
+            .filter(model::group_webhook::Column::Id.eq(webhook_id))
+            .filter(model::group_webhook::Column::GroupId.eq(group_id))
+            .one(self.db.as_ref())
+            .await?
+            .ok_or(ServiceError::NotFound("webhook_not_found"))?;
+
+        Ok(model::group_webhook_delivery::Entity::find()
+            .filter(model::group_webhook_delivery::Column::WebhookId.eq(webhook_id))
+            .order_by_desc(model::group_webhook_delivery::Column::CreatedAt)
+            .order_by_desc(model::group_webhook_delivery::Column::Id)
+            .limit(KEPT_DELIVERIES_PER_WEBHOOK)
+            .all(self.db.as_ref())
+            .await?
+            .into_iter()
+            .map(WebhookDelivery::from)
+            .collect())
+    }
+
+    /// Queues the event for all webhooks of the group which subscribed to it.
+    /// Failures are only logged, the change itself has already been made.
+    pub async fn dispatch(&self, group_id: &str, event: &GroupEvent) {
+        let queue = match &self.queue {
+            Some(queue) => queue,
+            None => return,
+        };
+
+        match self._create_deliveries(group_id, event).await {
+            Ok(delivery_ids) => {
+                for delivery_id in delivery_ids {
+                    let _ = queue.send(delivery_id);
+                }
+            }
+            Err(e) => {
+                warn!(group_id, event = event.name(), error = %e, "creating the webhook deliveries failed")
+            }
+        }
+    }
+
+    /// Queues the deliveries which were not finished before the last shutdown
+    pub async fn resume_pending_deliveries(&self) -> ServiceResult<()> {
+        let queue = match &self.queue {
+            Some(queue) => queue,
+            None => return Ok(()),
+        };
+
+        let pending = model::group_webhook_delivery::Entity::find()
+            .filter(
+                model::group_webhook_delivery::Column::Status.eq(DeliveryStatus::Pending.as_str()),
+            )
+            .order_by_asc(model::group_webhook_delivery::Column::CreatedAt)
+            .order_by_asc(model::group_webhook_delivery::Column::Id)
+            .all(self.db.as_ref())
+            .await?;
+
+        for delivery in pending {
+            let _ = queue.send(delivery.id);
+        }
+
+        Ok(())
+    }
+
+    async fn _create_deliveries(
+        &self,
+        group_id: &str,
+        event: &GroupEvent,
+    ) -> ServiceResult<Vec<String>> {
+        let webhooks = model::group_webhook::Entity::find()
+            .filter(model::group_webhook::Column::GroupId.eq(group_id))
+            .all(self.db.as_ref())
+            .await?
+            .into_iter()
+            .filter(|webhook| webhook.events.split(',').any(|name| name == event.name()))
+            .collect::<Vec<model::group_webhook::Model>>();
+
+        if webhooks.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let timestamp = timestamp_or_now(None);
+        let mut deliveries = vec![];
+        for webhook in webhooks {
+            // time-ordered, so deliveries of the same second keep their order
+            let delivery_id = uuid::Uuid::now_v7().to_string();
+            let payload = serde_json::to_string(&WebhookPayload {
+                id: &delivery_id,
+                group_id,
+                timestamp,
+                event,
+            })
+            .expect("error serializing the webhook payload");
+
+            deliveries.push(model::group_webhook_delivery::ActiveModel {
+                id: ActiveValue::Set(delivery_id),
+                webhook_id: ActiveValue::Set(webhook.id),
+                event: ActiveValue::Set(event.name().to_owned()),
+                payload: ActiveValue::Set(payload),
+                status: ActiveValue::Set(DeliveryStatus::Pending.as_str().to_owned()),
+                attempts: ActiveValue::Set(0),
+                response_status: ActiveValue::Set(None),
+                error: ActiveValue::Set(None),
+                created_at: ActiveValue::Set(timestamp),
+                updated_at: ActiveValue::Set(timestamp),
+            });
+        }
+
+        let delivery_ids = deliveries
+            .iter()
+            .map(|delivery| delivery.id.clone().unwrap())
+            .collect();
+
+        model::group_webhook_delivery::Entity::insert_many(deliveries)
+            .exec(self.db.as_ref())
+            .await?;
+
+        Ok(delivery_ids)
+    }
+
+    fn _to_webhook(webhook: model::group_webhook::Model) -> Webhook {
+        Webhook {
+            id: webhook.id,
+            url: webhook.url,
+            events: webhook.events.split(',').map(str::to_owned).collect(),
+            created_at: webhook.created_at,
+        }
+    }
+}
+
+/// Loopback, private, link-local and other internal addresses are only allowed
+/// if their network is configured explicitly
+fn is_allowed_address(ip: IpAddr, allowed_networks: Option<&IpNetList>) -> bool {
+    if allowed_networks.is_some_and(|networks| networks.contains(&ip)) {
+        return true;
+    }
+
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            // 100.64.0.0/10 is used for carrier-grade NAT
+            let is_shared = first == 100 && (64..128).contains(&second);
+
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || is_shared)
+        }
+        IpAddr::V6(ip) => {
+            !(ip.is_loopback()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local()
+                || ip.is_unspecified()
+                || ip.is_multicast())
+        }
+    }
+}
+
+/// Resolves the host of the url. Fails if any of its addresses is not allowed, so the
+/// deliveries connect to the checked addresses instead of resolving the host again.
+async fn resolve_target(
+    uri: &Uri,
+    allowed_networks: Option<&IpNetList>,
+) -> Result<Vec<SocketAddr>, String> {
+    let host = uri.host().ok_or("the url has no host")?;
+    // ipv6 literals keep their brackets in the url
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = uri
+        .port_u16()
+        .unwrap_or(if uri.scheme_str() == Some("https") {
+            443
+        } else {
+            80
+        });
+
+    let addresses = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| e.to_string())?
+        .collect::<Vec<SocketAddr>>();
+
+    if addresses.is_empty() {
+        return Err(format!("{} has no addresses", host));
+    }
+    if let Some(address) = addresses
+        .iter()
+        .find(|address| !is_allowed_address(address.ip(), allowed_networks))
+    {
+        return Err(format!("the address {} is not allowed", address.ip()));
+    }
+
+    Ok(addresses)
+}
+
+/// Hex encoded HMAC-SHA256 of the body, sent as `sha256=<signature>`
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any size");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Sends the queued deliveries on its own thread, so retries keep running independently
+/// of the runtime handling the requests
+struct DeliveryWorker {
+    db: Arc<DatabaseConnection>,
+    settings: DeliverySettings,
+}
+
+impl DeliveryWorker {
+    /// The worker stops once the returned sender is dropped
+    fn start(self) -> mpsc::UnboundedSender<String> {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+
+        thread::Builder::new()
+            .name("webhooks".to_owned())
+            .spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .expect("error building the webhook runtime");
+                let worker = Arc::new(self);
+
+                runtime.block_on(async move {
+                    while let Some(delivery_id) = receiver.recv().await {
+                        let worker = worker.clone();
+                        tokio::spawn(async move { worker.deliver(delivery_id).await });
+                    }
+                });
+            })
+            .expect("error starting the webhook worker");
+
+        sender
+    }
+
+    async fn deliver(&self, delivery_id: String) {
+        if let Err(e) = self._deliver(&delivery_id).await {
+            warn!(delivery_id, error = %e, "delivering the webhook failed");
+        }
+    }
+
+    /// Attempts the delivery until it succeeds or the attempts are exhausted,
+    /// waiting twice as long before every retry
+    async fn _deliver(&self, delivery_id: &str) -> ServiceResult<()> {
+        let (delivery, webhook) =
+            match model::group_webhook_delivery::Entity::find_by_id(delivery_id.to_owned())
+                .find_also_related(model::group_webhook::Entity)
+                .one(self.db.as_ref())
+                .await?
+            {
+                Some((delivery, Some(webhook))) => (delivery, webhook),
+                // the webhook has been deleted in the meantime
+                _ => return Ok(()),
+            };
+
+        let mut attempts = delivery.attempts as u32;
+        let mut status = DeliveryStatus::from_str(&delivery.status);
+
+        while status == DeliveryStatus::Pending && attempts < self.settings.max_attempts {
+            if attempts > 0 {
+                tokio::time::sleep(self.settings.retry_delay * 2u32.saturating_pow(attempts - 1))
+                    .await;
+            }
+
+            let result = self._send(&webhook, &delivery).await;
+            attempts += 1;
+
+            let (response_status, error) = match result {
+                Ok(code) if (200..300).contains(&code) => {
+                    status = DeliveryStatus::Delivered;
+                    (Some(code), None)
+                }
+                Ok(code) => (Some(code), Some(format!("unexpected status {}", code))),
+                Err(e) => (None, Some(e)),
+            };
+            if status == DeliveryStatus::Pending && attempts >= self.settings.max_attempts {
+                status = DeliveryStatus::Failed;
+            }
+
+            debug!(
+                delivery_id,
+                attempts,
+                status = status.as_str(),
+                error = error.as_deref(),
+                "webhook delivery attempted"
+            );
+
+            let res = model::group_webhook_delivery::Entity::update_many()
+                .col_expr(
+                    model::group_webhook_delivery::Column::Status,
+                    Expr::value(status.as_str()),
+                )
+                .col_expr(
+                    model::group_webhook_delivery::Column::Attempts,
+                    Expr::value(attempts as i32),
+                )
+                .col_expr(
+                    model::group_webhook_delivery::Column::ResponseStatus,
+                    Expr::value(response_status.map(i32::from)),
+                )
+                .col_expr(
+                    model::group_webhook_delivery::Column::Error,
+                    Expr::value(error),
+                )
+                .col_expr(
+                    model::group_webhook_delivery::Column::UpdatedAt,
+                    Expr::value(timestamp_or_now(None)),
+                )
+                .filter(model::group_webhook_delivery::Column::Id.eq(delivery_id))
+                .exec(self.db.as_ref())
+                .await?;
+
+            // the webhook has been deleted in the meantime
+            if res.rows_affected == 0 {
+                return Ok(());
+            }
+        }
+
+        self._prune_deliveries(&webhook.id).await
+    }
+
+    async fn _prune_deliveries(&self, webhook_id: &str) -> ServiceResult<()> {
+        let stale_ids = model::group_webhook_delivery::Entity::find()
+            .filter(model::group_webhook_delivery::Column::WebhookId.eq(webhook_id))
+            .filter(
+                model::group_webhook_delivery::Column::Status.ne(DeliveryStatus::Pending.as_str()),
+            )
+            .order_by_desc(model::group_webhook_delivery::Column::CreatedAt)
+            .order_by_desc(model::group_webhook_delivery::Column::Id)
+            .offset(KEPT_DELIVERIES_PER_WEBHOOK)
+            .all(self.db.as_ref())
+            .await?
+            .into_iter()
+            .map(|delivery| delivery.id)
+            .collect::<Vec<String>>();
+
+        if !stale_ids.is_empty() {
+            model::group_webhook_delivery::Entity::delete_many()
+                .filter(model::group_webhook_delivery::Column::Id.is_in(stale_ids))
+                .exec(self.db.as_ref())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the status code of the response or why no response was received
+    async fn _send(
+        &self,
+        webhook: &model::group_webhook::Model,
+        delivery: &model::group_webhook_delivery::Model,
+    ) -> Result<u16, String> {
+        let uri = webhook.url.parse::<Uri>().map_err(|e| e.to_string())?;
+        let host = uri.host().ok_or("the url has no host")?;
+        let tls = uri.scheme_str() == Some("https");
+
+        let request = Request::post(uri.path_and_query().map_or("/", |p| p.as_str()))
+            .header(HOST, uri.authority().map_or(host, |a| a.as_str()))
+            .header(CONTENT_TYPE, "application/json")
+            .header(
+                USER_AGENT,
+                concat!("money-balancer/", env!("CARGO_PKG_VERSION")),
+            )
+            .header(EVENT_HEADER, &delivery.event)
+            .header(DELIVERY_HEADER, &delivery.id)
+            .header(
+                SIGNATURE_HEADER,
+                format!("sha256={}", sign(&webhook.secret, &delivery.payload)),
+            )
+            .body(Body::from(delivery.payload.clone()))
+            .map_err(|e| e.to_string())?;
+
+        let send = async {
+            // checked again, the addresses of the host might have changed since its creation
+            let addresses = resolve_target(&uri, self.settings.allowed_networks.as_ref()).await?;
+            let stream = TcpStream::connect(addresses.as_slice())
+                .await
+                .map_err(|e| e.to_string())?;
+
+            match tls {
+                true => {
+                    let host = host.trim_start_matches('[').trim_end_matches(']');
+                    let stream = async_native_tls::connect(host, stream.compat())
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    Self::_send_request(stream.compat(), request).await
+                }
+                false => Self::_send_request(stream, request).await,
+            }
+        };
+
+        tokio::time::timeout(self.settings.timeout, send)
+            .await
+            .map_err(|_| "timed out".to_owned())?
+    }
+
+    async fn _send_request<T>(io: T, request: Request<Body>) -> Result<u16, String>
+    where
+        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (mut sender, connection) = hyper::client::conn::handshake(io)
+            .await
+            .map_err(|e| e.to_string())?;
+        tokio::spawn(connection);
+
+        let response = sender
+            .send_request(request)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(response.status().as_u16())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_allowed_address, resolve_target};
+    use hyper::Uri;
+
+    #[test]
+    fn test_allowed_addresses() {
+        for ip in ["93.184.215.14", "2606:2800:21f:cb07:6820:80da:af6b:8b2c"] {
+            assert!(is_allowed_address(ip.parse().unwrap(), None), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_allowed_address(ip.parse().unwrap(), None), "{}", ip);
+        }
+
+        let allowed = "10.0.0.0/8,::1".parse().unwrap();
+        assert!(is_allowed_address(
+            "10.1.2.3".parse().unwrap(),
+            Some(&allowed)
+        ));
+        assert!(!is_allowed_address(
+            "192.168.1.1".parse().unwrap(),
+            Some(&allowed)
+        ));
+    }
+
+    #[test]
+    fn test_resolve_ipv6_literal() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let uri = "https://[::1]/hook".parse::<Uri>().unwrap();
+
+        let addresses = runtime
+            .block_on(resolve_target(&uri, Some(&"::1".parse().unwrap())))
+            .unwrap();
+        assert_eq!(addresses, vec!["[::1]:443".parse().unwrap()]);
+        assert!(runtime.block_on(resolve_target(&uri, None)).is_err());
+    }
+}